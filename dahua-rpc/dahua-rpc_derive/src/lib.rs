@@ -15,7 +15,28 @@ fn impl_config_table(ast: &syn::DeriveInput) -> TokenStream {
     let set_expr = if should_add_set(ast) {
         quote!(
             pub async fn set(self, rpc: crate::RequestBuilder) -> Result<(), crate::Error> {
-                crate::modules::configmanager::SetConfigRequest {name: #name_string, channel: 0, table: self}.set(rpc).await
+                crate::modules::configmanager::assert_config_table_value::<Self>();
+                crate::modules::configmanager::SetConfigRequest {name: #name_string, channel: None, table: self}.set(rpc).await
+            }
+        )
+    } else {
+        quote!()
+    };
+
+    // Array tables have one entry per channel which can also be accessed individually
+    let channel_expr = if let Some(item) = array_table_item(ast) {
+        quote!(
+            pub async fn get_channel(rpc: crate::RequestBuilder, channel: i32) -> Result<#item, crate::Error> {
+                crate::modules::configmanager::GetConfigRequest { name: #name_string, channel: Some(channel)}.get::<#item>(rpc).await
+            }
+
+            pub async fn get_default_channel(rpc: crate::RequestBuilder, channel: i32) -> Result<#item, crate::Error> {
+                crate::modules::configmanager::GetConfigRequest { name: #name_string, channel: Some(channel)}.get_default::<#item>(rpc).await
+            }
+
+            pub async fn set_channel(rpc: crate::RequestBuilder, channel: i32, table: #item) -> Result<(), crate::Error> {
+                crate::modules::configmanager::assert_config_table_value::<#item>();
+                crate::modules::configmanager::SetConfigRequest {name: #name_string, channel: Some(channel), table}.set(rpc).await
             }
         )
     } else {
@@ -25,14 +46,16 @@ fn impl_config_table(ast: &syn::DeriveInput) -> TokenStream {
     let gen = quote! {
         impl #name {
             pub async fn get(rpc: crate::RequestBuilder) -> Result<Self, crate::Error> {
-                crate::modules::configmanager::GetConfigRequest { name: #name_string, channel: 0}.get::<Self>(rpc).await
+                crate::modules::configmanager::GetConfigRequest { name: #name_string, channel: None}.get::<Self>(rpc).await
             }
 
             pub async fn get_default(rpc: crate::RequestBuilder) -> Result<Self, crate::Error> {
-                crate::modules::configmanager::GetConfigRequest { name: #name_string, channel: 0}.get_default::<Self>(rpc).await
+                crate::modules::configmanager::GetConfigRequest { name: #name_string, channel: None}.get_default::<Self>(rpc).await
             }
 
             #set_expr
+
+            #channel_expr
         }
     };
 
@@ -43,14 +66,12 @@ fn should_add_set(ast: &syn::DeriveInput) -> bool {
     if let syn::Data::Struct(data) = &ast.data {
         match &data.fields {
             Fields::Named(fields) => {
+                // Nested structs are checked by the ConfigTableValue bound on set
                 for ele in fields.named.iter() {
-                    // TODO: recursive check structs to make sure they have serde(flatten)
                     for ele in ele.attrs.iter() {
                         if let Some(ident) = ele.path.get_ident() {
-                            if ident == "serde" {
-                                if ele.tokens.to_string() == "(flatten)" {
-                                    return true;
-                                }
+                            if ident == "serde" && ele.tokens.to_string() == "(flatten)" {
+                                return true;
                             }
                         }
                     }
@@ -62,13 +83,46 @@ fn should_add_set(ast: &syn::DeriveInput) -> bool {
         }
     }
 
-    return false;
+    false
+}
+
+/// Returns T for tables in the form of `struct Table(Vec<T>)`.
+fn array_table_item(ast: &syn::DeriveInput) -> Option<&syn::Type> {
+    let syn::Data::Struct(data) = &ast.data else {
+        return None;
+    };
+    let Fields::Unnamed(fields) = &data.fields else {
+        return None;
+    };
+    if fields.unnamed.len() != 1 {
+        return None;
+    }
+    let syn::Type::Path(path) = &fields.unnamed[0].ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Vec" {
+        return None;
+    }
+    let syn::PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    match args.args.first()? {
+        syn::GenericArgument::Type(ty) => Some(ty),
+        _ => None,
+    }
 }
 
 #[proc_macro_attribute]
 pub fn config_table(_: TokenStream, input: TokenStream) -> TokenStream {
     let mut ast: DeriveInput = parse_macro_input!(input as DeriveInput);
 
+    // Every field has to keep unknown fields too, otherwise set would drop them
+    let field_types: Vec<syn::Type> = match &ast.data {
+        syn::Data::Struct(data) => data.fields.iter().map(|f| f.ty.clone()).collect(),
+        _ => vec![],
+    };
+
     if let syn::Data::Struct(ref mut data) = ast.data {
         match data.fields {
             Fields::Named(ref mut fields) => {
@@ -83,8 +137,15 @@ pub fn config_table(_: TokenStream, input: TokenStream) -> TokenStream {
         }
     }
 
+    let name = &ast.ident;
     let gen = quote! {
         #ast
+
+        impl crate::modules::configmanager::ConfigTableValue for #name {}
+
+        const _: fn() = || {
+            #(crate::modules::configmanager::assert_config_table_value::<#field_types>();)*
+        };
     };
 
     TokenStream::from(gen)
@@ -0,0 +1,962 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    net::SocketAddr,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+pub const CODE_LOGIN_CHALLENGE: i32 = 268632079;
+pub const CODE_USER_OR_PASSWORD_NOT_VALID: i32 = 268632085;
+pub const CODE_HAS_BEEN_LOCKED: i32 = 268632081;
+pub const CODE_INVALID_SESSION: i32 = 287637505;
+pub const CODE_INVALID_REQUEST: i32 = 268894209;
+pub const CODE_METHOD_NOT_FOUND: i32 = 268894210;
+pub const CODE_NO_DATA: i32 = 285409284;
+
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+const DEFAULT_SESSION_TIMEOUT: Duration = Duration::from_secs(60 * 5);
+
+fn to_timestamp(date_time: &DateTime<Utc>) -> String {
+    date_time
+        .with_timezone(&Local)
+        .format(TIMESTAMP_FORMAT)
+        .to_string()
+}
+
+fn from_timestamp(timestamp: &str) -> Option<DateTime<Utc>> {
+    let naive = NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT).ok()?;
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|t| t.with_timezone(&Utc))
+}
+
+/// Request body of /RPC2 and /RPC2_Login.
+#[derive(Deserialize, Debug)]
+pub struct RpcRequest {
+    #[serde(default)]
+    pub id: i64,
+    #[serde(default)]
+    pub session: Value,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+    pub object: Option<i64>,
+}
+
+impl RpcRequest {
+    fn session(&self) -> String {
+        match &self.session {
+            Value::String(session) => session.clone(),
+            Value::Number(session) => session.to_string(),
+            _ => "".to_string(),
+        }
+    }
+}
+
+/// File that is returned by mediaFileFind and served by /RPC_Loadfile.
+#[derive(Clone, Debug)]
+pub struct MockFile {
+    pub channel: i32,
+    pub kind: String,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub file_path: String,
+    pub events: Vec<String>,
+    pub data: Vec<u8>,
+}
+
+impl MockFile {
+    /// Creates a file with a file path in the same form as the ones on a SD card.
+    pub fn new(
+        channel: i32,
+        kind: &str,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+    ) -> MockFile {
+        let start = start_time.with_timezone(&Local);
+        let end = end_time.with_timezone(&Local);
+        let file_path = format!(
+            "/mnt/sd/{}/{:03}/{}/{}/{}-{}[R][0@0][0].{}",
+            start.format("%Y-%m-%d"),
+            channel + 1,
+            kind,
+            start.format("%H"),
+            start.format("%H.%M.%S"),
+            end.format("%H.%M.%S"),
+            kind
+        );
+
+        MockFile {
+            channel,
+            kind: kind.to_string(),
+            start_time,
+            end_time,
+            file_path,
+            events: vec![],
+            data: vec![],
+        }
+    }
+
+    pub fn events(mut self, events: Vec<String>) -> MockFile {
+        self.events = events;
+        self
+    }
+
+    pub fn data(mut self, data: Vec<u8>) -> MockFile {
+        self.data = data;
+        self
+    }
+
+    fn info(&self) -> Value {
+        json!({
+            "Channel": self.channel,
+            "Cluster": 0,
+            "Disk": 0,
+            "Duration": (self.end_time - self.start_time).num_seconds(),
+            "EndTime": to_timestamp(&self.end_time),
+            "Events": self.events,
+            "FilePath": self.file_path,
+            "Flags": ["Timing"],
+            "Length": self.data.len(),
+            "Partition": 0,
+            "StartTime": to_timestamp(&self.start_time),
+            "Type": self.kind,
+            "VideoStream": "Main",
+        })
+    }
+}
+
+struct MockUser {
+    name: String,
+    password: String,
+    group: String,
+    memo: String,
+}
+
+impl MockUser {
+    fn info(&self, id: usize) -> Value {
+        json!({
+            "AuthorityList": [],
+            "Group": self.group,
+            "Id": id + 1,
+            "Memo": self.memo,
+            "Name": self.name,
+            "Password": "******",
+            "Reserved": id == 0,
+            "Sharable": true,
+        })
+    }
+}
+
+struct Session {
+    random: String,
+    active: bool,
+    last_active: Instant,
+}
+
+struct Finder {
+    files: Vec<MockFile>,
+    cursor: usize,
+}
+
+struct DeviceState {
+    username: String,
+    password: String,
+    /// Users other than the one used for logins.
+    users: Vec<MockUser>,
+    realm: String,
+    locked: bool,
+    session_timeout: Duration,
+    /// How far the clock of the device is ahead.
+    clock: Duration,
+    last_id: i64,
+    sessions: HashMap<String, Session>,
+    finders: HashMap<i64, Finder>,
+    files: Vec<MockFile>,
+    responses: HashMap<String, Value>,
+    configs: HashMap<String, Value>,
+    defaults: HashMap<String, Value>,
+    errors: HashMap<String, VecDeque<(i32, String)>>,
+    resets: usize,
+    /// Methods whose next call is handled but never answered.
+    lost: Vec<String>,
+    calls: Vec<String>,
+    unsupported: HashSet<String>,
+}
+
+impl DeviceState {
+    /// Sorted methods the device answers.
+    fn methods(&self) -> Vec<&str> {
+        let mut methods: Vec<&str> = BUILTIN_METHODS
+            .iter()
+            .copied()
+            .chain(self.responses.keys().map(String::as_str))
+            .filter(|method| !self.unsupported.contains(*method))
+            .collect();
+        methods.sort_unstable();
+        methods.dedup();
+        methods
+    }
+
+    fn now(&self) -> Instant {
+        Instant::now() + self.clock
+    }
+
+    fn next_id(&mut self) -> i64 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    fn session_valid(&mut self, session: &str) -> bool {
+        let (now, timeout) = (self.now(), self.session_timeout);
+        match self.sessions.get(session) {
+            Some(s) if s.active && now - s.last_active < timeout => true,
+            Some(_) => {
+                self.sessions.remove(session);
+                false
+            }
+            None => false,
+        }
+    }
+
+    fn auth(&self, random: &str) -> String {
+        let password = format!(
+            "{:x}",
+            md5::compute(format!(
+                "{}:{}:{}",
+                self.username, self.realm, self.password
+            ))
+        )
+        .to_uppercase();
+
+        format!(
+            "{:x}",
+            md5::compute(format!("{}:{}:{}", self.username, random, password))
+        )
+        .to_uppercase()
+    }
+}
+
+/// Methods with built-in behaviour, the rest come from Device::respond.
+const BUILTIN_METHODS: &[&str] = &[
+    "system.multicall",
+    "system.listMethod",
+    "system.listService",
+    "global.login",
+    "global.logout",
+    "global.keepAlive",
+    "global.getCurrentTime",
+    "storage.factory.instance",
+    "configManager.getConfig",
+    "configManager.getDefault",
+    "configManager.setConfig",
+    "mediaFileFind.factory.create",
+    "mediaFileFind.findFile",
+    "mediaFileFind.findNextFile",
+    "mediaFileFind.getCount",
+    "mediaFileFind.close",
+    "mediaFileFind.destroy",
+    "userManager.getUserInfoAll",
+    "userManager.getGroupInfoAll",
+    "userManager.getActiveUserInfoAll",
+    "userManager.addUser",
+    "userManager.deleteUser",
+    "userManager.modifyPassword",
+];
+
+/// Scriptable fake device, clones share the same state.
+#[derive(Clone)]
+pub struct Device {
+    state: Arc<Mutex<DeviceState>>,
+}
+
+impl Device {
+    pub fn new(username: &str, password: &str) -> Device {
+        let mut responses = HashMap::new();
+        responses.insert(
+            "magicBox.getSerialNo".to_string(),
+            json!({ "sn": "MOCK0000000000" }),
+        );
+        responses.insert(
+            "magicBox.getDeviceType".to_string(),
+            json!({ "type": "IPC-HDW-MOCK" }),
+        );
+        responses.insert(
+            "magicBox.getDeviceClass".to_string(),
+            json!({ "type": "IPC" }),
+        );
+        responses.insert(
+            "magicBox.getHardwareVersion".to_string(),
+            json!({ "version": "1.00" }),
+        );
+        responses.insert(
+            "magicBox.getVendor".to_string(),
+            json!({ "Vendor": "Dahua" }),
+        );
+        responses.insert(
+            "magicBox.getMarketArea".to_string(),
+            json!({ "AbroadInfo": "Oversea" }),
+        );
+        responses.insert(
+            "magicBox.getSoftwareVersion".to_string(),
+            json!({ "version": {
+                "Build": "",
+                "BuildDate": "2023-01-01",
+                "SecurityBaseLineVersion": "V2.2",
+                "Version": "2.800.0000000.0.R",
+                "WebVersion": "V3.2.1.0"
+            }}),
+        );
+        responses.insert(
+            "magicBox.getMemoryInfo".to_string(),
+            json!({ "free": 100000000, "total": 500000000 }),
+        );
+        responses.insert("magicBox.getCPUUsage".to_string(), json!({ "usage": 10 }));
+        responses.insert("magicBox.getProcessInfo".to_string(), json!({ "info": "" }));
+        responses.insert(
+            "magicBox.needReboot".to_string(),
+            json!({ "needReboot": 0 }),
+        );
+        responses.insert("magicBox.reboot".to_string(), Value::Null);
+        responses.insert("License.getLicenseInfo".to_string(), json!([]));
+        responses.insert(
+            "storage.getDeviceAllInfo".to_string(),
+            json!({ "info": [{
+                "Name": "/dev/mmc0",
+                "State": "Success",
+                "Detail": [{
+                    "Path": "/mnt/sd",
+                    "Type": "ReadWrite",
+                    "TotalBytes": 64000000000_i64,
+                    "UsedBytes": 32000000000_i64,
+                    "IsError": false
+                }]
+            }]}),
+        );
+
+        let mut configs = HashMap::new();
+        configs.insert("ChannelTitle".to_string(), json!([{ "Name": "Channel 1" }]));
+        configs.insert(
+            "General".to_string(),
+            json!({
+                "LocalNo": 8,
+                "LockLoginEnable": true,
+                "LockLoginTimes": 5,
+                "LoginFailLockTime": 1800,
+                "MachineName": "Mock",
+                "MaxOnlineTime": 1800
+            }),
+        );
+
+        Device {
+            state: Arc::new(Mutex::new(DeviceState {
+                username: username.to_string(),
+                password: password.to_string(),
+                users: vec![],
+                realm: "Login to MOCK0000000000".to_string(),
+                locked: false,
+                session_timeout: DEFAULT_SESSION_TIMEOUT,
+                clock: Duration::ZERO,
+                last_id: 0,
+                sessions: HashMap::new(),
+                finders: HashMap::new(),
+                files: vec![],
+                responses,
+                configs: configs.clone(),
+                defaults: configs,
+                errors: HashMap::new(),
+                resets: 0,
+                lost: vec![],
+                calls: vec![],
+                unsupported: HashSet::new(),
+            })),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, DeviceState> {
+        self.state.lock().unwrap()
+    }
+
+    pub fn add_file(&self, file: MockFile) {
+        self.lock().files.push(file);
+    }
+
+    pub fn remove_file(&self, file_path: &str) {
+        self.lock().files.retain(|f| f.file_path != file_path);
+    }
+
+    /// Sets the params returned by a method, this also adds unknown methods.
+    pub fn respond(&self, method: &str, params: Value) {
+        self.lock().responses.insert(method.to_string(), params);
+    }
+
+    pub fn set_config(&self, name: &str, table: Value) {
+        self.lock().configs.insert(name.to_string(), table);
+    }
+
+    pub fn config(&self, name: &str) -> Option<Value> {
+        self.lock().configs.get(name).cloned()
+    }
+
+    pub fn set_channels(&self, channels: usize) {
+        let titles: Vec<Value> = (0..channels)
+            .map(|i| json!({ "Name": format!("Channel {}", i + 1) }))
+            .collect();
+        self.set_config("ChannelTitle", Value::Array(titles));
+    }
+
+    /// The next call of the method fails with the given error code.
+    pub fn fail(&self, method: &str, code: i32, message: &str) {
+        self.lock()
+            .errors
+            .entry(method.to_string())
+            .or_default()
+            .push_back((code, message.to_string()));
+    }
+
+    /// The next count connections are closed before a response is sent.
+    pub fn reset_connections(&self, count: usize) {
+        self.lock().resets += count;
+    }
+
+    pub(crate) fn take_reset(&self) -> bool {
+        let mut state = self.lock();
+        if state.resets > 0 {
+            state.resets -= 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// The next call of the method is handled but the connection is closed before it is
+    /// answered, like a response that is lost on the way back.
+    pub fn lose_response(&self, method: &str) {
+        self.lock().lost.push(method.to_string());
+    }
+
+    pub(crate) fn take_lost(&self, method: &str) -> bool {
+        let mut state = self.lock();
+        match state.lost.iter().position(|lost| lost == method) {
+            Some(idx) => {
+                state.lost.remove(idx);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn expire_sessions(&self) {
+        self.lock().sessions.clear();
+    }
+
+    pub fn set_session_timeout(&self, timeout: Duration) {
+        self.lock().session_timeout = timeout;
+    }
+
+    /// Moves the clock of the device forward, sessions that are not used in time expire.
+    pub fn advance(&self, duration: Duration) {
+        self.lock().clock += duration;
+    }
+
+    /// Every login fails with HasBeenLocked while locked.
+    pub fn set_locked(&self, locked: bool) {
+        self.lock().locked = locked;
+    }
+
+    pub fn password(&self) -> String {
+        self.lock().password.clone()
+    }
+
+    pub fn set_password(&self, password: &str) {
+        self.lock().password = password.to_string();
+    }
+
+    /// Makes the device answer method with method not found and leave it out of system.listMethod.
+    pub fn unsupport(&self, method: &str) {
+        self.lock().unsupported.insert(method.to_string());
+    }
+
+    /// Methods that have been called in order, including logins.
+    pub fn calls(&self) -> Vec<String> {
+        self.lock().calls.clone()
+    }
+
+    pub fn sessions(&self) -> usize {
+        let mut state = self.lock();
+        let (now, timeout) = (state.now(), state.session_timeout);
+        state
+            .sessions
+            .retain(|_, s| !s.active || now - s.last_active < timeout);
+        state.sessions.values().filter(|s| s.active).count()
+    }
+
+    /// What the device answers to a DHDiscover.search probe, the MAC is derived from the serial.
+    pub fn device_info(&self, http_addr: SocketAddr) -> Value {
+        let state = self.lock();
+        let response = |method: &str, pointer: &str| {
+            state
+                .responses
+                .get(method)
+                .and_then(|v| v.pointer(pointer))
+                .cloned()
+                .unwrap_or(Value::Null)
+        };
+        let sn = response("magicBox.getSerialNo", "/sn");
+        let digest = md5::compute(sn.as_str().unwrap_or_default());
+        let mac = digest[..6]
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<Vec<_>>()
+            .join(":");
+
+        json!({
+            "DeviceClass": response("magicBox.getDeviceClass", "/type"),
+            "DeviceType": response("magicBox.getDeviceType", "/type"),
+            "HttpPort": http_addr.port(),
+            "IPv4Address": {
+                "DhcpEnable": true,
+                "IPAddress": http_addr.ip().to_string(),
+                "SubnetMask": "255.255.255.0"
+            },
+            "Mac": mac,
+            "SerialNo": sn,
+            "Vendor": response("magicBox.getVendor", "/Vendor"),
+            "Version": response("magicBox.getSoftwareVersion", "/version/Version"),
+        })
+    }
+
+    pub(crate) fn file(&self, session: &str, file_path: &str) -> Option<Vec<u8>> {
+        let mut state = self.lock();
+        if !state.session_valid(session) {
+            return None;
+        }
+        state
+            .files
+            .iter()
+            .find(|f| f.file_path == file_path)
+            .map(|f| f.data.clone())
+    }
+
+    pub(crate) fn session_valid(&self, session: &str) -> bool {
+        self.lock().session_valid(session)
+    }
+
+    /// Answers every request in params like it was sent by itself.
+    fn multicall(&self, req: RpcRequest) -> Value {
+        let session = req.session();
+        {
+            let mut state = self.lock();
+            state.calls.push(req.method.clone());
+            if !state.session_valid(&session) {
+                return error(
+                    &req,
+                    &session,
+                    CODE_INVALID_SESSION,
+                    "Invalid session in request data!",
+                );
+            }
+            if let Some(res) = Self::injected_error(&mut state, &req, &session) {
+                return res;
+            }
+            if state.unsupported.contains(&req.method) {
+                return error(&req, &session, CODE_METHOD_NOT_FOUND, "Method not found!");
+            }
+        }
+
+        let calls: Vec<RpcRequest> = match serde_json::from_value(req.params.clone()) {
+            Ok(o) => o,
+            Err(_) => return error(&req, &session, CODE_INVALID_REQUEST, "Invalid request!"),
+        };
+        let responses: Vec<Value> = calls.into_iter().map(|call| self.rpc(call)).collect();
+
+        success(&req, &session, json!(responses), json!(true))
+    }
+
+    pub(crate) fn login(&self, req: RpcRequest) -> Value {
+        let mut state = self.lock();
+        state.calls.push(req.method.clone());
+        let session = req.session();
+
+        if let Some(res) = Self::injected_error(&mut state, &req, &session) {
+            return res;
+        }
+
+        if req.method != "global.login" {
+            return error(&req, &session, CODE_METHOD_NOT_FOUND, "Method not found!");
+        }
+
+        let random = match state.sessions.get(&session) {
+            Some(s) if !s.active => s.random.clone(),
+            // First login
+            _ => {
+                let id = state.next_id();
+                let session = format!("{:032x}", md5::compute(format!("session{id}")));
+                let random = format!("{}", 100000000 + id);
+                let now = state.now();
+                state.sessions.insert(
+                    session.clone(),
+                    Session {
+                        random: random.clone(),
+                        active: false,
+                        last_active: now,
+                    },
+                );
+
+                return json!({
+                    "error": { "code": CODE_LOGIN_CHALLENGE, "message": "Component error: login challenge!" },
+                    "id": req.id,
+                    "params": {
+                        "encryption": "Default",
+                        "random": random,
+                        "realm": state.realm,
+                    },
+                    "result": false,
+                    "session": session,
+                });
+            }
+        };
+
+        if state.locked {
+            return error(&req, &session, CODE_HAS_BEEN_LOCKED, "HasBeenLocked");
+        }
+
+        let password = req.params["password"].as_str().unwrap_or_default();
+        if req.params["userName"].as_str() != Some(&state.username)
+            || password != state.auth(&random)
+        {
+            state.sessions.remove(&session);
+            return error(
+                &req,
+                &session,
+                CODE_USER_OR_PASSWORD_NOT_VALID,
+                "Component error: password not valid!",
+            );
+        }
+
+        let now = state.now();
+        if let Some(s) = state.sessions.get_mut(&session) {
+            s.active = true;
+            s.last_active = now;
+        }
+
+        success(
+            &req,
+            &session,
+            json!({ "keepAliveInterval": state.session_timeout.as_secs() }),
+            json!(true),
+        )
+    }
+
+    pub(crate) fn rpc(&self, req: RpcRequest) -> Value {
+        if req.method == "system.multicall" {
+            return self.multicall(req);
+        }
+
+        let mut state = self.lock();
+        state.calls.push(req.method.clone());
+        let session = req.session();
+
+        if !state.session_valid(&session) {
+            return error(
+                &req,
+                &session,
+                CODE_INVALID_SESSION,
+                "Invalid session in request data!",
+            );
+        }
+        let now = state.now();
+        if let Some(s) = state.sessions.get_mut(&session) {
+            s.last_active = now;
+        }
+
+        if let Some(res) = Self::injected_error(&mut state, &req, &session) {
+            return res;
+        }
+        if state.unsupported.contains(&req.method) {
+            return error(&req, &session, CODE_METHOD_NOT_FOUND, "Method not found!");
+        }
+
+        match req.method.as_str() {
+            "system.listMethod" => success(
+                &req,
+                &session,
+                json!({ "method": state.methods() }),
+                json!(true),
+            ),
+            "system.listService" => {
+                let mut services: Vec<&str> = state
+                    .methods()
+                    .into_iter()
+                    .filter_map(|method| method.split('.').next())
+                    .collect();
+                services.dedup();
+                success(&req, &session, json!({ "service": services }), json!(true))
+            }
+            "global.keepAlive" => {
+                // The device never grants more than its own session lifetime
+                let timeout = req.params["timeout"]
+                    .as_u64()
+                    .unwrap_or(60)
+                    .min(state.session_timeout.as_secs());
+                success(&req, &session, json!({ "timeout": timeout }), json!(true))
+            }
+            "global.logout" => {
+                state.sessions.remove(&session);
+                success(&req, &session, Value::Null, json!(true))
+            }
+            "global.getCurrentTime" => success(
+                &req,
+                &session,
+                json!({ "time": to_timestamp(&Utc::now()) }),
+                json!(true),
+            ),
+            "storage.factory.instance" | "mediaFileFind.factory.create" => {
+                let object = state.next_id();
+                success(&req, &session, Value::Null, json!(object))
+            }
+            "configManager.getConfig" | "configManager.getDefault" => {
+                let configs = if req.method == "configManager.getConfig" {
+                    &state.configs
+                } else {
+                    &state.defaults
+                };
+                let name = req.params["name"].as_str().unwrap_or_default();
+                let table = match (configs.get(name), req.params["channel"].as_u64()) {
+                    (Some(Value::Array(tables)), Some(channel)) => tables.get(channel as usize),
+                    (table, _) => table,
+                };
+
+                match table {
+                    Some(table) => success(&req, &session, json!({ "table": table }), json!(true)),
+                    None => error(&req, &session, CODE_INVALID_REQUEST, "Invalid request!"),
+                }
+            }
+            "configManager.setConfig" => {
+                let name = req.params["name"].as_str().unwrap_or_default().to_string();
+                let table = req.params["table"].clone();
+                match (state.configs.get_mut(&name), req.params["channel"].as_u64()) {
+                    (Some(Value::Array(tables)), Some(channel)) => {
+                        match tables.get_mut(channel as usize) {
+                            Some(t) => *t = table,
+                            None => {
+                                return error(
+                                    &req,
+                                    &session,
+                                    CODE_INVALID_REQUEST,
+                                    "Invalid request!",
+                                )
+                            }
+                        }
+                    }
+                    _ => {
+                        state.configs.insert(name, table);
+                    }
+                }
+
+                success(&req, &session, Value::Null, json!(true))
+            }
+            "mediaFileFind.findFile" => {
+                let condition = &req.params["condition"];
+                let channel = condition["Channel"].as_i64().unwrap_or_default() as i32;
+                let types: Vec<&str> = condition["Types"]
+                    .as_array()
+                    .map(|t| t.iter().filter_map(|t| t.as_str()).collect())
+                    .unwrap_or_default();
+                let (start, end) = match (
+                    condition["StartTime"].as_str().and_then(from_timestamp),
+                    condition["EndTime"].as_str().and_then(from_timestamp),
+                ) {
+                    (Some(start), Some(end)) => (start, end),
+                    _ => return error(&req, &session, CODE_INVALID_REQUEST, "Invalid request!"),
+                };
+
+                let mut files: Vec<MockFile> = state
+                    .files
+                    .iter()
+                    .filter(|f| {
+                        f.channel == channel
+                            && (types.is_empty() || types.contains(&f.kind.as_str()))
+                            && f.start_time >= start
+                            && f.start_time <= end
+                    })
+                    .cloned()
+                    .collect();
+                files.sort_by_key(|f| f.start_time);
+
+                if files.is_empty() {
+                    return error(&req, &session, CODE_NO_DATA, "No data!");
+                }
+
+                let object = req.object.unwrap_or_default();
+                state.finders.insert(object, Finder { files, cursor: 0 });
+
+                success(&req, &session, Value::Null, json!(true))
+            }
+            "mediaFileFind.findNextFile" => {
+                let count = req.params["count"].as_u64().unwrap_or(1) as usize;
+                let finder = match state.finders.get_mut(&req.object.unwrap_or_default()) {
+                    Some(finder) => finder,
+                    None => return error(&req, &session, CODE_INVALID_REQUEST, "Invalid request!"),
+                };
+
+                let infos: Vec<Value> = finder
+                    .files
+                    .iter()
+                    .skip(finder.cursor)
+                    .take(count)
+                    .map(MockFile::info)
+                    .collect();
+                finder.cursor += infos.len();
+
+                let params = if infos.is_empty() {
+                    json!({ "found": 0 })
+                } else {
+                    json!({ "found": infos.len(), "infos": infos })
+                };
+
+                success(&req, &session, params, json!(true))
+            }
+            "mediaFileFind.getCount" => {
+                let count = state
+                    .finders
+                    .get(&req.object.unwrap_or_default())
+                    .map(|f| f.files.len())
+                    .unwrap_or_default();
+                success(&req, &session, json!({ "count": count }), json!(true))
+            }
+            "mediaFileFind.close" => success(&req, &session, Value::Null, json!(true)),
+            "mediaFileFind.destroy" => {
+                state.finders.remove(&req.object.unwrap_or_default());
+                success(&req, &session, Value::Null, json!(true))
+            }
+            "userManager.getUserInfoAll" => {
+                let login = MockUser {
+                    name: state.username.clone(),
+                    password: state.password.clone(),
+                    group: "admin".to_string(),
+                    memo: "admin 's account".to_string(),
+                };
+                let users: Vec<Value> = std::iter::once(&login)
+                    .chain(state.users.iter())
+                    .enumerate()
+                    .map(|(id, user)| user.info(id))
+                    .collect();
+                success(&req, &session, json!({ "users": users }), json!(true))
+            }
+            "userManager.getGroupInfoAll" => success(
+                &req,
+                &session,
+                json!({ "group": [
+                    { "AuthorityList": [], "Id": 1, "Memo": "administrator group", "Name": "admin" },
+                    { "AuthorityList": [], "Id": 2, "Memo": "user group", "Name": "user" }
+                ]}),
+                json!(true),
+            ),
+            "userManager.getActiveUserInfoAll" => {
+                let users: Vec<Value> = state
+                    .sessions
+                    .values()
+                    .filter(|s| s.active)
+                    .enumerate()
+                    .map(|(id, _)| {
+                        json!({
+                            "ClientAddress": "127.0.0.1",
+                            "ClientType": "RPC",
+                            "Group": "admin",
+                            "Id": id + 1,
+                            "LoginTime": to_timestamp(&Utc::now()),
+                            "Name": state.username,
+                        })
+                    })
+                    .collect();
+                success(&req, &session, json!({ "users": users }), json!(true))
+            }
+            "userManager.addUser" => {
+                let user = &req.params["user"];
+                let name = user["Name"].as_str().unwrap_or_default().to_string();
+                if name.is_empty()
+                    || name == state.username
+                    || state.users.iter().any(|u| u.name == name)
+                {
+                    return error(&req, &session, CODE_INVALID_REQUEST, "Invalid request!");
+                }
+                state.users.push(MockUser {
+                    name,
+                    password: user["Password"].as_str().unwrap_or_default().to_string(),
+                    group: user["Group"].as_str().unwrap_or("user").to_string(),
+                    memo: user["Memo"].as_str().unwrap_or_default().to_string(),
+                });
+                success(&req, &session, Value::Null, json!(true))
+            }
+            "userManager.deleteUser" => {
+                let name = req.params["name"].as_str().unwrap_or_default();
+                let count = state.users.len();
+                state.users.retain(|u| u.name != name);
+                if state.users.len() == count {
+                    return error(&req, &session, CODE_INVALID_REQUEST, "Invalid request!");
+                }
+                success(&req, &session, Value::Null, json!(true))
+            }
+            "userManager.modifyPassword" => {
+                let name = req.params["name"].as_str().unwrap_or_default();
+                let old = req.params["pwdOld"].as_str().unwrap_or_default();
+                let new = req.params["pwd"].as_str().unwrap_or_default().to_string();
+                let state = &mut *state;
+                let password = if name == state.username {
+                    &mut state.password
+                } else {
+                    match state.users.iter_mut().find(|u| u.name == name) {
+                        Some(user) => &mut user.password,
+                        None => {
+                            return error(&req, &session, CODE_INVALID_REQUEST, "Invalid request!")
+                        }
+                    }
+                };
+                if *password != old || new.is_empty() {
+                    return error(
+                        &req,
+                        &session,
+                        CODE_USER_OR_PASSWORD_NOT_VALID,
+                        "User or password not valid!",
+                    );
+                }
+                *password = new;
+                success(&req, &session, Value::Null, json!(true))
+            }
+            method => match state.responses.get(method) {
+                Some(params) => success(&req, &session, params.clone(), json!(true)),
+                None => error(&req, &session, CODE_METHOD_NOT_FOUND, "Method not found!"),
+            },
+        }
+    }
+
+    fn injected_error(state: &mut DeviceState, req: &RpcRequest, session: &str) -> Option<Value> {
+        let (code, message) = state.errors.get_mut(&req.method)?.pop_front()?;
+        Some(error(req, session, code, &message))
+    }
+}
+
+fn success(req: &RpcRequest, session: &str, params: Value, result: Value) -> Value {
+    json!({
+        "id": req.id,
+        "params": params,
+        "result": result,
+        "session": session,
+    })
+}
+
+fn error(req: &RpcRequest, session: &str, code: i32, message: &str) -> Value {
+    json!({
+        "error": { "code": code, "message": message },
+        "id": req.id,
+        "params": null,
+        "result": false,
+        "session": session,
+    })
+}
@@ -0,0 +1,73 @@
+use std::net::SocketAddr;
+
+use serde_json::{json, Value};
+use tokio::{net::UdpSocket, task::JoinHandle};
+
+use crate::device::Device;
+
+const HEADER_LEN: usize = 32;
+
+/// Local UDP server that answers DHDiscover probes like a camera, it stops when dropped.
+pub struct MockResponder {
+    pub addr: SocketAddr,
+    handle: JoinHandle<()>,
+}
+
+impl MockResponder {
+    /// Replies advertise http_addr, usually the address of a MockServer for the same device.
+    pub async fn spawn(device: Device, http_addr: SocketAddr) -> std::io::Result<MockResponder> {
+        let socket = UdpSocket::bind("127.0.0.1:0").await?;
+        let addr = socket.local_addr()?;
+
+        let handle = tokio::spawn(async move {
+            let mut buf = vec![0; 64 * 1024];
+            loop {
+                let Ok((len, from)) = socket.recv_from(&mut buf).await else {
+                    continue;
+                };
+                if !is_probe(&buf[..len]) {
+                    continue;
+                }
+
+                let reply = json!({
+                    "mac": "",
+                    "method": "client.notifyDevInfo",
+                    "params": { "deviceInfo": device.device_info(http_addr) }
+                });
+                socket
+                    .send_to(&packet(reply.to_string().as_bytes()), from)
+                    .await
+                    .ok();
+            }
+        });
+
+        Ok(MockResponder { addr, handle })
+    }
+}
+
+impl Drop for MockResponder {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+fn is_probe(buf: &[u8]) -> bool {
+    buf.len() > HEADER_LEN
+        && &buf[4..8] == b"DHIP"
+        && serde_json::from_slice::<Value>(&buf[HEADER_LEN..])
+            .is_ok_and(|body| body["method"] == "DHDiscover.search")
+}
+
+fn packet(body: &[u8]) -> Vec<u8> {
+    let len = (body.len() as u32).to_le_bytes();
+
+    let mut buf = (HEADER_LEN as u32).to_le_bytes().to_vec();
+    buf.extend(b"DHIP");
+    buf.extend([0; 8]);
+    buf.extend(len);
+    buf.extend([0; 4]);
+    buf.extend(len);
+    buf.extend([0; 4]);
+    buf.extend(body);
+    buf
+}
@@ -0,0 +1,554 @@
+//! Fake Dahua camera that speaks RPC2 over HTTP so code using `dahua_rpc::Client` can be tested
+//! without a device.
+//!
+//! ```ignore
+//! let device = Device::new("admin", "password");
+//! device.add_file(MockFile::new(0, "dav", start, end));
+//! let server = MockServer::spawn(device.clone()).await?;
+//! let client = Client::new(reqwest::Client::new(), server.ip().parse()?, "admin".into(), "password".into());
+//! ```
+
+pub mod device;
+pub mod discover;
+pub mod server;
+
+pub use device::{Device, MockFile};
+pub use discover::MockResponder;
+pub use server::MockServer;
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+    use dahua_rpc::{
+        discover::Discover,
+        fixture::Replay,
+        modules::{config, magicbox, mediafilefind, usermanager},
+        multicall::Multicall,
+        reqwest, Client, Error, LockPolicy, LoginError, RequestError, RequestKind, ResponseError,
+        ResponseKind, RetryPolicy, State,
+    };
+
+    use serde_json::json;
+
+    use super::*;
+
+    async fn setup() -> (Device, MockServer, Client) {
+        let device = Device::new("admin", "password");
+        let server = MockServer::spawn(device.clone()).await.unwrap();
+        let client = Client::new(
+            dahua_rpc::recommended_reqwest_client_builder()
+                .build()
+                .unwrap(),
+            server.ip().parse().unwrap(),
+            "admin".to_string(),
+            "password".to_string(),
+        );
+
+        (device, server, client)
+    }
+
+    #[tokio::test]
+    async fn it_login() {
+        let (device, _server, client) = setup().await;
+
+        client.login().await.unwrap();
+        assert!(matches!(client.state(), State::Login(_)));
+        assert_eq!(device.sessions(), 1);
+
+        let sn = magicbox::get_serial_no(client.rpc().await.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(sn, "MOCK0000000000");
+
+        client.logout().await;
+        assert_eq!(device.sessions(), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn it_concurrent_clones() {
+        let (device, _server, client) = setup().await;
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let client = client.clone();
+                tokio::spawn(async move { magicbox::get_serial_no(client.rpc().await?).await })
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.await.unwrap().unwrap(), "MOCK0000000000");
+        }
+
+        // Login happened once and the clones share the session
+        let logins = device
+            .calls()
+            .iter()
+            .filter(|method| *method == "global.login")
+            .count();
+        assert_eq!(logins, 2);
+        assert_eq!(device.sessions(), 1);
+        assert!(matches!(client.state(), State::Login(_)));
+    }
+
+    #[tokio::test]
+    async fn it_login_error() {
+        let (device, _server, client) = setup().await;
+
+        device.set_password("other");
+        assert!(matches!(
+            client.login().await,
+            Err(Error::Login(LoginError::UserOrPasswordNotValid))
+        ));
+        assert!(matches!(
+            client.state(),
+            State::Error(LoginError::UserOrPasswordNotValid)
+        ));
+    }
+
+    #[tokio::test]
+    async fn it_login_locked() {
+        let (device, _server, client) = setup().await;
+
+        client.set_lock_policy(LockPolicy {
+            lock_time: std::time::Duration::from_millis(500),
+            ..Default::default()
+        });
+        device.set_locked(true);
+        assert!(matches!(
+            client.login().await,
+            Err(Error::Login(LoginError::HasBeenLocked))
+        ));
+        assert!(client.state().locked_for().is_some());
+
+        // No login is sent while locked
+        let logins = device.calls().len();
+        assert!(matches!(
+            client.rpc().await,
+            Err(Error::Login(LoginError::HasBeenLocked))
+        ));
+        assert_eq!(device.calls().len(), logins);
+
+        device.set_locked(false);
+        tokio::time::sleep(std::time::Duration::from_millis(600)).await;
+        client.rpc().await.unwrap();
+        assert!(matches!(client.state(), State::Login(_)));
+    }
+
+    #[tokio::test]
+    async fn it_lock_policy() {
+        let (_device, _server, client) = setup().await;
+
+        assert_eq!(client.lock_policy(), None);
+        client.login().await.unwrap();
+        assert_eq!(
+            client.lock_policy(),
+            Some(LockPolicy {
+                enabled: true,
+                lock_time: std::time::Duration::from_secs(1800),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn it_session_expired() {
+        let (device, _server, client) = setup().await;
+
+        client.login().await.unwrap();
+        device.expire_sessions();
+
+        assert!(matches!(
+            magicbox::get_serial_no(client.rpc().await.unwrap()).await,
+            Err(Error::Session(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn it_negotiated_timeout() {
+        let (device, _server, client) = setup().await;
+
+        device.set_session_timeout(std::time::Duration::from_secs(2));
+        client.login().await.unwrap();
+        let login = match client.state() {
+            State::Login(login) => login,
+            state => panic!("{state:?}"),
+        };
+        assert_eq!(login.timeout, std::time::Duration::from_secs(2));
+        assert!(login.expires_in() <= login.timeout);
+
+        // Past half of the session lifetime the session is renewed instead of logging in again
+        tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+        magicbox::get_serial_no(client.rpc().await.unwrap())
+            .await
+            .unwrap();
+        let calls = device.calls();
+        assert_eq!(calls.iter().filter(|m| *m == "global.login").count(), 2);
+        assert_eq!(calls.iter().filter(|m| *m == "global.keepAlive").count(), 1);
+        match client.state() {
+            State::Login(renewed) => {
+                assert_eq!(renewed.since, login.since);
+                assert!(renewed.renewed > login.renewed);
+            }
+            state => panic!("{state:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn it_keep_alive() {
+        let (device, _server, client) = setup().await;
+
+        device.set_session_timeout(std::time::Duration::from_secs(2));
+        client.login().await.unwrap();
+
+        // Due for a keep alive halfway through the session lifetime
+        tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+        client.rpc().await.unwrap();
+        // Past the lifetime of the login, but not of the keep alive
+        device.advance(std::time::Duration::from_millis(1500));
+        magicbox::get_serial_no(client.rpc().await.unwrap())
+            .await
+            .unwrap();
+
+        let calls = device.calls();
+        assert_eq!(calls.iter().filter(|m| *m == "global.keepAlive").count(), 1);
+        assert_eq!(calls.iter().filter(|m| *m == "global.login").count(), 2);
+        assert_eq!(device.sessions(), 1);
+        assert!(matches!(client.state(), State::Login(_)));
+
+        // Without a keep alive the session expires
+        device.advance(std::time::Duration::from_secs(2));
+        assert_eq!(device.sessions(), 0);
+    }
+
+    #[tokio::test]
+    async fn it_connection_reset() {
+        let (device, _server, client) = setup().await;
+
+        client.login().await.unwrap();
+        device.reset_connections(1);
+
+        // Retried by the default policy
+        assert!(magicbox::get_serial_no(client.rpc().await.unwrap())
+            .await
+            .is_ok());
+
+        // Requests that change the device are not
+        device.reset_connections(1);
+        assert!(matches!(
+            mediafilefind::create(client.rpc().await.unwrap()).await,
+            Err(Error::Request(_))
+        ));
+
+        let client = client.retry(RetryPolicy::none());
+        device.reset_connections(1);
+        assert!(matches!(
+            magicbox::get_serial_no(client.rpc().await.unwrap()).await,
+            Err(Error::Request(RequestError {
+                kind: RequestKind::ConnectionReset,
+                ..
+            }))
+        ));
+        assert!(magicbox::get_serial_no(client.rpc().await.unwrap())
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn it_error_injection() {
+        let (device, _server, client) = setup().await;
+
+        device.fail(
+            "magicBox.getSerialNo",
+            device::CODE_METHOD_NOT_FOUND,
+            "Method not found!",
+        );
+
+        assert!(matches!(
+            magicbox::get_serial_no(client.rpc().await.unwrap()).await,
+            Err(Error::Response(ResponseError {
+                kind: ResponseKind::MethodNotFound,
+                ..
+            }))
+        ));
+        assert!(magicbox::get_serial_no(client.rpc().await.unwrap())
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn it_multicall() {
+        let (device, _server, client) = setup().await;
+
+        device.fail(
+            "magicBox.getVendor",
+            device::CODE_METHOD_NOT_FOUND,
+            "Method not found!",
+        );
+        let mut mc = Multicall::new();
+        let sn = magicbox::get_serial_no_call(&mut mc);
+        let vendor = magicbox::get_vendor_call(&mut mc);
+        let mut res = client.multicall(mc).await.unwrap();
+
+        assert_eq!(res.take(sn).unwrap(), "MOCK0000000000");
+        assert!(matches!(
+            res.take(vendor),
+            Err(Error::Response(ResponseError {
+                kind: ResponseKind::MethodNotFound,
+                ..
+            }))
+        ));
+        assert_eq!(
+            device
+                .calls()
+                .iter()
+                .filter(|method| *method == "system.multicall")
+                .count(),
+            1
+        );
+
+        // Devices without system.multicall get one request per call
+        device.fail(
+            "system.multicall",
+            device::CODE_METHOD_NOT_FOUND,
+            "Method not found!",
+        );
+        let mut mc = Multicall::new();
+        let sn = magicbox::get_serial_no_call(&mut mc);
+        let vendor = magicbox::get_vendor_call(&mut mc);
+        let mut res = client.multicall(mc).await.unwrap();
+
+        assert_eq!(res.take(sn).unwrap(), "MOCK0000000000");
+        assert!(!res.take(vendor).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn it_capabilities() {
+        let (device, _server, client) = setup().await;
+
+        device.unsupport("magicBox.getVendor");
+        let capabilities = client.capabilities().await.unwrap();
+        assert!(capabilities.supports("magicBox.getSerialNo"));
+        assert!(capabilities.supports("mediaFileFind.findFile"));
+        assert!(!capabilities.supports("magicBox.getVendor"));
+        assert!(!capabilities.supports("ptz.start"));
+        assert!(!capabilities.has_service("ptz"));
+
+        // Cached for the session
+        client.capabilities().await.unwrap();
+        let lists = |device: &Device| {
+            device
+                .calls()
+                .iter()
+                .filter(|method| *method == "system.listMethod")
+                .count()
+        };
+        assert_eq!(lists(&device), 1);
+
+        // Devices that cannot list their methods support everything
+        client.logout().await;
+        device.unsupport("system.listMethod");
+        device.unsupport("system.listService");
+        let capabilities = client.capabilities().await.unwrap();
+        assert!(capabilities.is_unknown());
+        assert!(capabilities.supports("ptz.start"));
+    }
+
+    #[tokio::test]
+    async fn it_config() {
+        let (device, _server, client) = setup().await;
+
+        device.set_channels(2);
+        let titles = config::ChannelTitle::get(client.rpc().await.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(titles.0.len(), 2);
+
+        let mut general = config::General::get(client.rpc().await.unwrap())
+            .await
+            .unwrap();
+        general.machine_name = "Changed".to_string();
+        general.set(client.rpc().await.unwrap()).await.unwrap();
+        assert_eq!(device.config("General").unwrap()["MachineName"], "Changed");
+    }
+
+    #[tokio::test]
+    async fn it_record_and_replay() {
+        let (_device, server, mut client) = setup().await;
+
+        let dir = std::env::temp_dir().join(format!(
+            "dahua-rpc-fixtures-{}-{}",
+            std::process::id(),
+            server.addr.port()
+        ));
+        client.record(&dir).await.unwrap();
+        magicbox::get_serial_no(client.rpc().await.unwrap())
+            .await
+            .unwrap();
+        config::General::get(client.rpc().await.unwrap())
+            .await
+            .unwrap();
+
+        let recorder = client.recorder.clone().unwrap();
+        assert!(recorder.dir().ends_with("IPC-HDW-MOCK/2.800.0000000.0.R"));
+
+        let replay = Replay::load(recorder.dir()).unwrap();
+        assert_eq!(
+            magicbox::get_serial_no(replay.rpc()).await.unwrap(),
+            "REDACTED"
+        );
+        let general = config::General::get(replay.rpc()).await.unwrap();
+        assert_eq!(general.machine_name, "REDACTED");
+        assert_eq!(general.lock_login_times, 5);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn it_find_and_load_files() {
+        let (device, _server, client) = setup().await;
+
+        let start = Utc.with_ymd_and_hms(2023, 6, 18, 10, 0, 0).unwrap();
+        for i in 0..3 {
+            let start = start + Duration::minutes(5 * i);
+            device.add_file(
+                MockFile::new(0, "dav", start, start + Duration::minutes(5))
+                    .data(vec![i as u8; 16]),
+            );
+        }
+        device.add_file(MockFile::new(1, "dav", start, start + Duration::minutes(5)));
+
+        let object = mediafilefind::create(client.rpc().await.unwrap())
+            .await
+            .unwrap();
+        let condition = mediafilefind::Condition::new(start, start + Duration::hours(1)).video();
+        assert!(
+            mediafilefind::find_file(client.rpc().await.unwrap(), object, condition)
+                .await
+                .unwrap()
+        );
+
+        let first = mediafilefind::find_next_file(client.rpc().await.unwrap(), object, 2)
+            .await
+            .unwrap();
+        assert_eq!(first.found, 2);
+        let infos = first.infos.unwrap();
+        assert_eq!(infos[0].start_time, start);
+        assert_eq!(infos[0].length, 16);
+
+        let second = mediafilefind::find_next_file(client.rpc().await.unwrap(), object, 2)
+            .await
+            .unwrap();
+        assert_eq!(second.found, 1);
+
+        let res = reqwest::Client::new()
+            .get(client.file_url(&infos[1].file_path))
+            .header(reqwest::header::COOKIE, client.cookie().await.unwrap())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), reqwest::StatusCode::OK);
+        assert_eq!(res.bytes().await.unwrap().to_vec(), vec![1; 16]);
+
+        // Nothing on the requested channel
+        let object = mediafilefind::create(client.rpc().await.unwrap())
+            .await
+            .unwrap();
+        let condition = mediafilefind::Condition::new(start, start + Duration::hours(1))
+            .video()
+            .channel(2);
+        assert!(matches!(
+            mediafilefind::find_file(client.rpc().await.unwrap(), object, condition).await,
+            Err(Error::Response(ResponseError {
+                kind: ResponseKind::NoData,
+                ..
+            }))
+        ));
+    }
+
+    #[tokio::test]
+    async fn it_discover() {
+        let (device, server, _client) = setup().await;
+        let other = Device::new("admin", "password");
+        other.respond("magicBox.getSerialNo", json!({ "sn": "MOCK0000000001" }));
+        let responders = [
+            MockResponder::spawn(device, server.addr).await.unwrap(),
+            MockResponder::spawn(other, "127.0.0.2:8080".parse().unwrap())
+                .await
+                .unwrap(),
+        ];
+
+        let mut devices = Discover::new()
+            .targets(responders.iter().map(|r| r.addr).collect())
+            .timeout(std::time::Duration::from_millis(300))
+            .run()
+            .await
+            .unwrap();
+        devices.sort_by(|a, b| a.serial.cmp(&b.serial));
+
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].ip, "127.0.0.1");
+        assert_eq!(devices[0].http_port, server.addr.port());
+        assert_eq!(devices[0].serial, "MOCK0000000000");
+        assert_eq!(devices[0].device_type, "IPC-HDW-MOCK");
+        assert_eq!(devices[0].version, "2.800.0000000.0.R");
+        assert_eq!(devices[1].ip, "127.0.0.2");
+        assert_eq!(devices[1].serial, "MOCK0000000001");
+        assert_ne!(devices[0].mac, devices[1].mac);
+    }
+
+    #[tokio::test]
+    async fn it_user_manager() {
+        let (device, _server, client) = setup().await;
+
+        let users = usermanager::get_user_info_all(client.rpc().await.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(users.len(), 1);
+        assert_eq!(users[0].name, "admin");
+        let groups = usermanager::get_group_info_all(client.rpc().await.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(groups.len(), 2);
+        let active = usermanager::get_active_user_info_all(client.rpc().await.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(active.len(), 1);
+
+        let user = usermanager::NewUser {
+            name: "viewer".to_string(),
+            password: "viewer".to_string(),
+            group: "user".to_string(),
+            memo: "".to_string(),
+            authority_list: vec![],
+            reserved: false,
+            sharable: true,
+        };
+        assert!(usermanager::add_user(client.rpc().await.unwrap(), user)
+            .await
+            .unwrap());
+        assert!(
+            usermanager::delete_user(client.rpc().await.unwrap(), "viewer")
+                .await
+                .unwrap()
+        );
+
+        // Wrong old password
+        assert!(usermanager::modify_password(
+            client.rpc().await.unwrap(),
+            "admin",
+            "wrong",
+            "rotated"
+        )
+        .await
+        .is_err());
+        assert_eq!(device.password(), "password");
+
+        assert!(usermanager::modify_password(
+            client.rpc().await.unwrap(),
+            "admin",
+            "password",
+            "rotated"
+        )
+        .await
+        .unwrap());
+        assert_eq!(device.password(), "rotated");
+    }
+}
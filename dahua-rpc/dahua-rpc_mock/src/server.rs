@@ -0,0 +1,133 @@
+use std::{convert::Infallible, net::SocketAddr};
+
+use axum::{
+    body::{Bytes, StreamBody},
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use futures_util::stream;
+use serde_json::Value;
+use tokio::{net::TcpListener, sync::oneshot, task::JoinHandle};
+
+use crate::device::{Device, RpcRequest};
+
+/// Local HTTP server that answers like a camera, it is shut down when dropped.
+pub struct MockServer {
+    pub addr: SocketAddr,
+    shutdown: Option<oneshot::Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+    pub async fn spawn(device: Device) -> std::io::Result<MockServer> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+
+        let app = Router::new()
+            .route("/RPC2_Login", post(rpc_login))
+            .route("/RPC2", post(rpc))
+            .route("/RPC_Loadfile/*file_path", get(load_file))
+            .with_state(device.clone());
+
+        // Connections are accepted by hand so that they can be reset
+        let incoming = stream::unfold((listener, device), |(listener, device)| async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) if device.take_reset() => drop(stream),
+                    Ok((stream, _)) => {
+                        return Some((Ok::<_, Infallible>(stream), (listener, device)))
+                    }
+                    Err(_) => continue,
+                }
+            }
+        });
+
+        let (shutdown, rx) = oneshot::channel::<()>();
+        let server = hyper::Server::builder(hyper::server::accept::from_stream(incoming))
+            // Every request gets its own connection so resets are predictable
+            .http1_keepalive(false)
+            .serve(app.into_make_service())
+            .with_graceful_shutdown(async {
+                rx.await.ok();
+            });
+        let handle = tokio::spawn(async move {
+            server.await.ok();
+        });
+
+        Ok(MockServer {
+            addr,
+            shutdown: Some(shutdown),
+            handle: Some(handle),
+        })
+    }
+
+    /// Address to give to the client in place of the camera's IP.
+    pub fn ip(&self) -> String {
+        self.addr.to_string()
+    }
+
+    pub async fn shutdown(mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            shutdown.send(()).ok();
+        }
+        if let Some(handle) = self.handle.take() {
+            handle.await.ok();
+        }
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            shutdown.send(()).ok();
+        }
+    }
+}
+
+async fn rpc_login(State(device): State<Device>, Json(req): Json<RpcRequest>) -> Json<Value> {
+    Json(device.login(req))
+}
+
+async fn rpc(State(device): State<Device>, Json(req): Json<RpcRequest>) -> Response {
+    let method = req.method.clone();
+    let res = device.rpc(req);
+    if device.take_lost(&method) {
+        // The body fails after the headers so the connection is closed without an answer
+        let body = stream::once(async {
+            Err::<Bytes, _>(std::io::Error::from(std::io::ErrorKind::ConnectionReset))
+        });
+        return StreamBody::new(body).into_response();
+    }
+
+    Json(res).into_response()
+}
+
+fn cookie_session(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::COOKIE)?
+        .to_str()
+        .ok()?
+        .split(';')
+        .filter_map(|c| c.trim().split_once('='))
+        .find(|(key, _)| *key == "WebClientSessionID")
+        .map(|(_, value)| value)
+}
+
+async fn load_file(
+    Path(file_path): Path<String>,
+    State(device): State<Device>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let session = cookie_session(&headers).unwrap_or_default();
+    if !device.session_valid(session) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    match device.file(session, &format!("/{file_path}")) {
+        Some(data) => Ok(Bytes::from(data)),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
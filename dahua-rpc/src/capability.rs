@@ -0,0 +1,106 @@
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{modules::system, multicall::Multicall, Client, Error, ResponseKind};
+
+/// Methods and services a device says it supports.
+///
+/// Devices that cannot list them have empty sets and are assumed to support everything.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq, Debug)]
+pub struct Capabilities {
+    pub methods: BTreeSet<String>,
+    pub services: BTreeSet<String>,
+}
+
+impl Capabilities {
+    pub fn is_unknown(&self) -> bool {
+        self.methods.is_empty() && self.services.is_empty()
+    }
+
+    /// Service of a method such as `mediaFileFind` for `mediaFileFind.factory.create`.
+    pub fn service_of(method: &str) -> &str {
+        method.split('.').next().unwrap_or(method)
+    }
+
+    pub fn has_service(&self, service: &str) -> bool {
+        self.services.is_empty() || self.services.contains(service)
+    }
+
+    pub fn supports(&self, method: &str) -> bool {
+        if !self.methods.is_empty() {
+            return self.methods.contains(method);
+        }
+        self.has_service(Self::service_of(method))
+    }
+}
+
+/// Treats errors from devices that do not know the method as an empty list.
+fn unsupported_as_empty(res: Result<Vec<String>, Error>) -> Result<Vec<String>, Error> {
+    match res {
+        Err(Error::Response(err))
+            if matches!(
+                err.kind,
+                ResponseKind::MethodNotFound
+                    | ResponseKind::InterfaceNotFound
+                    | ResponseKind::InvalidRequest
+            ) =>
+        {
+            Ok(vec![])
+        }
+        res => res,
+    }
+}
+
+impl Client {
+    /// Capabilities of the device, they are queried once per session.
+    pub async fn capabilities(&self) -> Result<Capabilities, Error> {
+        self.keep_alive_or_login().await?;
+        if let Some(capabilities) = self.cached_capabilities() {
+            return Ok(capabilities);
+        }
+
+        let mut mc = Multicall::new();
+        let methods = system::list_method_call(&mut mc);
+        let services = system::list_service_call(&mut mc);
+        let mut res = self.multicall(mc).await?;
+        let capabilities = Capabilities {
+            methods: unsupported_as_empty(res.take(methods))?
+                .into_iter()
+                .collect(),
+            services: unsupported_as_empty(res.take(services))?
+                .into_iter()
+                .collect(),
+        };
+
+        self.cache_capabilities(capabilities.clone());
+        Ok(capabilities)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_supports() {
+        let unknown = Capabilities::default();
+        assert!(unknown.is_unknown());
+        assert!(unknown.supports("ptz.start"));
+
+        let services = Capabilities {
+            methods: BTreeSet::new(),
+            services: ["magicBox", "mediaFileFind"].map(String::from).into(),
+        };
+        assert!(services.supports("mediaFileFind.factory.create"));
+        assert!(!services.supports("ptz.start"));
+
+        let methods = Capabilities {
+            methods: ["magicBox.getSerialNo"].map(String::from).into(),
+            services: ["magicBox"].map(String::from).into(),
+        };
+        assert!(methods.supports("magicBox.getSerialNo"));
+        assert!(!methods.supports("magicBox.getVendor"));
+        assert!(methods.has_service("magicBox"));
+    }
+}
@@ -0,0 +1,334 @@
+//! Parser for Dahua's DAV container which is what RPC_Loadfile returns for recordings.
+//!
+//! Every frame looks like `DHAV` + 20 byte header + extension fields + payload + `dhav` + length.
+//! Video payloads are H.264 or H.265 in Annex B format.
+
+use chrono::NaiveDateTime;
+
+pub mod mp4;
+
+const MAGIC: &[u8; 4] = b"DHAV";
+const TRAILER_MAGIC: &[u8; 4] = b"dhav";
+const HEADER_LEN: usize = 24;
+const TRAILER_LEN: usize = 8;
+/// Frames larger than this are treated as corrupt.
+const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+const SAMPLE_RATES: &[u32] = &[
+    8000, 4000, 8000, 11025, 16000, 20000, 22050, 32000, 44100, 48000, 96000, 192000, 64000,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameKind {
+    Video {
+        key: bool,
+    },
+    Audio,
+    /// Metadata such as motion detection or smart codec info.
+    Aux,
+    Unknown(u8),
+}
+
+impl From<u8> for FrameKind {
+    fn from(value: u8) -> Self {
+        match value {
+            0xFD => FrameKind::Video { key: true },
+            0xFC | 0xFB => FrameKind::Video { key: false },
+            0xF0 => FrameKind::Audio,
+            0xF1 => FrameKind::Aux,
+            _ => FrameKind::Unknown(value),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VideoCodec {
+    Mpeg4,
+    H264,
+    H265,
+    Unknown(u8),
+}
+
+impl From<u8> for VideoCodec {
+    fn from(value: u8) -> Self {
+        match value {
+            0x01 => VideoCodec::Mpeg4,
+            0x02 | 0x08 => VideoCodec::H264,
+            0x0C => VideoCodec::H265,
+            _ => VideoCodec::Unknown(value),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AudioCodec {
+    PcmS8,
+    PcmS16le,
+    Mulaw,
+    Alaw,
+    AdpcmMs,
+    Aac,
+    Mp2,
+    Mp3,
+    Unknown(u8),
+}
+
+impl From<u8> for AudioCodec {
+    fn from(value: u8) -> Self {
+        match value {
+            0x07 => AudioCodec::PcmS8,
+            0x0C | 0x10 => AudioCodec::PcmS16le,
+            0x0A | 0x16 => AudioCodec::Mulaw,
+            0x0E => AudioCodec::Alaw,
+            0x0D => AudioCodec::AdpcmMs,
+            0x1A => AudioCodec::Aac,
+            0x1F => AudioCodec::Mp2,
+            0x21 => AudioCodec::Mp3,
+            _ => AudioCodec::Unknown(value),
+        }
+    }
+}
+
+/// Stream info from the extension fields, most of it is only sent with key frames.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct FrameInfo {
+    pub width: Option<u16>,
+    pub height: Option<u16>,
+    pub video_codec: Option<VideoCodec>,
+    pub frame_rate: Option<u8>,
+    pub audio_codec: Option<AudioCodec>,
+    pub audio_channels: Option<u8>,
+    pub sample_rate: Option<u32>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Frame {
+    pub kind: FrameKind,
+    pub sub_type: u8,
+    pub channel: u8,
+    pub sequence: u32,
+    /// Camera local time with a resolution of a second.
+    pub date_time: Option<NaiveDateTime>,
+    /// Milliseconds, wraps around.
+    pub timestamp: u16,
+    pub info: FrameInfo,
+    pub data: Vec<u8>,
+}
+
+impl Frame {
+    pub fn is_key(&self) -> bool {
+        self.kind == FrameKind::Video { key: true }
+    }
+}
+
+/// Decodes the packed date time of a frame header.
+pub fn date_time(value: u32) -> Option<NaiveDateTime> {
+    let sec = value & 0x3F;
+    let min = (value >> 6) & 0x3F;
+    let hour = (value >> 12) & 0x1F;
+    let day = (value >> 17) & 0x1F;
+    let month = (value >> 22) & 0x0F;
+    let year = ((value >> 26) & 0x3F) as i32 + 2000;
+
+    chrono::NaiveDate::from_ymd_opt(year, month, day)?.and_hms_opt(hour, min, sec)
+}
+
+fn parse_info(mut ext: &[u8]) -> FrameInfo {
+    let mut info = FrameInfo::default();
+    while let Some(&kind) = ext.first() {
+        let len = match kind {
+            0x80 | 0x81 | 0x83 | 0x84 | 0x85 | 0x8B | 0x94 | 0x96 | 0xA0 | 0xB2 | 0xB4 => 4,
+            0x82 | 0x88 | 0x8C | 0x91 | 0x92 | 0x93 | 0x95 | 0x9A | 0x9B | 0xB3 => 8,
+            // Length of unknown fields is unknown
+            _ => break,
+        };
+        if ext.len() < len {
+            break;
+        }
+
+        let field = &ext[..len];
+        match kind {
+            0x80 => {
+                info.width = Some(field[2] as u16 * 8);
+                info.height = Some(field[3] as u16 * 8);
+            }
+            0x81 => {
+                info.video_codec = Some(field[2].into());
+                info.frame_rate = Some(field[3]);
+            }
+            0x82 => {
+                info.width = Some(u16::from_le_bytes([field[4], field[5]]));
+                info.height = Some(u16::from_le_bytes([field[6], field[7]]));
+            }
+            0x83 | 0x8C => {
+                let field = if kind == 0x8C { &field[1..] } else { field };
+                info.audio_channels = Some(field[1]);
+                info.audio_codec = Some(field[2].into());
+                info.sample_rate = SAMPLE_RATES.get(field[3] as usize).copied();
+            }
+            _ => {}
+        }
+
+        ext = &ext[len..];
+    }
+
+    info
+}
+
+/// Incremental parser, feed it bytes as they arrive and take frames out.
+///
+/// Garbage between frames, such as a download that starts in the middle of a frame, is skipped.
+#[derive(Default, Debug)]
+pub struct Parser {
+    buf: Vec<u8>,
+    offset: usize,
+}
+
+impl Parser {
+    pub fn new() -> Parser {
+        Parser::default()
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        // Drop consumed bytes before growing the buffer
+        if self.offset > 0 {
+            self.buf.drain(..self.offset);
+            self.offset = 0;
+        }
+        self.buf.extend_from_slice(data);
+    }
+
+    /// Next complete frame, None when more bytes are needed.
+    pub fn next_frame(&mut self) -> Option<Frame> {
+        loop {
+            let buf = &self.buf[self.offset..];
+            let Some(start) = buf.windows(MAGIC.len()).position(|w| w == MAGIC) else {
+                // Keep what could be the start of the magic
+                self.offset += buf.len().saturating_sub(MAGIC.len() - 1);
+                return None;
+            };
+            self.offset += start;
+
+            let buf = &self.buf[self.offset..];
+            if buf.len() < HEADER_LEN {
+                return None;
+            }
+            let len = u32::from_le_bytes([buf[12], buf[13], buf[14], buf[15]]) as usize;
+            let ext_len = buf[22] as usize;
+            if !(HEADER_LEN + ext_len + TRAILER_LEN..=MAX_FRAME_LEN).contains(&len) {
+                self.offset += 1;
+                continue;
+            }
+            if buf.len() < len {
+                return None;
+            }
+            let trailer = &buf[len - TRAILER_LEN..len];
+            let trailer_len = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+            if &trailer[..4] != TRAILER_MAGIC || trailer_len as usize != len {
+                self.offset += 1;
+                continue;
+            }
+
+            let frame = Frame {
+                kind: buf[4].into(),
+                sub_type: buf[5],
+                channel: buf[6],
+                sequence: u32::from_le_bytes([buf[8], buf[9], buf[10], buf[11]]),
+                date_time: date_time(u32::from_le_bytes([buf[16], buf[17], buf[18], buf[19]])),
+                timestamp: u16::from_le_bytes([buf[20], buf[21]]),
+                info: parse_info(&buf[HEADER_LEN..HEADER_LEN + ext_len]),
+                data: buf[HEADER_LEN + ext_len..len - TRAILER_LEN].to_vec(),
+            };
+            self.offset += len;
+
+            return Some(frame);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Packs a frame the way a camera does.
+    pub(crate) fn frame(
+        kind: u8,
+        sequence: u32,
+        timestamp: u16,
+        ext: &[u8],
+        data: &[u8],
+    ) -> Vec<u8> {
+        let len = (HEADER_LEN + ext.len() + data.len() + TRAILER_LEN) as u32;
+        // 2023-06-01 12:30:15
+        let date: u32 = (23 << 26) | (6 << 22) | (1 << 17) | (12 << 12) | (30 << 6) | 15;
+
+        let mut buf = MAGIC.to_vec();
+        buf.extend([kind, 0, 0, 0]);
+        buf.extend(sequence.to_le_bytes());
+        buf.extend(len.to_le_bytes());
+        buf.extend(date.to_le_bytes());
+        buf.extend(timestamp.to_le_bytes());
+        buf.extend([ext.len() as u8, 0]);
+        buf.extend(ext);
+        buf.extend(data);
+        buf.extend(TRAILER_MAGIC);
+        buf.extend(len.to_le_bytes());
+        buf
+    }
+
+    #[test]
+    fn it_parse_frames() {
+        let ext = [0x80, 0, 240, 135, 0x81, 0, 0x02, 25];
+        let mut data = b"garbage".to_vec();
+        data.extend(frame(0xFD, 1, 1000, &ext, &[0, 0, 0, 1, 0x65]));
+        data.extend(frame(0xF0, 2, 1020, &[0x83, 1, 0x0E, 0], &[0xD5; 4]));
+        data.extend(frame(0xFC, 3, 1040, &[], &[0, 0, 0, 1, 0x41]));
+
+        // Byte by byte to cover frames split across pushes
+        let mut parser = Parser::new();
+        let mut frames = vec![];
+        for byte in data {
+            parser.push(&[byte]);
+            frames.extend(std::iter::from_fn(|| parser.next_frame()));
+        }
+
+        assert_eq!(frames.len(), 3);
+        let key = &frames[0];
+        assert!(key.is_key());
+        assert_eq!(key.sequence, 1);
+        assert_eq!(key.timestamp, 1000);
+        assert_eq!(key.data, [0, 0, 0, 1, 0x65]);
+        assert_eq!(
+            key.date_time.unwrap().to_string(),
+            "2023-06-01 12:30:15".to_string()
+        );
+        assert_eq!(key.info.width, Some(1920));
+        assert_eq!(key.info.height, Some(1080));
+        assert_eq!(key.info.video_codec, Some(VideoCodec::H264));
+        assert_eq!(key.info.frame_rate, Some(25));
+
+        let audio = &frames[1];
+        assert_eq!(audio.kind, FrameKind::Audio);
+        assert_eq!(audio.info.audio_codec, Some(AudioCodec::Alaw));
+        assert_eq!(audio.info.sample_rate, Some(8000));
+
+        assert_eq!(frames[2].kind, FrameKind::Video { key: false });
+        assert!(!frames[2].is_key());
+        assert_eq!(frames[2].info, FrameInfo::default());
+    }
+
+    #[test]
+    fn it_skip_corrupt_frame() {
+        let mut corrupt = frame(0xFD, 1, 0, &[], &[1, 2, 3]);
+        let len = corrupt.len();
+        corrupt[len - 8] = b'x';
+
+        let mut parser = Parser::new();
+        parser.push(&corrupt);
+        parser.push(&frame(0xFC, 2, 40, &[], &[4, 5, 6]));
+
+        let frame = parser.next_frame().unwrap();
+        assert_eq!(frame.sequence, 2);
+        assert!(parser.next_frame().is_none());
+    }
+}
@@ -0,0 +1,803 @@
+//! Remuxes the video of a DAV stream into fragmented MP4 without re-encoding.
+//!
+//! Audio is dropped as cameras mostly record G.711 which browsers do not play from MP4.
+//! Every GOP becomes one fragment so playback can start before the whole file has been read.
+
+use super::{Frame, FrameKind, Parser, VideoCodec};
+
+/// Timescale of the video track, DAV timestamps are in milliseconds.
+const TIMESCALE: u32 = 1000;
+const TRACK_ID: u32 = 1;
+/// Sample duration when the frame rate is unknown.
+const DEFAULT_DURATION: u32 = 40;
+
+const SAMPLE_FLAGS_KEY: u32 = 0x0200_0000;
+const SAMPLE_FLAGS_NON_KEY: u32 = 0x0101_0000;
+
+fn mp4_box(name: &[u8; 4], content: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(8 + content.len());
+    buf.extend(((8 + content.len()) as u32).to_be_bytes());
+    buf.extend(name);
+    buf.extend(content);
+    buf
+}
+
+fn full_box(name: &[u8; 4], version: u8, flags: u32, content: &[u8]) -> Vec<u8> {
+    let mut buf = vec![version];
+    buf.extend(&flags.to_be_bytes()[1..]);
+    buf.extend(content);
+    mp4_box(name, &buf)
+}
+
+/// Writes big endian fields one after the other.
+#[derive(Default)]
+struct Fields(Vec<u8>);
+
+impl Fields {
+    fn u8(mut self, value: u8) -> Self {
+        self.0.push(value);
+        self
+    }
+
+    fn u16(mut self, value: u16) -> Self {
+        self.0.extend(value.to_be_bytes());
+        self
+    }
+
+    fn u32(mut self, value: u32) -> Self {
+        self.0.extend(value.to_be_bytes());
+        self
+    }
+
+    fn bytes(mut self, value: &[u8]) -> Self {
+        self.0.extend(value);
+        self
+    }
+
+    fn zeros(mut self, count: usize) -> Self {
+        self.0.resize(self.0.len() + count, 0);
+        self
+    }
+
+    fn matrix(self) -> Self {
+        [0x0001_0000, 0, 0, 0, 0x0001_0000, 0, 0, 0, 0x4000_0000]
+            .into_iter()
+            .fold(self, |fields, value| fields.u32(value))
+    }
+}
+
+/// Splits an Annex B byte stream into NAL units without start codes.
+pub fn split_nals(data: &[u8]) -> Vec<&[u8]> {
+    let mut nals = vec![];
+    let mut start = None;
+    let mut i = 0;
+    while i + 3 <= data.len() {
+        if data[i] == 0 && data[i + 1] == 0 && data[i + 2] == 1 {
+            if let Some(start) = start {
+                nals.push(trim_zeros(&data[start..i]));
+            }
+            i += 3;
+            start = Some(i);
+        } else {
+            i += 1;
+        }
+    }
+    if let Some(start) = start {
+        nals.push(&data[start..]);
+    }
+
+    nals.into_iter().filter(|nal| !nal.is_empty()).collect()
+}
+
+/// Removes the leading zero of a 4 byte start code that belongs to the next NAL unit.
+fn trim_zeros(nal: &[u8]) -> &[u8] {
+    let end = nal.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+    &nal[..end]
+}
+
+/// Removes emulation prevention bytes.
+fn rbsp(nal: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(nal.len());
+    let mut zeros = 0;
+    for &b in nal {
+        if zeros >= 2 && b == 3 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if b == 0 { zeros + 1 } else { 0 };
+        buf.push(b);
+    }
+    buf
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        BitReader { data, pos: 0 }
+    }
+
+    fn bit(&mut self) -> Option<u32> {
+        let byte = self.data.get(self.pos / 8)?;
+        let bit = (byte >> (7 - self.pos % 8)) & 1;
+        self.pos += 1;
+        Some(bit as u32)
+    }
+
+    fn bits(&mut self, count: usize) -> Option<u32> {
+        (0..count).try_fold(0, |value, _| Some((value << 1) | self.bit()?))
+    }
+
+    fn skip(&mut self, count: usize) {
+        self.pos += count;
+    }
+
+    /// Exp-Golomb coded unsigned integer.
+    fn ue(&mut self) -> Option<u32> {
+        let mut zeros = 0;
+        while self.bit()? == 0 {
+            zeros += 1;
+            if zeros > 31 {
+                return None;
+            }
+        }
+        Some((1 << zeros) - 1 + self.bits(zeros)?)
+    }
+}
+
+/// Fields of a H.265 SPS needed for the hvcC box.
+struct HevcSps {
+    max_sub_layers: u8,
+    temporal_id_nesting: bool,
+    /// general_profile_space through general_level_idc.
+    profile_tier_level: [u8; 12],
+    chroma_format_idc: u8,
+    bit_depth_luma_minus8: u8,
+    bit_depth_chroma_minus8: u8,
+}
+
+impl HevcSps {
+    fn parse(nal: &[u8]) -> Option<HevcSps> {
+        let rbsp = rbsp(nal);
+        let mut r = BitReader::new(rbsp.get(2..)?);
+        r.skip(4); // sps_video_parameter_set_id
+        let max_sub_layers_minus1 = r.bits(3)? as usize;
+        let temporal_id_nesting = r.bit()? == 1;
+        let profile_tier_level: [u8; 12] = rbsp.get(3..15)?.try_into().ok()?;
+        r.skip(96);
+
+        // Skip sub layer profile and level
+        let mut present = vec![];
+        for _ in 0..max_sub_layers_minus1 {
+            present.push((r.bit()?, r.bit()?));
+        }
+        if max_sub_layers_minus1 > 0 {
+            r.skip(2 * (8 - max_sub_layers_minus1));
+        }
+        for (profile, level) in present {
+            r.skip(88 * profile as usize + 8 * level as usize);
+        }
+
+        r.ue()?; // sps_seq_parameter_set_id
+        let chroma_format_idc = r.ue()?;
+        if chroma_format_idc == 3 {
+            r.skip(1); // separate_colour_plane_flag
+        }
+        r.ue()?; // pic_width_in_luma_samples
+        r.ue()?; // pic_height_in_luma_samples
+        if r.bit()? == 1 {
+            // conf_win offsets
+            for _ in 0..4 {
+                r.ue()?;
+            }
+        }
+        let bit_depth_luma_minus8 = r.ue()?;
+        let bit_depth_chroma_minus8 = r.ue()?;
+
+        Some(HevcSps {
+            max_sub_layers: max_sub_layers_minus1 as u8 + 1,
+            temporal_id_nesting,
+            profile_tier_level,
+            chroma_format_idc: chroma_format_idc as u8,
+            bit_depth_luma_minus8: bit_depth_luma_minus8 as u8,
+            bit_depth_chroma_minus8: bit_depth_chroma_minus8 as u8,
+        })
+    }
+}
+
+/// Parameter sets of the video stream.
+#[derive(Default)]
+struct ParameterSets {
+    vps: Option<Vec<u8>>,
+    sps: Option<Vec<u8>>,
+    pps: Option<Vec<u8>>,
+}
+
+impl ParameterSets {
+    fn is_complete(&self, codec: VideoCodec) -> bool {
+        self.sps.is_some()
+            && self.pps.is_some()
+            && (codec != VideoCodec::H265 || self.vps.is_some())
+    }
+
+    fn avcc(&self) -> Option<Vec<u8>> {
+        let (sps, pps) = (self.sps.as_ref()?, self.pps.as_ref()?);
+        let content = Fields::default()
+            .u8(1)
+            .bytes(sps.get(1..4)?) // profile, compatibility and level
+            .u8(0xFF) // 4 byte NAL lengths
+            .u8(0xE1)
+            .u16(sps.len() as u16)
+            .bytes(sps)
+            .u8(1)
+            .u16(pps.len() as u16)
+            .bytes(pps);
+
+        Some(mp4_box(b"avcC", &content.0))
+    }
+
+    fn hvcc(&self) -> Option<Vec<u8>> {
+        let (vps, sps, pps) = (self.vps.as_ref()?, self.sps.as_ref()?, self.pps.as_ref()?);
+        let info = HevcSps::parse(sps)?;
+        let mut content = Fields::default()
+            .u8(1)
+            .bytes(&info.profile_tier_level)
+            .u16(0xF000) // min_spatial_segmentation_idc
+            .u8(0xFC) // parallelismType
+            .u8(0xFC | info.chroma_format_idc)
+            .u8(0xF8 | info.bit_depth_luma_minus8)
+            .u8(0xF8 | info.bit_depth_chroma_minus8)
+            .u16(0) // avgFrameRate
+            .u8((info.max_sub_layers << 3) | ((info.temporal_id_nesting as u8) << 2) | 3)
+            .u8(3);
+        for (nal_type, nal) in [(32, vps), (33, sps), (34, pps)] {
+            content = content
+                .u8(0x80 | nal_type)
+                .u16(1)
+                .u16(nal.len() as u16)
+                .bytes(nal);
+        }
+
+        Some(mp4_box(b"hvcC", &content.0))
+    }
+}
+
+struct Sample {
+    /// NAL units with 4 byte length prefixes.
+    data: Vec<u8>,
+    key: bool,
+    time: u64,
+}
+
+/// Turns video frames into an init segment followed by a fragment per GOP.
+pub struct Remuxer {
+    codec: Option<VideoCodec>,
+    width: u16,
+    height: u16,
+    frame_rate: Option<u8>,
+    params: ParameterSets,
+    initialized: bool,
+    sequence: u32,
+    samples: Vec<Sample>,
+    last_timestamp: Option<u16>,
+    time: u64,
+}
+
+impl Default for Remuxer {
+    fn default() -> Self {
+        Remuxer::new()
+    }
+}
+
+impl Remuxer {
+    pub fn new() -> Remuxer {
+        Remuxer {
+            codec: None,
+            width: 0,
+            height: 0,
+            frame_rate: None,
+            params: ParameterSets::default(),
+            initialized: false,
+            sequence: 0,
+            samples: vec![],
+            last_timestamp: None,
+            time: 0,
+        }
+    }
+
+    /// Bytes of the MP4 that are ready, frames before the first key frame are dropped.
+    pub fn push(&mut self, frame: &Frame) -> Vec<u8> {
+        let key = match frame.kind {
+            FrameKind::Video { key } => key,
+            _ => return vec![],
+        };
+        if let Some(codec) = frame.info.video_codec {
+            self.codec = Some(codec);
+        }
+        self.width = frame.info.width.unwrap_or(self.width);
+        self.height = frame.info.height.unwrap_or(self.height);
+        self.frame_rate = frame.info.frame_rate.or(self.frame_rate);
+        let codec = match self.codec {
+            Some(codec @ (VideoCodec::H264 | VideoCodec::H265)) => codec,
+            _ => return vec![],
+        };
+
+        // Timestamps are milliseconds that wrap around
+        if let Some(last) = self.last_timestamp {
+            self.time += frame.timestamp.wrapping_sub(last) as u64;
+        }
+        self.last_timestamp = Some(frame.timestamp);
+
+        let mut data = vec![];
+        for nal in split_nals(&frame.data) {
+            let param = match codec {
+                VideoCodec::H265 => match (nal[0] >> 1) & 0x3F {
+                    32 => Some(&mut self.params.vps),
+                    33 => Some(&mut self.params.sps),
+                    34 => Some(&mut self.params.pps),
+                    // Access unit delimiter
+                    35 => continue,
+                    _ => None,
+                },
+                _ => match nal[0] & 0x1F {
+                    7 => Some(&mut self.params.sps),
+                    8 => Some(&mut self.params.pps),
+                    9 => continue,
+                    _ => None,
+                },
+            };
+            match param {
+                // Parameter sets go into the init segment
+                Some(param) => {
+                    if param.is_none() {
+                        *param = Some(nal.to_vec());
+                    }
+                }
+                None => {
+                    data.extend((nal.len() as u32).to_be_bytes());
+                    data.extend(nal);
+                }
+            }
+        }
+
+        let mut out = vec![];
+        if !self.initialized {
+            if !key || !self.params.is_complete(codec) {
+                return out;
+            }
+            match self.init_segment(codec) {
+                Some(init) => out.extend(init),
+                None => return out,
+            }
+            self.initialized = true;
+        }
+        if key && !self.samples.is_empty() {
+            out.extend(self.fragment(self.time));
+        }
+        if !data.is_empty() {
+            self.samples.push(Sample {
+                data,
+                key,
+                time: self.time,
+            });
+        }
+
+        out
+    }
+
+    /// Bytes of the last fragment.
+    pub fn finish(&mut self) -> Vec<u8> {
+        if self.samples.is_empty() {
+            return vec![];
+        }
+
+        let duration = match self.frame_rate {
+            Some(rate) if rate > 0 => TIMESCALE / rate as u32,
+            _ => DEFAULT_DURATION,
+        };
+        let end = self.samples[self.samples.len() - 1].time + duration as u64;
+        self.fragment(end)
+    }
+
+    fn init_segment(&self, codec: VideoCodec) -> Option<Vec<u8>> {
+        let (sample_entry, config, brand) = match codec {
+            VideoCodec::H265 => (b"hvc1", self.params.hvcc()?, b"hvc1"),
+            _ => (b"avc1", self.params.avcc()?, b"avc1"),
+        };
+
+        let ftyp = mp4_box(
+            b"ftyp",
+            &Fields::default()
+                .bytes(b"isom")
+                .u32(0x200)
+                .bytes(b"isom")
+                .bytes(b"iso6")
+                .bytes(brand)
+                .bytes(b"mp41")
+                .0,
+        );
+
+        let mvhd = full_box(
+            b"mvhd",
+            0,
+            0,
+            &Fields::default()
+                .u32(0) // creation_time
+                .u32(0) // modification_time
+                .u32(TIMESCALE)
+                .u32(0) // duration
+                .u32(0x0001_0000) // rate
+                .u16(0x0100) // volume
+                .zeros(10)
+                .matrix()
+                .zeros(24)
+                .u32(TRACK_ID + 1)
+                .0,
+        );
+        let tkhd = full_box(
+            b"tkhd",
+            0,
+            3,
+            &Fields::default()
+                .u32(0)
+                .u32(0)
+                .u32(TRACK_ID)
+                .u32(0)
+                .u32(0) // duration
+                .zeros(8)
+                .u16(0) // layer
+                .u16(0) // alternate_group
+                .u16(0) // volume
+                .u16(0)
+                .matrix()
+                .u32((self.width as u32) << 16)
+                .u32((self.height as u32) << 16)
+                .0,
+        );
+        let mdhd = full_box(
+            b"mdhd",
+            0,
+            0,
+            &Fields::default()
+                .u32(0)
+                .u32(0)
+                .u32(TIMESCALE)
+                .u32(0)
+                .u16(0x55C4) // und
+                .u16(0)
+                .0,
+        );
+        let hdlr = full_box(
+            b"hdlr",
+            0,
+            0,
+            &Fields::default()
+                .u32(0)
+                .bytes(b"vide")
+                .zeros(12)
+                .bytes(b"VideoHandler\0")
+                .0,
+        );
+        let vmhd = full_box(b"vmhd", 0, 1, &Fields::default().zeros(8).0);
+        let dinf = mp4_box(
+            b"dinf",
+            &full_box(
+                b"dref",
+                0,
+                0,
+                &Fields::default()
+                    .u32(1)
+                    .bytes(&full_box(b"url ", 0, 1, &[]))
+                    .0,
+            ),
+        );
+        let visual_sample_entry = mp4_box(
+            sample_entry,
+            &Fields::default()
+                .zeros(6)
+                .u16(1) // data_reference_index
+                .zeros(16)
+                .u16(self.width)
+                .u16(self.height)
+                .u32(0x0048_0000) // 72 dpi
+                .u32(0x0048_0000)
+                .u32(0)
+                .u16(1) // frame_count
+                .zeros(32) // compressorname
+                .u16(0x0018) // depth
+                .u16(0xFFFF)
+                .bytes(&config)
+                .0,
+        );
+        let stbl = mp4_box(
+            b"stbl",
+            &[
+                full_box(
+                    b"stsd",
+                    0,
+                    0,
+                    &Fields::default().u32(1).bytes(&visual_sample_entry).0,
+                ),
+                full_box(b"stts", 0, 0, &0u32.to_be_bytes()),
+                full_box(b"stsc", 0, 0, &0u32.to_be_bytes()),
+                full_box(b"stsz", 0, 0, &0u64.to_be_bytes()),
+                full_box(b"stco", 0, 0, &0u32.to_be_bytes()),
+            ]
+            .concat(),
+        );
+        let minf = mp4_box(b"minf", &[vmhd, dinf, stbl].concat());
+        let mdia = mp4_box(b"mdia", &[mdhd, hdlr, minf].concat());
+        let trak = mp4_box(b"trak", &[tkhd, mdia].concat());
+        let trex = full_box(
+            b"trex",
+            0,
+            0,
+            &Fields::default()
+                .u32(TRACK_ID)
+                .u32(1) // default_sample_description_index
+                .u32(0)
+                .u32(0)
+                .u32(0)
+                .0,
+        );
+        let mvex = mp4_box(b"mvex", &trex);
+        let moov = mp4_box(b"moov", &[mvhd, trak, mvex].concat());
+
+        Some([ftyp, moov].concat())
+    }
+
+    /// moof and mdat of the pending samples, end is the time after the last sample.
+    fn fragment(&mut self, end: u64) -> Vec<u8> {
+        let samples = std::mem::take(&mut self.samples);
+        self.sequence += 1;
+
+        let moof = |data_offset: u32| {
+            let mfhd = full_box(b"mfhd", 0, 0, &self.sequence.to_be_bytes());
+            // default-base-is-moof
+            let tfhd = full_box(b"tfhd", 0, 0x02_0000, &TRACK_ID.to_be_bytes());
+            let tfdt = full_box(b"tfdt", 1, 0, &samples[0].time.to_be_bytes());
+            let mut trun = Fields::default().u32(samples.len() as u32).u32(data_offset);
+            for (i, sample) in samples.iter().enumerate() {
+                let next = samples.get(i + 1).map_or(end, |next| next.time);
+                trun = trun
+                    .u32(next.saturating_sub(sample.time) as u32)
+                    .u32(sample.data.len() as u32)
+                    .u32(if sample.key {
+                        SAMPLE_FLAGS_KEY
+                    } else {
+                        SAMPLE_FLAGS_NON_KEY
+                    });
+            }
+            // data-offset, sample-duration, sample-size and sample-flags present
+            let trun = full_box(b"trun", 0, 0x00_0701, &trun.0);
+            let traf = mp4_box(b"traf", &[tfhd, tfdt, trun].concat());
+
+            mp4_box(b"moof", &[mfhd, traf].concat())
+        };
+        // Data starts after the moof and the mdat header
+        let size = moof(0).len();
+        let moof = moof(size as u32 + 8);
+
+        let data = samples.iter().flat_map(|s| s.data.iter().copied());
+        let mdat = mp4_box(b"mdat", &data.collect::<Vec<_>>());
+
+        [moof, mdat].concat()
+    }
+}
+
+/// DAV bytes in, MP4 bytes out.
+#[derive(Default)]
+pub struct DavToMp4 {
+    parser: Parser,
+    remuxer: Remuxer,
+}
+
+impl DavToMp4 {
+    pub fn new() -> DavToMp4 {
+        DavToMp4::default()
+    }
+
+    pub fn push(&mut self, data: &[u8]) -> Vec<u8> {
+        self.parser.push(data);
+        let mut out = vec![];
+        while let Some(frame) = self.parser.next_frame() {
+            out.extend(self.remuxer.push(&frame));
+        }
+        out
+    }
+
+    pub fn finish(&mut self) -> Vec<u8> {
+        self.remuxer.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dav::tests::frame;
+
+    use super::*;
+
+    /// Top level boxes as (name, content).
+    fn boxes(mut data: &[u8]) -> Vec<(String, &[u8])> {
+        let mut boxes = vec![];
+        while data.len() >= 8 {
+            let size = u32::from_be_bytes(data[..4].try_into().unwrap()) as usize;
+            boxes.push((
+                String::from_utf8_lossy(&data[4..8]).to_string(),
+                &data[8..size],
+            ));
+            data = &data[size..];
+        }
+        boxes
+    }
+
+    fn find<'a>(data: &'a [u8], path: &[&str]) -> Option<&'a [u8]> {
+        let (name, rest) = path.split_first()?;
+        let (_, content) = boxes(data).into_iter().find(|(n, _)| n == name)?;
+        if rest.is_empty() {
+            Some(content)
+        } else {
+            find(content, rest)
+        }
+    }
+
+    #[test]
+    fn it_split_nals() {
+        let data = [
+            0, 0, 0, 1, 0x67, 1, 2, 0, 0, 1, 0x68, 3, 0, 0, 0, 1, 0x65, 4, 0,
+        ];
+        assert_eq!(
+            split_nals(&data),
+            vec![&[0x67, 1, 2][..], &[0x68, 3], &[0x65, 4, 0]]
+        );
+        assert_eq!(rbsp(&[1, 0, 0, 3, 1, 0, 0, 3]), vec![1, 0, 0, 1, 0, 0]);
+    }
+
+    #[test]
+    fn it_remux_h264() {
+        let ext = [0x80, 0, 80, 45, 0x81, 0, 0x02, 25];
+        let key = |seq, ts| {
+            frame(
+                0xFD,
+                seq,
+                ts,
+                &ext,
+                &[
+                    0, 0, 0, 1, 0x67, 0x64, 0x00, 0x1F, 0xAC, // SPS
+                    0, 0, 0, 1, 0x68, 0xEE, 0x3C, 0x80, // PPS
+                    0, 0, 0, 1, 0x65, 0x88, 0x84, // IDR
+                ],
+            )
+        };
+        let p = |seq, ts| frame(0xFC, seq, ts, &[], &[0, 0, 0, 1, 0x41, 0x9A, 0x02]);
+
+        let mut data = vec![];
+        // Frames before the first key frame cannot be decoded
+        data.extend(p(0, 65500));
+        data.extend(key(1, 65520));
+        data.extend(p(2, 24)); // timestamp wrapped
+        data.extend(frame(0xF0, 3, 30, &[0x83, 1, 0x0E, 0], &[0xD5; 4]));
+        data.extend(p(4, 64));
+        data.extend(key(5, 104));
+        data.extend(p(6, 144));
+
+        let mut remux = DavToMp4::new();
+        let mut out = remux.push(&data);
+        out.extend(remux.finish());
+
+        let names: Vec<_> = boxes(&out).into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["ftyp", "moov", "moof", "mdat", "moof", "mdat"]);
+
+        let stsd = find(&out, &["moov", "trak", "mdia", "minf", "stbl", "stsd"]).unwrap();
+        // Sample entry starts after the version, flags and entry count
+        let avc1 = &stsd[8..];
+        assert_eq!(&avc1[4..8], b"avc1");
+        assert_eq!(u16::from_be_bytes([avc1[32], avc1[33]]), 640);
+        assert_eq!(u16::from_be_bytes([avc1[34], avc1[35]]), 360);
+
+        // First GOP has the key frame and two P frames 40ms apart
+        let trun = find(&out, &["moof", "traf", "trun"]).unwrap();
+        assert_eq!(u32::from_be_bytes(trun[4..8].try_into().unwrap()), 3);
+        let durations: Vec<_> = (0..3)
+            .map(|i| u32::from_be_bytes(trun[12 + i * 12..16 + i * 12].try_into().unwrap()))
+            .collect();
+        assert_eq!(durations, [40, 40, 40]);
+        let first_flags = u32::from_be_bytes(trun[20..24].try_into().unwrap());
+        assert_eq!(first_flags, SAMPLE_FLAGS_KEY);
+
+        // Parameter sets are left out of the samples
+        let mdat = boxes(&out)[3].1;
+        assert_eq!(&mdat[..7], &[0, 0, 0, 3, 0x65, 0x88, 0x84]);
+    }
+
+    /// Writes bits for building a SPS by hand.
+    #[derive(Default)]
+    struct BitWriter {
+        bits: Vec<u8>,
+    }
+
+    impl BitWriter {
+        fn bits(&mut self, value: u32, count: usize) {
+            for i in (0..count).rev() {
+                self.bits.push(((value >> i) & 1) as u8);
+            }
+        }
+
+        fn ue(&mut self, value: u32) {
+            let len = 32 - (value + 1).leading_zeros() as usize;
+            self.bits(0, len - 1);
+            self.bits(value + 1, len);
+        }
+
+        fn bytes(mut self) -> Vec<u8> {
+            // rbsp_stop_one_bit and alignment
+            self.bits.push(1);
+            while !self.bits.len().is_multiple_of(8) {
+                self.bits.push(0);
+            }
+            self.bits
+                .chunks(8)
+                .map(|byte| byte.iter().fold(0, |acc, bit| (acc << 1) | bit))
+                .collect()
+        }
+    }
+
+    /// Adds emulation prevention bytes.
+    fn escape(rbsp: &[u8]) -> Vec<u8> {
+        let mut nal = vec![];
+        let mut zeros = 0;
+        for &b in rbsp {
+            if zeros >= 2 && b <= 3 {
+                nal.push(3);
+                zeros = 0;
+            }
+            zeros = if b == 0 { zeros + 1 } else { 0 };
+            nal.push(b);
+        }
+        nal
+    }
+
+    #[test]
+    fn it_hevc_sps() {
+        let ptl = [0x01, 0x60, 0, 0, 0, 0x90, 0, 0, 0, 0, 0, 0x5D];
+        let mut w = BitWriter::default();
+        w.bits(0x4201, 16); // NAL header
+        w.bits(0, 4); // sps_video_parameter_set_id
+        w.bits(0, 3); // sps_max_sub_layers_minus1
+        w.bits(1, 1); // sps_temporal_id_nesting_flag
+        for b in ptl {
+            w.bits(b as u32, 8);
+        }
+        w.ue(0); // sps_seq_parameter_set_id
+        w.ue(1); // chroma_format_idc
+        w.ue(1920);
+        w.ue(1080);
+        w.bits(0, 1); // conformance_window_flag
+        w.ue(2); // bit_depth_luma_minus8
+        w.ue(2); // bit_depth_chroma_minus8
+        let sps = escape(&w.bytes());
+
+        let info = HevcSps::parse(&sps).unwrap();
+        assert_eq!(info.profile_tier_level, ptl);
+        assert_eq!(info.max_sub_layers, 1);
+        assert!(info.temporal_id_nesting);
+        assert_eq!(info.chroma_format_idc, 1);
+        assert_eq!(info.bit_depth_luma_minus8, 2);
+        assert_eq!(info.bit_depth_chroma_minus8, 2);
+
+        let params = ParameterSets {
+            vps: Some(vec![0x40, 0x01, 0x0C]),
+            sps: Some(sps),
+            pps: Some(vec![0x44, 0x01, 0xC1]),
+        };
+        let hvcc = params.hvcc().unwrap();
+        assert_eq!(&hvcc[4..8], b"hvcC");
+        assert_eq!(&hvcc[9..21], &ptl);
+        // Three arrays with VPS, SPS and PPS
+        assert_eq!(hvcc[30], 3);
+        assert_eq!(hvcc[31], 0x80 | 32);
+    }
+}
@@ -0,0 +1,312 @@
+//! LAN device discovery with the DHDiscover protocol that ConfigTool uses.
+//!
+//! A probe is sent to the multicast group and the broadcast address, every device answers with
+//! its network settings. Packets are a 32 byte `DHIP` header followed by JSON.
+
+use std::{
+    collections::HashSet,
+    io,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    time::Duration,
+};
+
+use serde::Deserialize;
+use serde_json::json;
+use tokio::{net::UdpSocket, time::Instant};
+
+pub const MULTICAST_ADDR: SocketAddrV4 =
+    SocketAddrV4::new(Ipv4Addr::new(239, 255, 255, 251), 37810);
+pub const BROADCAST_ADDR: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::BROADCAST, 5050);
+
+const MAGIC: &[u8; 4] = b"DHIP";
+const HEADER_LEN: usize = 32;
+const MAX_PACKET_LEN: usize = 64 * 1024;
+
+/// Packs a JSON body into a DHIP packet.
+pub fn packet(body: &[u8]) -> Vec<u8> {
+    let len = (body.len() as u32).to_le_bytes();
+
+    let mut buf = Vec::with_capacity(HEADER_LEN + body.len());
+    buf.extend((HEADER_LEN as u32).to_le_bytes());
+    buf.extend(MAGIC);
+    buf.extend([0; 8]);
+    buf.extend(len);
+    buf.extend([0; 4]);
+    buf.extend(len);
+    buf.extend([0; 4]);
+    buf.extend(body);
+    buf
+}
+
+/// JSON body of a DHIP packet.
+pub fn packet_body(buf: &[u8]) -> Option<&[u8]> {
+    if buf.len() < HEADER_LEN || &buf[4..8] != MAGIC {
+        return None;
+    }
+    let len = u32::from_le_bytes([buf[16], buf[17], buf[18], buf[19]]) as usize;
+
+    // Some firmwares pad the body with NULs
+    let body = buf[HEADER_LEN..].get(..len).unwrap_or(&buf[HEADER_LEN..]);
+    let end = body.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+
+    Some(&body[..end])
+}
+
+pub fn probe() -> Vec<u8> {
+    packet(
+        json!({ "method": "DHDiscover.search", "params": { "mac": "", "uni": 1 } })
+            .to_string()
+            .as_bytes(),
+    )
+}
+
+#[derive(Deserialize, Debug)]
+struct Reply {
+    params: ReplyParams,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ReplyParams {
+    device_info: DeviceInfo,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(rename_all = "PascalCase", default)]
+struct DeviceInfo {
+    #[serde(rename = "IPv4Address")]
+    ipv4_address: Ipv4Address,
+    mac: String,
+    serial_no: String,
+    device_type: String,
+    device_class: String,
+    http_port: u16,
+    version: String,
+    vendor: String,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(rename_all = "PascalCase", default)]
+struct Ipv4Address {
+    #[serde(rename = "IPAddress")]
+    ip_address: String,
+}
+
+/// Device that answered a probe.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiscoveredDevice {
+    pub ip: String,
+    pub mac: String,
+    pub serial: String,
+    pub device_type: String,
+    pub device_class: String,
+    pub http_port: u16,
+    /// Firmware version.
+    pub version: String,
+    pub vendor: String,
+}
+
+impl DiscoveredDevice {
+    /// Parses a reply packet, from is used when the device does not report its IP.
+    pub fn parse(buf: &[u8], from: SocketAddr) -> Option<DiscoveredDevice> {
+        let reply: Reply = serde_json::from_slice(packet_body(buf)?).ok()?;
+        let info = reply.params.device_info;
+
+        Some(DiscoveredDevice {
+            ip: if info.ipv4_address.ip_address.is_empty() {
+                from.ip().to_string()
+            } else {
+                info.ipv4_address.ip_address
+            },
+            mac: info.mac.to_lowercase(),
+            serial: info.serial_no,
+            device_type: info.device_type,
+            device_class: info.device_class,
+            http_port: if info.http_port == 0 {
+                80
+            } else {
+                info.http_port
+            },
+            version: info.version,
+            vendor: info.vendor,
+        })
+    }
+
+    fn key(&self) -> &str {
+        if self.mac.is_empty() {
+            &self.ip
+        } else {
+            &self.mac
+        }
+    }
+}
+
+/// Sends probes and collects replies until the timeout.
+#[derive(Clone, Debug)]
+pub struct Discover {
+    targets: Vec<SocketAddr>,
+    timeout: Duration,
+}
+
+impl Default for Discover {
+    fn default() -> Self {
+        Discover {
+            targets: vec![MULTICAST_ADDR.into(), BROADCAST_ADDR.into()],
+            timeout: Duration::from_secs(3),
+        }
+    }
+}
+
+impl Discover {
+    pub fn new() -> Discover {
+        Discover::default()
+    }
+
+    /// Where probes are sent, defaults to the DHDiscover multicast group and broadcast address.
+    pub fn targets(mut self, targets: Vec<SocketAddr>) -> Self {
+        self.targets = targets;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub async fn run(&self) -> io::Result<Vec<DiscoveredDevice>> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+        socket.set_broadcast(true)?;
+
+        // Some devices answer to the multicast group instead of the sender
+        let group = if self.targets.contains(&MULTICAST_ADDR.into()) {
+            join_multicast().await.ok()
+        } else {
+            None
+        };
+
+        let probe = probe();
+        let mut sent = false;
+        let mut last_err = None;
+        for target in &self.targets {
+            match socket.send_to(&probe, target).await {
+                Ok(_) => sent = true,
+                Err(err) => last_err = Some(err),
+            }
+        }
+        if let (false, Some(err)) = (sent, last_err) {
+            return Err(err);
+        }
+
+        let deadline = Instant::now() + self.timeout;
+        let mut seen = HashSet::new();
+        let mut devices = Vec::new();
+        let mut buf = vec![0; MAX_PACKET_LEN];
+        let mut group_buf = vec![0; MAX_PACKET_LEN];
+        loop {
+            let reply = async {
+                match &group {
+                    Some(group) => tokio::select! {
+                        res = recv(&socket, &mut buf) => res,
+                        res = recv(group, &mut group_buf) => res,
+                    },
+                    None => recv(&socket, &mut buf).await,
+                }
+            };
+            let (packet, from) = match tokio::time::timeout_at(deadline, reply).await {
+                Ok(Ok(res)) => res,
+                // ICMP port unreachable from earlier probes shows up here
+                Ok(Err(err)) if err.kind() == io::ErrorKind::ConnectionRefused => continue,
+                Ok(Err(err)) => return Err(err),
+                Err(_) => break,
+            };
+
+            // Our own probe comes back on the multicast group
+            let Some(device) = DiscoveredDevice::parse(packet, from) else {
+                continue;
+            };
+            if seen.insert(device.key().to_string()) {
+                devices.push(device);
+            }
+        }
+
+        Ok(devices)
+    }
+}
+
+async fn recv<'a>(socket: &UdpSocket, buf: &'a mut [u8]) -> io::Result<(&'a [u8], SocketAddr)> {
+    let (len, from) = socket.recv_from(buf).await?;
+    Ok((&buf[..len], from))
+}
+
+async fn join_multicast() -> io::Result<UdpSocket> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, MULTICAST_ADDR.port())).await?;
+    socket.join_multicast_v4(*MULTICAST_ADDR.ip(), Ipv4Addr::UNSPECIFIED)?;
+    Ok(socket)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_packet() {
+        let probe = probe();
+        assert_eq!(&probe[..8], b"\x20\x00\x00\x00DHIP");
+        let body: serde_json::Value = serde_json::from_slice(packet_body(&probe).unwrap()).unwrap();
+        assert_eq!(body["method"], "DHDiscover.search");
+
+        let mut padded = packet(b"{}\0\0");
+        padded.extend([0, 0]);
+        assert_eq!(packet_body(&padded), Some(&b"{}"[..]));
+
+        assert_eq!(packet_body(b"not a packet"), None);
+    }
+
+    #[test]
+    fn it_parse_reply() {
+        let reply = packet(
+            json!({
+                "mac": "3c:ef:8c:00:00:01",
+                "method": "client.notifyDevInfo",
+                "params": { "deviceInfo": {
+                    "DeviceClass": "IPC",
+                    "DeviceType": "IPC-HDW1230S",
+                    "HttpPort": 8080,
+                    "IPv4Address": {
+                        "DefaultGateway": "192.168.1.1",
+                        "DhcpEnable": true,
+                        "IPAddress": "192.168.1.108",
+                        "SubnetMask": "255.255.255.0"
+                    },
+                    "Mac": "3C:EF:8C:00:00:01",
+                    "SerialNo": "5F0000000000001",
+                    "Vendor": "Dahua",
+                    "Version": "2.800.0000000.16.R"
+                }}
+            })
+            .to_string()
+            .as_bytes(),
+        );
+
+        let device = DiscoveredDevice::parse(&reply, "10.0.0.1:5050".parse().unwrap()).unwrap();
+        assert_eq!(
+            device,
+            DiscoveredDevice {
+                ip: "192.168.1.108".to_string(),
+                mac: "3c:ef:8c:00:00:01".to_string(),
+                serial: "5F0000000000001".to_string(),
+                device_type: "IPC-HDW1230S".to_string(),
+                device_class: "IPC".to_string(),
+                http_port: 8080,
+                version: "2.800.0000000.16.R".to_string(),
+                vendor: "Dahua".to_string(),
+            }
+        );
+
+        // The probe itself is not a reply
+        assert_eq!(
+            DiscoveredDevice::parse(&probe(), "10.0.0.1:5050".parse().unwrap()),
+            None
+        );
+    }
+}
@@ -0,0 +1,190 @@
+use std::{fmt, str::FromStr};
+
+use crate::Error;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Scheme {
+    #[default]
+    Http,
+    Https,
+}
+
+impl Scheme {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scheme::Http => "http",
+            Scheme::Https => "https",
+        }
+    }
+
+    pub fn default_port(&self) -> u16 {
+        match self {
+            Scheme::Http => 80,
+            Scheme::Https => 443,
+        }
+    }
+}
+
+impl FromStr for Scheme {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "http" => Ok(Scheme::Http),
+            "https" => Ok(Scheme::Https),
+            _ => Err(Error::Parse(format!("invalid scheme '{s}'"))),
+        }
+    }
+}
+
+/// Where a camera can be reached.
+///
+/// Cameras with self-signed certificates need accept_invalid_certs and a reqwest client built with
+/// `danger_accept_invalid_certs(true)`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Endpoint {
+    pub scheme: Scheme,
+    pub host: String,
+    pub port: Option<u16>,
+    pub accept_invalid_certs: bool,
+}
+
+impl Endpoint {
+    pub fn new(host: String) -> Endpoint {
+        Endpoint {
+            scheme: Scheme::Http,
+            host,
+            port: None,
+            accept_invalid_certs: false,
+        }
+    }
+
+    pub fn scheme(mut self, scheme: Scheme) -> Endpoint {
+        self.scheme = scheme;
+        self
+    }
+
+    pub fn port(mut self, port: Option<u16>) -> Endpoint {
+        self.port = port;
+        self
+    }
+
+    pub fn accept_invalid_certs(mut self, accept_invalid_certs: bool) -> Endpoint {
+        self.accept_invalid_certs = accept_invalid_certs;
+        self
+    }
+
+    /// Host with the port when it is not the default one for the scheme.
+    pub fn authority(&self) -> String {
+        match self.port {
+            Some(port) if port != self.scheme.default_port() => format!("{}:{}", self.host, port),
+            _ => self.host.clone(),
+        }
+    }
+
+    /// Absolute URL of path on the camera, path has to start with a '/'.
+    pub fn url(&self, path: &str) -> String {
+        format!("{}://{}{}", self.scheme.as_str(), self.authority(), path)
+    }
+}
+
+/// Splits `host:port` while leaving IPv6 addresses such as `[::1]` alone.
+pub fn split_host_port(address: &str) -> Result<(&str, Option<u16>), Error> {
+    let port_start = match address.rfind(':') {
+        Some(idx) if !address[idx..].contains(']') => idx,
+        _ => return Ok((address, None)),
+    };
+    if address[..port_start].contains(':') && !address.starts_with('[') {
+        // Bare IPv6 address
+        return Ok((address, None));
+    }
+
+    let port = address[port_start + 1..]
+        .parse()
+        .map_err(|_| Error::Parse(format!("invalid port in '{address}'")))?;
+
+    Ok((&address[..port_start], Some(port)))
+}
+
+impl FromStr for Endpoint {
+    type Err = Error;
+
+    /// Parses `host`, `host:port` or `scheme://host[:port]`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scheme, address) = match s.split_once("://") {
+            Some((scheme, address)) => (scheme.parse()?, address),
+            None => (Scheme::Http, s),
+        };
+        let address = address.trim_end_matches('/');
+        if address.is_empty() || address.contains(['/', '@', ' ']) {
+            return Err(Error::Parse(format!("invalid endpoint '{s}'")));
+        }
+        let (host, port) = split_host_port(address)?;
+
+        Ok(Endpoint::new(host.to_string()).scheme(scheme).port(port))
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.url(""))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parse_endpoint() {
+        let data = [
+            ("192.168.1.108", Scheme::Http, "192.168.1.108", None),
+            (
+                "192.168.1.108:8080",
+                Scheme::Http,
+                "192.168.1.108",
+                Some(8080),
+            ),
+            ("https://cam.local", Scheme::Https, "cam.local", None),
+            (
+                "HTTPS://cam.local:8443/",
+                Scheme::Https,
+                "cam.local",
+                Some(8443),
+            ),
+            ("[::1]:8080", Scheme::Http, "[::1]", Some(8080)),
+            ("[::1]", Scheme::Http, "[::1]", None),
+        ];
+
+        for (input, scheme, host, port) in data {
+            let endpoint: Endpoint = input.parse().unwrap();
+            assert_eq!(endpoint.scheme, scheme, "{input}");
+            assert_eq!(endpoint.host, host, "{input}");
+            assert_eq!(endpoint.port, port, "{input}");
+        }
+
+        for input in [
+            "",
+            "ftp://cam",
+            "cam:port",
+            "cam/path",
+            "user@cam",
+            "cam:99999",
+        ] {
+            assert!(input.parse::<Endpoint>().is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn it_endpoint_url() {
+        let endpoint = Endpoint::new("cam.local".to_string());
+        assert_eq!(endpoint.url("/RPC2"), "http://cam.local/RPC2");
+
+        let endpoint = endpoint.scheme(Scheme::Https).port(Some(443));
+        assert_eq!(endpoint.url("/RPC2"), "https://cam.local/RPC2");
+
+        let endpoint = endpoint.port(Some(8443));
+        assert_eq!(endpoint.url("/RPC2"), "https://cam.local:8443/RPC2");
+        assert_eq!(endpoint.to_string(), "https://cam.local:8443");
+    }
+}
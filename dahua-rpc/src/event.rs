@@ -0,0 +1,316 @@
+use crate::{
+    modules::eventmanager::{self, Event, Notify, NotifyEventStream},
+    transport::{Chunks, Stream},
+    Client, Error, State,
+};
+
+const DEFAULT_BOUNDARY: &str = "myboundary";
+const NOTIFY_EVENT_STREAM: &str = "client.notifyEventStream";
+
+impl Client {
+    pub fn event_url(&self) -> String {
+        self.endpoint.url(&format!(
+            "/SubscribeNotify.cgi?sessionId={}",
+            self.connection().session
+        ))
+    }
+
+    /// Attaches to the given event codes and opens the multipart notify stream.
+    /// The returned stream does not borrow the client, it should be closed to detach again.
+    pub async fn event_stream(&self, codes: &[&str]) -> Result<EventStream, Error> {
+        let sid = eventmanager::attach(self.rpc().await?, codes).await?;
+
+        let res = self
+            .transport
+            .stream(&self.event_url(), &self.cookie_raw())
+            .await?;
+
+        Ok(EventStream::new(
+            sid,
+            res,
+            self.clone(),
+            codes.iter().map(|code| code.to_string()).collect(),
+        ))
+    }
+}
+
+pub struct EventStream {
+    pub sid: i64,
+    body: Box<dyn Chunks>,
+    client: Client,
+    codes: Vec<String>,
+    parser: EventParser,
+    closed: bool,
+}
+
+impl EventStream {
+    fn new(sid: i64, res: Stream, client: Client, codes: Vec<String>) -> EventStream {
+        let boundary = res
+            .content_type
+            .as_deref()
+            .and_then(parse_boundary)
+            .unwrap_or(DEFAULT_BOUNDARY)
+            .to_string();
+
+        EventStream {
+            sid,
+            body: res.body,
+            client,
+            codes,
+            parser: EventParser::new(boundary),
+            closed: false,
+        }
+    }
+
+    /// Returns the next batch of events or None when the camera closes the stream.
+    pub async fn next(&mut self) -> Option<Result<Vec<Event>, Error>> {
+        loop {
+            match self.parser.next() {
+                Some(Ok(events)) if events.is_empty() => continue,
+                Some(res) => return Some(res),
+                None => {}
+            }
+
+            if self.closed {
+                return None;
+            }
+
+            match self.body.chunk().await {
+                Ok(Some(chunk)) => self.parser.push(&chunk),
+                Ok(None) => {
+                    self.closed = true;
+                    return None;
+                }
+                Err(err) => {
+                    self.closed = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+
+    /// Detaches from the event codes, the device keeps the subscription for the session otherwise.
+    ///
+    /// Nothing is sent when the session is gone since the subscription went with it.
+    pub async fn close(self) -> Result<(), Error> {
+        drop(self.body);
+        if !matches!(self.client.state(), State::Login(_)) {
+            return Ok(());
+        }
+
+        let codes: Vec<&str> = self.codes.iter().map(String::as_str).collect();
+        eventmanager::detach(self.client.rpc_raw(), &codes)
+            .await
+            .map(|_| ())
+    }
+}
+
+fn parse_boundary(content_type: &str) -> Option<&str> {
+    content_type
+        .split(';')
+        .filter_map(|param| param.trim().strip_prefix("boundary="))
+        .map(|boundary| boundary.trim_matches('"'))
+        .next()
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn content_length(headers: &str) -> Option<usize> {
+    headers.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        if key.trim().eq_ignore_ascii_case("content-length") {
+            value.trim().parse().ok()
+        } else {
+            None
+        }
+    })
+}
+
+/// Incremental parser for the multipart body of SubscribeNotify.cgi.
+struct EventParser {
+    delimiter: Vec<u8>,
+    buf: Vec<u8>,
+}
+
+impl EventParser {
+    fn new(boundary: String) -> EventParser {
+        EventParser {
+            delimiter: format!("--{boundary}").into_bytes(),
+            buf: vec![],
+        }
+    }
+
+    fn push(&mut self, chunk: &[u8]) {
+        self.buf.extend_from_slice(chunk);
+    }
+
+    /// Returns the body of the next complete part.
+    fn next_part(&mut self) -> Option<Vec<u8>> {
+        let headers_start = find(&self.buf, &self.delimiter)? + self.delimiter.len();
+        let headers_end = find(&self.buf[headers_start..], b"\r\n\r\n")? + headers_start;
+        let headers = String::from_utf8_lossy(&self.buf[headers_start..headers_end]);
+
+        let body_start = headers_end + 4;
+        let body_end = match content_length(&headers) {
+            Some(length) if self.buf.len() >= body_start + length => body_start + length,
+            Some(_) => return None,
+            None => find(&self.buf[body_start..], &self.delimiter)? + body_start,
+        };
+
+        let body = self.buf[body_start..body_end].to_vec();
+        self.buf.drain(..body_end);
+
+        Some(body)
+    }
+
+    fn next(&mut self) -> Option<Result<Vec<Event>, Error>> {
+        let body = self.next_part()?;
+        let body = body.trim_ascii();
+
+        // Heartbeats and other non JSON parts are ignored
+        if !body.starts_with(b"{") {
+            return Some(Ok(vec![]));
+        }
+
+        let notify = match serde_json::from_slice::<Notify>(body) {
+            Ok(o) => o,
+            Err(err) => return Some(Err(Error::Parse(err.to_string()))),
+        };
+        if notify.method != NOTIFY_EVENT_STREAM {
+            return Some(Ok(vec![]));
+        }
+
+        Some(
+            serde_json::from_value::<NotifyEventStream>(notify.params.unwrap_or_default())
+                .map(|p| p.event_list)
+                .map_err(|e| Error::Parse(e.to_string())),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use serde_json::json;
+
+    use crate::{modules::eventmanager::EventAction, transport::MemoryTransport, Endpoint};
+
+    use super::*;
+
+    fn part(body: &str) -> String {
+        format!(
+            "--myboundary\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\r\n{}\r\n",
+            body.len(),
+            body
+        )
+    }
+
+    #[test]
+    fn it_parse_boundary() {
+        assert_eq!(
+            parse_boundary("multipart/x-mixed-replace; boundary=myboundary"),
+            Some("myboundary")
+        );
+        assert_eq!(
+            parse_boundary("multipart/x-mixed-replace;boundary=\"abc\""),
+            Some("abc")
+        );
+        assert_eq!(parse_boundary("text/plain"), None);
+    }
+
+    #[test]
+    fn it_event_parser() {
+        let body = part(
+            r#"{"id":2,"method":"client.notifyEventStream","params":{"SID":513,"eventList":[{"Action":"Start","Code":"VideoMotion","Data":{"RegionName":["Region1"]},"Index":0},{"Action":"Pulse","Code":"NTPAdjustTime","Index":0}]},"session":"abc"}"#,
+        ) + &part("Heartbeat")
+            + &part(
+                r#"{"id":3,"method":"client.notifyEventStream","params":{"SID":513,"eventList":[{"Action":"Stop","Code":"VideoMotion","Index":1}]},"session":"abc"}"#,
+            );
+
+        // Feed the parser in small chunks to simulate a slow stream
+        let mut parser = EventParser::new(DEFAULT_BOUNDARY.to_string());
+        let mut batches = vec![];
+        for chunk in body.as_bytes().chunks(7) {
+            parser.push(chunk);
+            while let Some(res) = parser.next() {
+                let events = res.unwrap();
+                if !events.is_empty() {
+                    batches.push(events);
+                }
+            }
+        }
+
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].len(), 2);
+        assert_eq!(batches[0][0].code, "VideoMotion");
+        assert_eq!(batches[0][0].action, EventAction::Start);
+        assert_eq!(batches[0][0].data["RegionName"][0], "Region1");
+        assert_eq!(batches[0][1].action, EventAction::Pulse);
+        assert_eq!(batches[1][0].action, EventAction::Stop);
+        assert_eq!(batches[1][0].index, 1);
+    }
+
+    #[tokio::test]
+    async fn it_event_stream() {
+        let calls = Arc::new(Mutex::new(vec![]));
+        let transport = MemoryTransport::new({
+            let calls = calls.clone();
+            move |url, req| {
+                calls.lock().unwrap().push(req.method.to_string());
+                let res = match (url.ends_with("/RPC2_Login"), req.method) {
+                    (true, "global.login") if req.session.is_empty() => json!({
+                        "error": { "code": 268632079, "message": "Component error: login challenge!" },
+                        "params": { "encryption": "Default", "random": "1172275829", "realm": "Login to test" },
+                        "result": false,
+                        "session": "abc",
+                    }),
+                    (true, "global.login") => json!({ "result": true, "session": "abc" }),
+                    (false, "eventManager.attach") => {
+                        json!({ "params": { "SID": 513 }, "result": true, "session": "abc" })
+                    }
+                    (false, "eventManager.detach") => json!({ "result": true, "session": "abc" }),
+                    _ => {
+                        return Err(Error::Request(
+                            format!("unexpected {url} {}", req.method).into(),
+                        ))
+                    }
+                };
+
+                Ok(res)
+            }
+        })
+        .stream(|url| {
+            assert!(url.ends_with("/SubscribeNotify.cgi?sessionId=abc"));
+            let body = part(
+                r#"{"id":2,"method":"client.notifyEventStream","params":{"SID":513,"eventList":[{"Action":"Start","Code":"VideoMotion","Index":0}]},"session":"abc"}"#,
+            ) + &part("Heartbeat");
+            Ok(body.as_bytes().chunks(16).map(<[u8]>::to_vec).collect())
+        });
+        let client = Client::new(
+            reqwest::Client::new(),
+            Endpoint::new("camera".to_string()),
+            "admin".to_string(),
+            "123".to_string(),
+        )
+        .transport(Arc::new(transport));
+
+        let mut stream = client.event_stream(&["VideoMotion"]).await.unwrap();
+        assert_eq!(stream.sid, 513);
+        let events = stream.next().await.unwrap().unwrap();
+        assert_eq!(events[0].code, "VideoMotion");
+        assert_eq!(events[0].action, EventAction::Start);
+        assert!(stream.next().await.is_none());
+
+        stream.close().await.unwrap();
+        assert!(calls
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|method| method == "eventManager.detach"));
+    }
+}
@@ -1,7 +1,118 @@
-use crate::Client;
+use std::str::FromStr;
+
+use crate::{Client, Error};
 
 impl Client {
     pub fn file_url(&self, file_path: &str) -> String {
-        format!("http://{}/RPC_Loadfile{}", self.ip, file_path)
+        self.endpoint.url(&format!("/RPC_Loadfile{}", file_path))
+    }
+}
+
+/// Single range of a `Range: bytes=...` header, RPC_Loadfile is only ever asked for one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ByteRange {
+    /// `bytes=start-` or `bytes=start-end` where end is inclusive.
+    From(u64, Option<u64>),
+    /// `bytes=-len` for the last len bytes.
+    Suffix(u64),
+}
+
+impl ByteRange {
+    /// Inclusive start and end in a file of len bytes, None when the range cannot be satisfied.
+    pub fn resolve(&self, len: u64) -> Option<(u64, u64)> {
+        match *self {
+            ByteRange::From(start, _) if start >= len => None,
+            ByteRange::From(start, end) => {
+                Some((start, end.map_or(len - 1, |end| end.min(len - 1))))
+            }
+            ByteRange::Suffix(0) => None,
+            ByteRange::Suffix(_) if len == 0 => None,
+            ByteRange::Suffix(suffix) => Some((len.saturating_sub(suffix), len - 1)),
+        }
+    }
+}
+
+impl FromStr for ByteRange {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::Parse(format!("invalid range '{s}'"));
+        let (start, end) = s
+            .trim()
+            .strip_prefix("bytes=")
+            .and_then(|range| range.split_once('-'))
+            .ok_or_else(invalid)?;
+        let (start, end) = (start.trim(), end.trim());
+        if end.contains(',') {
+            return Err(invalid());
+        }
+
+        match (start.is_empty(), end.is_empty()) {
+            (true, false) => end.parse().map(ByteRange::Suffix).map_err(|_| invalid()),
+            (false, _) => {
+                let start = start.parse().map_err(|_| invalid())?;
+                let end = match end {
+                    "" => None,
+                    end => Some(end.parse().map_err(|_| invalid())?),
+                };
+                match end {
+                    Some(end) if end < start => Err(invalid()),
+                    end => Ok(ByteRange::From(start, end)),
+                }
+            }
+            (true, true) => Err(invalid()),
+        }
+    }
+}
+
+/// Value of the Content-Range header of a 206 response.
+pub fn content_range(start: u64, end: u64, len: u64) -> String {
+    format!("bytes {start}-{end}/{len}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parse_range() {
+        assert_eq!(
+            "bytes=0-499".parse::<ByteRange>().unwrap(),
+            ByteRange::From(0, Some(499))
+        );
+        assert_eq!(
+            "bytes=500-".parse::<ByteRange>().unwrap(),
+            ByteRange::From(500, None)
+        );
+        assert_eq!(
+            "bytes=-500".parse::<ByteRange>().unwrap(),
+            ByteRange::Suffix(500)
+        );
+
+        for range in [
+            "",
+            "bytes=",
+            "bytes=-",
+            "bytes=5-1",
+            "bytes=0-1,5-6",
+            "items=0-1",
+        ] {
+            assert!(range.parse::<ByteRange>().is_err(), "{range}");
+        }
+    }
+
+    #[test]
+    fn it_resolve_range() {
+        assert_eq!(ByteRange::From(0, Some(499)).resolve(1000), Some((0, 499)));
+        assert_eq!(ByteRange::From(500, None).resolve(1000), Some((500, 999)));
+        assert_eq!(
+            ByteRange::From(900, Some(2000)).resolve(1000),
+            Some((900, 999))
+        );
+        assert_eq!(ByteRange::From(1000, None).resolve(1000), None);
+        assert_eq!(ByteRange::Suffix(100).resolve(1000), Some((900, 999)));
+        assert_eq!(ByteRange::Suffix(2000).resolve(1000), Some((0, 999)));
+        assert_eq!(ByteRange::Suffix(0).resolve(1000), None);
+        assert_eq!(content_range(0, 499, 1000), "bytes 0-499/1000");
     }
 }
@@ -0,0 +1,431 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    modules::magicbox,
+    multicall,
+    transport::{Transport, TransportFuture},
+    Client, Error, Request, RequestBuilder,
+};
+
+const REDACTED: &str = "REDACTED";
+
+/// Keys whose values identify a device or its owner.
+const SENSITIVE_KEYS: &[&str] = &[
+    "password",
+    "Password",
+    "userName",
+    "UserName",
+    "Username",
+    "username",
+    "random",
+    "realm",
+    "session",
+    "sn",
+    "SerialNo",
+    "SerialNumber",
+    "MachineName",
+    "MAC",
+    "PhysicalAddress",
+    "IPAddress",
+    "DefaultGateway",
+    "Receivers",
+    "SendAddress",
+];
+
+/// Request and response pair as stored on disk.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Fixture {
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+    pub response: Value,
+}
+
+/// Replaces every sensitive value with a placeholder of the same JSON type so it still deserializes.
+pub fn sanitize(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if SENSITIVE_KEYS.contains(&key.as_str()) {
+                    redact(value);
+                } else {
+                    sanitize(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(sanitize),
+        _ => {}
+    }
+}
+
+fn redact(value: &mut Value) {
+    match value {
+        Value::String(s) if !s.is_empty() => *s = REDACTED.to_string(),
+        Value::Number(_) => *value = Value::from(0),
+        Value::Array(values) => values.iter_mut().for_each(redact),
+        Value::Object(map) => map.values_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+/// File name of a fixture, config requests also include the table name and the channel.
+pub fn fixture_name(method: &str, params: &Value) -> String {
+    let mut name = method.to_string();
+    if let Some(table) = params.get("name").and_then(Value::as_str) {
+        name = format!("{name}-{table}");
+    }
+    if let Some(channel) = params.get("channel").and_then(Value::as_i64) {
+        name = format!("{name}-{channel}");
+    }
+    name + ".json"
+}
+
+fn path_segment(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' | '_' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// Saves every response as a fixture in `{dir}/{device type}/{firmware version}`.
+#[derive(Clone, Debug)]
+pub struct Recorder {
+    dir: PathBuf,
+}
+
+impl Recorder {
+    pub fn new(dir: impl AsRef<Path>, device_type: &str, version: &str) -> Recorder {
+        Recorder {
+            dir: dir
+                .as_ref()
+                .join(path_segment(device_type))
+                .join(path_segment(version)),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub(crate) fn record(&self, req: &Request, res: &Value) -> io::Result<()> {
+        if req.method != multicall::METHOD {
+            return self.record_one(req.method, &req.params, res);
+        }
+
+        // Every request of a multicall gets its own fixture
+        let calls = req.params.as_array().into_iter().flatten();
+        let responses = res["params"].as_array().into_iter().flatten();
+        for (call, res) in calls.zip(responses) {
+            let method = call["method"].as_str().unwrap_or_default();
+            self.record_one(method, &call["params"], res)?;
+        }
+
+        Ok(())
+    }
+
+    fn record_one(&self, method: &str, params: &Value, res: &Value) -> io::Result<()> {
+        let mut fixture = Fixture {
+            method: method.to_string(),
+            params: params.clone(),
+            response: res.clone(),
+        };
+        sanitize(&mut fixture.params);
+        sanitize(&mut fixture.response);
+
+        fs::create_dir_all(&self.dir)?;
+        fs::write(
+            self.dir.join(fixture_name(method, params)),
+            serde_json::to_vec_pretty(&fixture)?,
+        )
+    }
+}
+
+/// Answers requests from recorded fixtures instead of a device.
+#[derive(Clone, Debug, Default)]
+pub struct Replay {
+    fixtures: Arc<HashMap<String, Value>>,
+}
+
+impl Replay {
+    /// Loads every fixture in a directory created by a Recorder.
+    pub fn load(dir: impl AsRef<Path>) -> io::Result<Replay> {
+        let mut fixtures = HashMap::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+
+            let fixture: Fixture = serde_json::from_slice(&fs::read(&path)?)?;
+            fixtures.insert(
+                fixture_name(&fixture.method, &fixture.params),
+                fixture.response,
+            );
+        }
+
+        Ok(Replay {
+            fixtures: Arc::new(fixtures),
+        })
+    }
+
+    pub fn contains(&self, method: &str, params: &Value) -> bool {
+        self.fixtures.contains_key(&fixture_name(method, params))
+    }
+
+    pub fn rpc(&self) -> RequestBuilder {
+        RequestBuilder::new(0, "".to_string(), Arc::new(self.clone()), "".to_string())
+    }
+}
+
+impl Replay {
+    fn fixture(&self, method: &str, params: &Value) -> Result<Value, Error> {
+        self.fixtures
+            .get(&fixture_name(method, params))
+            .cloned()
+            .ok_or_else(|| Error::Request(format!("No fixture for {method}").into()))
+    }
+
+    /// Answers a multicall from the fixtures of its requests.
+    fn multicall(&self, req: &Request) -> Result<Value, Error> {
+        let mut responses = vec![];
+        for call in req.params.as_array().into_iter().flatten() {
+            let method = call["method"].as_str().unwrap_or_default();
+            let mut res = self.fixture(method, &call["params"])?;
+            res["id"] = call["id"].clone();
+            responses.push(res);
+        }
+
+        Ok(json!({
+            "id": req.id,
+            "params": responses,
+            "result": true,
+            "session": req.session,
+        }))
+    }
+}
+
+impl Transport for Replay {
+    fn send<'a>(&'a self, _: &'a str, req: &'a Request) -> TransportFuture<'a> {
+        let res = if req.method == multicall::METHOD {
+            self.multicall(req)
+        } else {
+            self.fixture(req.method, &req.params)
+        };
+        Box::pin(async move { res })
+    }
+}
+
+impl Client {
+    /// Starts recording fixtures for the device into dir.
+    pub async fn record(&mut self, dir: impl AsRef<Path>) -> Result<(), Error> {
+        let device_type = magicbox::get_device_type(self.rpc().await?).await?;
+        let version = magicbox::get_software_version(self.rpc().await?)
+            .await?
+            .version;
+        self.recorder = Some(Recorder::new(dir, &device_type, &version));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::modules::{
+        config, configmanager, global, license, magicbox, mediafilefind, ptz, storage,
+    };
+
+    use super::*;
+
+    #[test]
+    fn it_sanitize() {
+        let mut value = json!({
+            "params": {
+                "sn": "6J0B1234567890",
+                "table": [{ "Name": "Door", "MachineName": "Front", "Port": 25 }],
+                "Receivers": ["a@example.com", ""],
+                "random": 123
+            }
+        });
+        sanitize(&mut value);
+
+        assert_eq!(
+            value,
+            json!({
+                "params": {
+                    "sn": REDACTED,
+                    "table": [{ "Name": "Door", "MachineName": REDACTED, "Port": 25 }],
+                    "Receivers": [REDACTED, ""],
+                    "random": 0
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn it_fixture_name() {
+        assert_eq!(
+            fixture_name("magicBox.getSerialNo", &Value::Null),
+            "magicBox.getSerialNo.json"
+        );
+        assert_eq!(
+            fixture_name("configManager.getConfig", &json!({ "name": "General" })),
+            "configManager.getConfig-General.json"
+        );
+        assert_eq!(
+            fixture_name(
+                "configManager.getConfig",
+                &json!({ "name": "Encode", "channel": 1 })
+            ),
+            "configManager.getConfig-Encode-1.json"
+        );
+        assert_eq!(
+            Recorder::new("fixtures", "IPC-HDW/5442", "2.800.0000000.25.R").dir(),
+            Path::new("fixtures/IPC-HDW_5442/2.800.0000000.25.R")
+        );
+    }
+
+    #[tokio::test]
+    async fn it_replay_channels() {
+        let dir = std::env::temp_dir().join(format!("dahua-rpc-fixtures-{}", std::process::id()));
+        let recorder = Recorder::new(&dir, "NVR", "1.0");
+        for channel in [0, 1] {
+            let req = Request {
+                id: 1,
+                session: String::new(),
+                method: "configManager.getConfig",
+                params: json!({ "name": "ChannelTitle", "channel": channel }),
+                object: None,
+            };
+            let res = json!({
+                "id": 1,
+                "params": { "table": { "Name": format!("Channel {channel}") } },
+                "result": true,
+            });
+            recorder.record(&req, &res).unwrap();
+        }
+
+        let replay = Replay::load(recorder.dir()).unwrap();
+        fs::remove_dir_all(&dir).ok();
+        for channel in [0, 1] {
+            let table: Value = configmanager::GetConfigRequest::new("ChannelTitle", Some(channel))
+                .get(replay.rpc())
+                .await
+                .unwrap();
+            assert_eq!(table["Name"], format!("Channel {channel}"));
+        }
+    }
+
+    /// Runs every deserializer against the fixtures of every recorded device.
+    #[tokio::test]
+    async fn it_replay_corpus() {
+        let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures");
+        let mut dirs = vec![];
+        for device in fs::read_dir(&corpus).unwrap() {
+            for version in fs::read_dir(device.unwrap().path()).unwrap() {
+                dirs.push(version.unwrap().path());
+            }
+        }
+        assert!(!dirs.is_empty());
+
+        for dir in dirs {
+            let replay = Replay::load(&dir).unwrap();
+            let has = |method: &str| replay.contains(method, &Value::Null);
+            let config =
+                |name: &str| replay.contains("configManager.getConfig", &json!({ "name": name }));
+            let context = format!("{}", dir.display());
+
+            macro_rules! check {
+                ($e:expr) => {
+                    $e.await.expect(&context)
+                };
+            }
+
+            if has("global.getCurrentTime") {
+                check!(global::get_current_time(replay.rpc()));
+            }
+            if has("magicBox.getSerialNo") {
+                check!(magicbox::get_serial_no(replay.rpc()));
+            }
+            if has("magicBox.getDeviceType") {
+                check!(magicbox::get_device_type(replay.rpc()));
+            }
+            if has("magicBox.getDeviceClass") {
+                check!(magicbox::get_device_class(replay.rpc()));
+            }
+            if has("magicBox.getMemoryInfo") {
+                check!(magicbox::get_memory_info(replay.rpc()));
+            }
+            if has("magicBox.getHardwareVersion") {
+                check!(magicbox::get_hardware_version(replay.rpc()));
+            }
+            if has("magicBox.getVendor") {
+                check!(magicbox::get_vendor(replay.rpc()));
+            }
+            if has("magicBox.getSoftwareVersion") {
+                check!(magicbox::get_software_version(replay.rpc()));
+            }
+            if has("magicBox.getSerialNo") && has("magicBox.getVendor") {
+                let mut mc = multicall::Multicall::new();
+                let sn = magicbox::get_serial_no_call(&mut mc);
+                let vendor = magicbox::get_vendor_call(&mut mc);
+                let mut res = check!(mc.send(replay.rpc()));
+                res.take(sn).expect(&context);
+                res.take(vendor).expect(&context);
+            }
+            if has("magicBox.getMarketArea") {
+                check!(magicbox::get_market_area(replay.rpc()));
+            }
+            if has("License.getLicenseInfo") {
+                check!(license::get_license_info(replay.rpc()));
+            }
+            if has("storage.getDeviceAllInfo") {
+                check!(storage::get_device_all_info(replay.rpc(), replay.rpc()));
+            }
+            if has("mediaFileFind.findNextFile") {
+                check!(mediafilefind::find_next_file(replay.rpc(), 0, 64));
+            }
+            if has("ptz.getPresets") {
+                check!(ptz::get_presets(replay.rpc(), 0));
+            }
+            if config("General") {
+                check!(config::General::get(replay.rpc()));
+            }
+            if config("NTP") {
+                check!(config::NTP::get(replay.rpc()));
+            }
+            if config("Locales") {
+                check!(config::Locales::get(replay.rpc()));
+            }
+            if config("Email") {
+                check!(config::Email::get(replay.rpc()));
+            }
+            if config("VideoInMode") {
+                check!(config::VideoInMode::get(replay.rpc()));
+            }
+            if config("ChannelTitle") {
+                check!(config::ChannelTitle::get(replay.rpc()));
+            }
+            if config("Encode") {
+                check!(config::Encode::get(replay.rpc()));
+            }
+            if config("Record") {
+                check!(config::Record::get(replay.rpc()));
+            }
+            if config("RecordMode") {
+                check!(config::RecordMode::get(replay.rpc()));
+            }
+            if config("MotionDetect") {
+                check!(config::MotionDetect::get(replay.rpc()));
+            }
+        }
+    }
+}
@@ -1,14 +1,36 @@
-use std::time::{Duration, Instant};
+use std::{
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
 
 pub use chrono;
 pub use reqwest;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::Value;
 
+use fixture::Recorder;
+use transport::{ReqwestTransport, Transport};
+
+pub use capability::Capabilities;
+pub use endpoint::{Endpoint, Scheme};
+pub use lockout::LockPolicy;
+pub use retry::{RequestError, RequestKind, RetryPolicy};
+
+pub mod capability;
 pub mod cookie;
+pub mod dav;
+pub mod discover;
+pub mod endpoint;
+pub mod event;
 pub mod file;
+pub mod fixture;
+pub mod lockout;
 pub mod login;
 pub mod modules;
+pub mod multicall;
+pub mod retry;
+pub mod snapshot;
+pub mod transport;
 mod utils;
 
 pub fn recommended_reqwest_client_builder() -> reqwest::ClientBuilder {
@@ -66,7 +88,7 @@ pub enum Error {
     Login(LoginError),
     // Request could not be made for any reason
     #[error("Request: {0}")]
-    Request(String),
+    Request(RequestError),
     // Response could not be deserialized
     #[error("Parse: {0}")]
     Parse(String),
@@ -180,12 +202,20 @@ pub struct Request {
 pub struct RequestBuilder {
     req: Request,
     url: String,
-    client: reqwest::Client,
+    transport: Arc<dyn Transport>,
     require_session: bool,
+    recorder: Option<Recorder>,
+    retry: RetryPolicy,
+    idempotent: bool,
 }
 
 impl RequestBuilder {
-    pub fn new(id: i32, url: String, client: reqwest::Client, session: String) -> RequestBuilder {
+    pub fn new(
+        id: i32,
+        url: String,
+        transport: Arc<dyn Transport>,
+        session: String,
+    ) -> RequestBuilder {
         RequestBuilder {
             req: Request {
                 id,
@@ -195,8 +225,11 @@ impl RequestBuilder {
                 object: None,
             },
             url,
-            client,
+            transport,
             require_session: false,
+            recorder: None,
+            retry: RetryPolicy::none(),
+            idempotent: true,
         }
     }
 
@@ -205,6 +238,25 @@ impl RequestBuilder {
         self
     }
 
+    /// Saves the response as a fixture.
+    pub fn recorder(mut self, recorder: Recorder) -> RequestBuilder {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Policy of the request unless it is marked as non idempotent.
+    pub fn retry(mut self, retry: RetryPolicy) -> RequestBuilder {
+        self.retry = retry;
+        self
+    }
+
+    /// Marks a request that must not be sent twice because it changes the device, such as a
+    /// reboot. Those are never retried.
+    pub fn non_idempotent(mut self) -> RequestBuilder {
+        self.idempotent = false;
+        self
+    }
+
     pub fn params(mut self, params: serde_json::Value) -> RequestBuilder {
         self.req.params = params;
         self
@@ -224,15 +276,21 @@ impl RequestBuilder {
         if self.require_session && self.req.session.is_empty() {
             return Err(Error::no_session());
         }
-        self.client
-            .post(&self.url)
-            .json(&self.req)
-            .send()
-            .await
-            .map_err(|e| Error::Request(e.to_string()))?
-            .json::<Response<T>>()
-            .await
-            .map_err(|e| Error::Parse(e.to_string()))
+        let retry = if self.idempotent {
+            self.retry
+        } else {
+            RetryPolicy::none()
+        };
+        let res = retry
+            .send(self.transport.as_ref(), &self.url, &self.req)
+            .await?;
+
+        if let Some(recorder) = &self.recorder {
+            // Failing to save a fixture should not fail the request
+            recorder.record(&self.req, &res).ok();
+        }
+
+        serde_json::from_value(res).map_err(|e| Error::Parse(e.to_string()))
     }
 
     pub async fn send<T: DeserializeOwned>(self) -> Result<Response<T>, Error> {
@@ -244,18 +302,65 @@ impl RequestBuilder {
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Clone, Copy, Debug)]
 pub enum State {
     #[default]
     Logout,
-    Login(Instant),
+    Login(Login),
+    /// The device locked the user, logins are refused until then.
+    Locked(Instant),
     Error(LoginError),
 }
 
-#[derive(Default, Debug)]
+/// Session lifetime used until the device tells us its own.
+pub const DEFAULT_SESSION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A logged in session.
+#[derive(Clone, Copy, Debug)]
+pub struct Login {
+    /// When the login happened.
+    pub since: Instant,
+    /// When the session was last renewed by a login or a keep alive.
+    pub renewed: Instant,
+    /// Session lifetime negotiated with the device.
+    pub timeout: Duration,
+}
+
+impl Login {
+    pub fn new(since: Instant, timeout: Duration) -> Login {
+        Login {
+            since,
+            renewed: since,
+            timeout,
+        }
+    }
+
+    pub fn age(&self) -> Duration {
+        self.since.elapsed()
+    }
+
+    /// When the device forgets the session unless it is renewed.
+    pub fn expires_at(&self) -> Instant {
+        self.renewed + self.timeout
+    }
+
+    pub fn expires_in(&self) -> Duration {
+        self.expires_at().saturating_duration_since(Instant::now())
+    }
+
+    /// Keep alives are sent halfway through the session lifetime so slow requests do not race the
+    /// expiry.
+    pub fn needs_keep_alive(&self) -> bool {
+        self.renewed.elapsed() >= self.timeout / 2
+    }
+}
+
+#[derive(Default, Clone, Debug)]
 pub struct Connection {
     last_id: i32,
     pub session: String,
+    /// Session lifetime from the last login or keep alive response.
+    pub timeout: Option<Duration>,
 }
 
 impl Connection {
@@ -263,57 +368,165 @@ impl Connection {
         self.last_id += 1;
         self.last_id
     }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout.unwrap_or(DEFAULT_SESSION_TIMEOUT)
+    }
+}
+
+#[derive(Default, Debug)]
+struct Session {
+    connection: Connection,
+    state: State,
+    capabilities: Option<Capabilities>,
+    // Outlives connections
+    lock_policy: Option<LockPolicy>,
+}
+
+/// Session state shared by every clone of a Client.
+#[derive(Default, Debug)]
+struct Shared {
+    session: Mutex<Session>,
+    // Serializes login, keep alive and logout
+    login: tokio::sync::Mutex<()>,
 }
 
+/// Clones share the session so RPCs can be sent concurrently while login and keep alive only
+/// happen once.
+#[derive(Clone)]
 pub struct Client {
     client: reqwest::Client,
-    pub ip: String,
+    transport: Arc<dyn Transport>,
+    shared: Arc<Shared>,
+    retry: RetryPolicy,
+    pub endpoint: Endpoint,
     pub username: String,
     pub password: String,
-    pub connection: Connection,
-    pub state: State,
+    pub recorder: Option<Recorder>,
 }
 
 impl Client {
-    pub fn new(client: reqwest::Client, ip: String, username: String, password: String) -> Client {
+    /// The client has to be built with `danger_accept_invalid_certs(true)` when the endpoint
+    /// accepts invalid certificates.
+    pub fn new(
+        client: reqwest::Client,
+        endpoint: Endpoint,
+        username: String,
+        password: String,
+    ) -> Client {
         Client {
+            transport: Arc::new(ReqwestTransport::new(client.clone())),
             client,
-            ip,
+            shared: Arc::new(Shared::default()),
+            retry: RetryPolicy::default(),
+            endpoint,
             username,
             password,
-            state: State::default(),
-            connection: Connection::default(),
+            recorder: None,
         }
     }
 
-    pub fn rpc_raw(&mut self) -> RequestBuilder {
-        RequestBuilder::new(
-            self.connection.next_id(),
-            format!("http://{}/RPC2", self.ip),
-            self.client.clone(),
-            self.connection.session.clone(),
+    /// Sends RPC requests and event streams through the transport instead of the reqwest client.
+    /// File downloads and snapshots still use the reqwest client.
+    pub fn transport(mut self, transport: Arc<dyn Transport>) -> Client {
+        self.transport = transport;
+        self
+    }
+
+    /// Retries requests that failed because of a transient RequestError unless they are marked as
+    /// non idempotent.
+    pub fn retry(mut self, retry: RetryPolicy) -> Client {
+        self.retry = retry;
+        self
+    }
+
+    /// Copies the client with another password, the copy logs in with a session of its own.
+    pub fn with_password(&self, password: String) -> Client {
+        Client {
+            shared: Arc::new(Shared::default()),
+            password,
+            ..self.clone()
+        }
+    }
+
+    fn session_lock(&self) -> MutexGuard<'_, Session> {
+        self.shared
+            .session
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn state(&self) -> State {
+        self.session_lock().state
+    }
+
+    pub fn connection(&self) -> Connection {
+        self.session_lock().connection.clone()
+    }
+
+    pub fn rpc_raw(&self) -> RequestBuilder {
+        let (id, session) = {
+            let mut lock = self.session_lock();
+            (lock.connection.next_id(), lock.connection.session.clone())
+        };
+        let rpc = RequestBuilder::new(
+            id,
+            self.endpoint.url("/RPC2"),
+            self.transport.clone(),
+            session,
         )
-        .require_session()
+        .retry(self.retry)
+        .require_session();
+
+        match &self.recorder {
+            Some(recorder) => rpc.recorder(recorder.clone()),
+            None => rpc,
+        }
     }
 
-    fn rpc_login(&mut self) -> RequestBuilder {
+    fn rpc_login(&self) -> RequestBuilder {
+        let (id, session) = {
+            let mut lock = self.session_lock();
+            (lock.connection.next_id(), lock.connection.session.clone())
+        };
         RequestBuilder::new(
-            self.connection.next_id(),
-            format!("http://{}/RPC2_Login", self.ip),
-            self.client.clone(),
-            self.connection.session.clone(),
+            id,
+            self.endpoint.url("/RPC2_Login"),
+            self.transport.clone(),
+            session,
         )
+        .retry(self.retry)
     }
 
-    fn transition(&mut self, state: State) {
-        match (&self.state, &state) {
+    fn cached_capabilities(&self) -> Option<Capabilities> {
+        self.session_lock().capabilities.clone()
+    }
+
+    fn cache_capabilities(&self, capabilities: Capabilities) {
+        self.session_lock().capabilities = Some(capabilities);
+    }
+
+    fn set_session(&self, session: String) {
+        self.session_lock().connection.session = session;
+    }
+
+    fn set_timeout(&self, timeout: Duration) {
+        self.session_lock().connection.timeout = Some(timeout);
+    }
+
+    fn transition(&self, state: State) {
+        let mut lock = self.session_lock();
+        match (&lock.state, &state) {
             // Successful login
             (State::Logout, State::Login(_)) => {}
             // Successful keep alive
             (State::Login(_), State::Login(_)) => {}
-            _ => self.connection = Connection::default(),
+            _ => {
+                lock.connection = Connection::default();
+                lock.capabilities = None;
+            }
         }
 
-        self.state = state;
+        lock.state = state;
     }
 }
@@ -0,0 +1,96 @@
+use std::time::{Duration, Instant};
+
+use crate::{modules::config, Client, State};
+
+/// How the device locks logins after failed attempts, from config::General.
+///
+/// The number of attempts is not kept since the client stops logging in after the first
+/// rejected credentials, it never gets close to the threshold of the device.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LockPolicy {
+    pub enabled: bool,
+    /// How long the user stays locked.
+    pub lock_time: Duration,
+}
+
+impl Default for LockPolicy {
+    /// Factory settings of most devices.
+    fn default() -> Self {
+        LockPolicy {
+            enabled: true,
+            lock_time: Duration::from_secs(1800),
+        }
+    }
+}
+
+impl From<config::General> for LockPolicy {
+    fn from(value: config::General) -> Self {
+        LockPolicy {
+            enabled: value.lock_login_enable,
+            lock_time: Duration::from_secs(value.login_fail_lock_time.max(0) as u64),
+        }
+    }
+}
+
+impl State {
+    /// Time left until the device unlocks logins.
+    pub fn locked_for(&self) -> Option<Duration> {
+        match self {
+            State::Locked(until) => Some(until.saturating_duration_since(Instant::now())),
+            _ => None,
+        }
+    }
+}
+
+impl Client {
+    /// Policy read from the device, None until a login has succeeded once.
+    pub fn lock_policy(&self) -> Option<LockPolicy> {
+        self.session_lock().lock_policy
+    }
+
+    pub fn set_lock_policy(&self, policy: LockPolicy) {
+        self.session_lock().lock_policy = Some(policy);
+    }
+
+    /// When a lock that starts now ends.
+    ///
+    /// The policy is only read after a login succeeds, a device that is already locked when the
+    /// client starts is waited out with the lock time of the default policy.
+    pub(crate) fn lock_until(&self) -> Instant {
+        Instant::now() + self.lock_policy().unwrap_or_default().lock_time
+    }
+
+    /// Reads the policy once after the first login, devices without config::General keep the
+    /// default one.
+    pub(crate) async fn load_lock_policy(&self) {
+        if self.lock_policy().is_some() {
+            return;
+        }
+
+        let policy = match config::General::get(self.rpc_raw()).await {
+            Ok(general) => LockPolicy::from(general),
+            Err(_) => LockPolicy::default(),
+        };
+        self.set_lock_policy(policy);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_locked_for() {
+        assert_eq!(State::Logout.locked_for(), None);
+
+        let left = State::Locked(Instant::now() + Duration::from_secs(60))
+            .locked_for()
+            .unwrap();
+        assert!(left > Duration::from_secs(59) && left <= Duration::from_secs(60));
+
+        assert_eq!(
+            State::Locked(Instant::now()).locked_for(),
+            Some(Duration::ZERO)
+        );
+    }
+}
@@ -1,37 +1,65 @@
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
-use crate::{modules::global, utils, Client, Error, LoginError, RequestBuilder, State};
+use crate::{modules::global, utils, Client, Error, Login, LoginError, RequestBuilder, State};
 
-const TIMEOUT: u64 = 60;
+/// Session lifetime in seconds requested on every keep alive.
+const KEEP_ALIVE_TIMEOUT: u64 = 300;
 const WATCH_NET: &str = "WatchNet";
 
 impl Client {
-    pub async fn logout(&mut self) {
-        if let State::Login(_) = self.state {
+    pub async fn logout(&self) {
+        let _guard = self.shared.login.lock().await;
+        self.logout_locked().await
+    }
+
+    /// Logs out and refuses to login again.
+    pub async fn close(&self) {
+        let _guard = self.shared.login.lock().await;
+        self.logout_locked().await;
+        self.transition(State::Error(LoginError::Closed))
+    }
+
+    pub async fn login(&self) -> Result<(), Error> {
+        let _guard = self.shared.login.lock().await;
+        self.login_locked().await
+    }
+
+    async fn logout_locked(&self) {
+        if let State::Login(_) = self.state() {
             global::logout(self.rpc_raw()).await.ok();
             self.transition(State::Logout)
         }
     }
 
-    pub async fn login(&mut self) -> Result<(), Error> {
+    async fn login_locked(&self) -> Result<(), Error> {
         // Make sure we are in State::Logout
-        match self.state {
+        match self.state() {
             State::Logout => {}
             State::Login(_) => {
                 global::logout(self.rpc_raw()).await.ok();
                 self.transition(State::Logout)
             }
+            // Trying again would extend the lock
+            State::Locked(until) if Instant::now() < until => {
+                return Err(Error::Login(LoginError::HasBeenLocked))
+            }
+            State::Locked(_) => self.transition(State::Logout),
             State::Error(err) => return Err(Error::Login(err)),
         }
 
         match self.login_procedure().await {
             Ok(o) => {
-                self.transition(State::Login(Instant::now()));
+                let timeout = self.connection().timeout();
+                self.transition(State::Login(Login::new(Instant::now(), timeout)));
+                self.load_lock_policy().await;
                 Ok(o)
             }
             Err(err) => {
-                if let Error::Login(err) = err {
-                    // Block client on a login error
+                if let Error::Login(LoginError::HasBeenLocked) = err {
+                    // Unlock once the device does
+                    self.transition(State::Locked(self.lock_until()));
+                } else if let Error::Login(err) = err {
+                    // Block client on a login error, retrying wrong credentials leads to a lock
                     self.transition(State::Error(err));
                 } else {
                     // Reset connection
@@ -43,10 +71,10 @@ impl Client {
         }
     }
 
-    async fn login_procedure(&mut self) -> Result<(), Error> {
+    async fn login_procedure(&self) -> Result<(), Error> {
         // Do a first login and set our session
         let (first_login, res) = global::first_login(self.rpc_login(), &self.username).await?;
-        self.connection.session = res.session();
+        self.set_session(res.session());
         // Make sure the caemra supports this login procedure
         match res.error {
             Some(err) => match err.code {
@@ -73,7 +101,12 @@ impl Client {
         );
 
         match res.await {
-            Ok(_) => Ok(()),
+            Ok(timeout) => {
+                if let Some(timeout) = timeout.filter(|t| *t > 0) {
+                    self.set_timeout(Duration::from_secs(timeout));
+                }
+                Ok(())
+            }
             Err(err) => Err(Error::Login(match err {
                 Error::Response(err) if err.code == 268632085 => LoginError::UserOrPasswordNotValid,
                 Error::Response(err) if err.code == 268632081 => LoginError::HasBeenLocked,
@@ -89,18 +122,38 @@ impl Client {
         }
     }
 
-    pub async fn keep_alive_or_login(&mut self) -> Result<(), Error> {
-        // Make sure we are login
-        if let State::Login(last_login) = self.state {
-            // Check if we need to run keep alive
-            if Instant::now().duration_since(last_login).as_secs() < TIMEOUT {
-                return Ok(());
-            }
+    /// True when the session is fresh enough to be used without a keep alive.
+    fn is_fresh(&self) -> bool {
+        match self.state() {
+            State::Login(login) => !login.needs_keep_alive(),
+            _ => false,
+        }
+    }
+
+    pub async fn keep_alive_or_login(&self) -> Result<(), Error> {
+        if self.is_fresh() {
+            return Ok(());
+        }
 
+        let _guard = self.shared.login.lock().await;
+        // Another clone might have logged in while we were waiting
+        if self.is_fresh() {
+            return Ok(());
+        }
+
+        // Make sure we are login
+        if let State::Login(login) = self.state() {
             // Run keep alive
-            match global::keep_alive(self.rpc_raw()).await {
-                Ok(_) => {
-                    self.transition(State::Login(Instant::now()));
+            match global::keep_alive(self.rpc_raw(), KEEP_ALIVE_TIMEOUT).await {
+                Ok(timeout) => {
+                    if timeout > 0 {
+                        self.set_timeout(Duration::from_secs(timeout));
+                    }
+                    self.transition(State::Login(Login {
+                        renewed: Instant::now(),
+                        timeout: self.connection().timeout(),
+                        ..login
+                    }));
                     Ok(())
                 }
                 Err(err @ Error::Request(_)) => Err(err), // Camera probably unreachable
@@ -108,26 +161,21 @@ impl Client {
                     // Let's just assume that our session is invalid
                     self.transition(State::Logout);
 
-                    match self.login().await {
-                        Ok(o) => Ok(o),
-                        // Assume error was a connection reset because we did a keep alive and login request on the same connection
-                        // This only affects some cameras such as the "SD2A500-GN-A-PV"
-                        // TODO: check error kind is OS connection reset
-                        Err(Error::Request(_)) => self.login().await,
-                        Err(err) => Err(err),
-                    }
+                    // Some cameras such as the "SD2A500-GN-A-PV" reset the connection after a failed
+                    // keep alive, the retry policy sends the login again
+                    self.login_locked().await
                 }
             }
         } else {
-            self.login().await
+            self.login_locked().await
         }
     }
 
-    pub async fn rpc(&mut self) -> Result<RequestBuilder, Error> {
+    pub async fn rpc(&self) -> Result<RequestBuilder, Error> {
         self.keep_alive_or_login().await.map(|_| self.rpc_raw())
     }
 
-    pub async fn cookie(&mut self) -> Result<String, Error> {
+    pub async fn cookie(&self) -> Result<String, Error> {
         self.keep_alive_or_login().await.map(|_| self.cookie_raw())
     }
 }
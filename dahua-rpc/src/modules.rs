@@ -1,7 +1,11 @@
 pub mod config;
 pub mod configmanager;
+pub mod eventmanager;
 pub mod global;
 pub mod license;
 pub mod magicbox;
 pub mod mediafilefind;
+pub mod ptz;
 pub mod storage;
+pub mod system;
+pub mod usermanager;
@@ -1,5 +1,9 @@
+use std::{fmt, str::FromStr};
+
 use dahua_rpc_derive::{config_table, ConfigTable};
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{modules::configmanager::ConfigTableValue, Error};
 
 #[config_table]
 #[derive(Serialize, Deserialize, Debug, ConfigTable)]
@@ -169,3 +173,357 @@ pub struct DisableEmailLinkage {
     #[serde(rename = "Name")]
     pub name: String,
 }
+
+#[config_table]
+#[derive(Serialize, Deserialize, Debug, ConfigTable)]
+pub struct ChannelTitle(pub Vec<_ChannelTitle>);
+
+#[config_table]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct _ChannelTitle {
+    #[serde(rename = "Name")]
+    pub name: String,
+}
+
+/// Section of a weekly schedule in the form of `1 00:00:00-24:00:00`.
+///
+/// The mask is a bit field of what is enabled during the section, for Record it is 1 for regular,
+/// 2 for motion detection and 4 for alarms.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeSection {
+    pub mask: u32,
+    pub start: String,
+    pub end: String,
+}
+
+impl FromStr for TimeSection {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::Parse(format!("invalid time section '{s}'"));
+        let (mask, range) = s.split_once(' ').ok_or_else(invalid)?;
+        let (start, end) = range.split_once('-').ok_or_else(invalid)?;
+
+        Ok(TimeSection {
+            mask: mask.parse().map_err(|_| invalid())?,
+            start: start.to_string(),
+            end: end.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for TimeSection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}-{}", self.mask, self.start, self.end)
+    }
+}
+
+impl Serialize for TimeSection {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for TimeSection {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl ConfigTableValue for TimeSection {}
+
+#[config_table]
+#[derive(Serialize, Deserialize, Debug, ConfigTable)]
+pub struct Encode(pub Vec<_Encode>);
+
+#[config_table]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct _Encode {
+    #[serde(rename = "MainFormat")]
+    pub main_format: Vec<_EncodeFormat>,
+    #[serde(rename = "ExtraFormat")]
+    pub extra_format: Vec<_EncodeFormat>,
+}
+
+#[config_table]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct _EncodeFormat {
+    #[serde(rename = "Video")]
+    pub video: _EncodeVideo,
+    #[serde(rename = "VideoEnable")]
+    pub video_enable: bool,
+}
+
+#[config_table]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct _EncodeVideo {
+    #[serde(rename = "BitRate")]
+    pub bit_rate: i32,
+    #[serde(rename = "BitRateControl")]
+    pub bit_rate_control: String,
+    #[serde(rename = "Compression")]
+    pub compression: String,
+    #[serde(rename = "FPS")]
+    pub fps: i32,
+    #[serde(rename = "GOP")]
+    pub gop: i32,
+    #[serde(rename = "Height")]
+    pub height: i32,
+    #[serde(rename = "Width")]
+    pub width: i32,
+}
+
+#[config_table]
+#[derive(Serialize, Deserialize, Debug, ConfigTable)]
+pub struct Record(pub Vec<_Record>);
+
+#[config_table]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct _Record {
+    #[serde(rename = "PreRecord")]
+    pub pre_record: i32,
+    #[serde(rename = "Redundancy")]
+    pub redundancy: bool,
+    #[serde(rename = "Stream")]
+    pub stream: i32,
+    /// One list of sections for each day of the week starting on Sunday.
+    #[serde(rename = "TimeSection")]
+    pub time_section: Vec<Vec<TimeSection>>,
+}
+
+#[config_table]
+#[derive(Serialize, Deserialize, Debug, ConfigTable)]
+pub struct RecordMode(pub Vec<_RecordMode>);
+
+#[config_table]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct _RecordMode {
+    /// 0 follows the Record schedule, 1 is always recording and 2 is off.
+    #[serde(rename = "Mode")]
+    pub mode: i32,
+}
+
+#[config_table]
+#[derive(Serialize, Deserialize, Debug, ConfigTable)]
+pub struct MotionDetect(pub Vec<_MotionDetect>);
+
+#[config_table]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct _MotionDetect {
+    #[serde(rename = "Enable")]
+    pub enable: bool,
+    #[serde(rename = "EventHandler")]
+    pub event_handler: _EventHandler,
+    #[serde(rename = "MotionDetectWindow")]
+    pub motion_detect_window: Vec<_MotionDetectWindow>,
+}
+
+#[config_table]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct _MotionDetectWindow {
+    #[serde(rename = "Id")]
+    pub id: i32,
+    #[serde(rename = "Name")]
+    pub name: String,
+    /// Bit mask of the enabled blocks for each row of the grid.
+    #[serde(rename = "Region")]
+    pub region: Vec<i64>,
+    #[serde(rename = "Sensitive")]
+    pub sensitive: i32,
+    #[serde(rename = "Threshold")]
+    pub threshold: i32,
+}
+
+#[config_table]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct _EventHandler {
+    #[serde(rename = "Dejitter")]
+    pub dejitter: i32,
+    #[serde(rename = "MailEnable")]
+    pub mail_enable: bool,
+    #[serde(rename = "RecordChannels")]
+    pub record_channels: Vec<i32>,
+    #[serde(rename = "RecordEnable")]
+    pub record_enable: bool,
+    #[serde(rename = "RecordLatch")]
+    pub record_latch: i32,
+    #[serde(rename = "SnapshotChannels")]
+    pub snapshot_channels: Vec<i32>,
+    #[serde(rename = "SnapshotEnable")]
+    pub snapshot_enable: bool,
+    #[serde(rename = "TimeSection")]
+    pub time_section: Vec<Vec<TimeSection>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::de::DeserializeOwned;
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn week(section: &str) -> Value {
+        let day = json!([
+            section,
+            "0 00:00:00-24:00:00",
+            "0 00:00:00-24:00:00",
+            "0 00:00:00-24:00:00",
+            "0 00:00:00-24:00:00",
+            "0 00:00:00-24:00:00"
+        ]);
+        json!([day, day, day, day, day, day, day])
+    }
+
+    /// Tables have to serialize back to exactly what the camera sent, otherwise set would change
+    /// fields we do not know about.
+    fn round_trip<T: Serialize + DeserializeOwned>(value: Value) -> T {
+        let table: T = serde_json::from_value(value.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&table).unwrap(), value);
+        table
+    }
+
+    #[test]
+    fn it_time_section() {
+        let section: TimeSection = "6 08:00:00-17:30:00".parse().unwrap();
+        assert_eq!(section.mask, 6);
+        assert_eq!(section.start, "08:00:00");
+        assert_eq!(section.end, "17:30:00");
+        assert_eq!(section.to_string(), "6 08:00:00-17:30:00");
+
+        assert!("08:00:00-17:30:00".parse::<TimeSection>().is_err());
+        assert!("a 08:00:00-17:30:00".parse::<TimeSection>().is_err());
+    }
+
+    #[test]
+    fn it_encode() {
+        let video = |compression: &str, width: i32, height: i32, bit_rate: i32| {
+            json!({
+                "BitRate": bit_rate,
+                "BitRateControl": "CBR",
+                "Compression": compression,
+                "CustomResolutionName": "1080P",
+                "FPS": 25,
+                "GOP": 50,
+                "Height": height,
+                "Pack": "DHAV",
+                "Priority": 0,
+                "Profile": "Main",
+                "Quality": 4,
+                "QualityRange": 6,
+                "SVCTLayer": 1,
+                "Width": width
+            })
+        };
+        let audio = json!({
+            "Bitrate": 64,
+            "Compression": "G.711A",
+            "Depth": 16,
+            "Frequency": 8000,
+            "Mode": 0,
+            "Pack": "DHAV"
+        });
+        let encode: Encode = round_trip(json!([{
+            "ExtraFormat": [
+                { "Audio": audio, "AudioEnable": false, "Video": video("H.264", 704, 480, 512), "VideoEnable": true },
+                { "Audio": audio, "AudioEnable": false, "Video": video("H.264", 352, 240, 256), "VideoEnable": false }
+            ],
+            "MainFormat": [
+                { "Audio": audio, "AudioEnable": true, "Video": video("H.265", 2688, 1520, 4096), "VideoEnable": true }
+            ],
+            "SnapFormat": [
+                { "Video": { "Compression": "MJPG", "FPS": 1, "Height": 1520, "Quality": 6, "Width": 2688 }, "VideoEnable": true }
+            ]
+        }]));
+
+        assert_eq!(encode.0.len(), 1);
+        let main = &encode.0[0].main_format[0].video;
+        assert_eq!(main.compression, "H.265");
+        assert_eq!(main.width, 2688);
+        assert_eq!(main.height, 1520);
+        assert_eq!(main.bit_rate, 4096);
+        assert_eq!(main.fps, 25);
+        assert_eq!(main.gop, 50);
+        assert!(!encode.0[0].extra_format[1].video_enable);
+    }
+
+    #[test]
+    fn it_record() {
+        let record: Record = round_trip(json!([{
+            "Format": "dav",
+            "HolidayTimeSection": ["0 00:00:00-24:00:00", "0 00:00:00-24:00:00"],
+            "PreRecord": 4,
+            "Redundancy": false,
+            "SnapShot": false,
+            "Stream": 0,
+            "TimeSection": week("1 00:00:00-24:00:00")
+        }]));
+
+        let record = &record.0[0];
+        assert_eq!(record.pre_record, 4);
+        assert_eq!(record.time_section.len(), 7);
+        assert_eq!(record.time_section[0][0].mask, 1);
+        assert_eq!(record.time_section[0][0].end, "24:00:00");
+
+        let mode: RecordMode = round_trip(json!([
+            { "Mode": 0, "ModeExtra1": 2, "ModeExtra2": 2 },
+            { "Mode": 2, "ModeExtra1": 2, "ModeExtra2": 2 }
+        ]));
+        assert_eq!(mode.0[0].mode, 0);
+        assert_eq!(mode.0[1].mode, 2);
+    }
+
+    #[test]
+    fn it_motion_detect() {
+        let motion_detect: MotionDetect = round_trip(json!([{
+            "Enable": true,
+            "EventHandler": {
+                "AlarmOutChannels": [0],
+                "AlarmOutEnable": false,
+                "AlarmOutLatch": 10,
+                "Dejitter": 5,
+                "ExAlarmOutChannels": [],
+                "ExAlarmOutEnable": false,
+                "FlashEnable": false,
+                "FlashLatch": 10,
+                "LogEnable": true,
+                "MailEnable": false,
+                "MessageEnable": false,
+                "RecordChannels": [0],
+                "RecordEnable": true,
+                "RecordLatch": 10,
+                "SnapshotChannels": [0],
+                "SnapshotEnable": false,
+                "SnapshotPeriod": 0,
+                "SnapshotTimes": 0,
+                "TimeSection": week("1 00:00:00-24:00:00")
+            },
+            "MotionDetectWindow": [{
+                "Id": 0,
+                "Name": "Region1",
+                "Region": [4194303, 4194303, 4194303, 4194303, 0, 0],
+                "Sensitive": 60,
+                "Threshold": 5,
+                "Window": [0, 0, 8191, 8191]
+            }],
+            "OpenRule": false,
+            "PtzManualEnable": false
+        }]));
+
+        let motion_detect = &motion_detect.0[0];
+        assert!(motion_detect.enable);
+        assert!(motion_detect.event_handler.record_enable);
+        assert_eq!(motion_detect.event_handler.record_latch, 10);
+        assert_eq!(motion_detect.event_handler.time_section[6][0].mask, 1);
+        assert_eq!(motion_detect.motion_detect_window[0].name, "Region1");
+        assert_eq!(motion_detect.motion_detect_window[0].sensitive, 60);
+        assert_eq!(motion_detect.motion_detect_window[0].region[0], 4194303);
+    }
+}
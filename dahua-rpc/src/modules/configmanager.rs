@@ -1,17 +1,38 @@
+use std::collections::HashMap;
+
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::{to_value, Value};
 
 use crate::{Error, RequestBuilder};
 
-fn is_zero(num: &i32) -> bool {
-    *num == 0
+/// Marks types that keep every field the device sends so that setting a config table does not
+/// drop the fields we do not know about.
+///
+/// Implemented by the `config_table` attribute, which also requires it for every field.
+pub trait ConfigTableValue {}
+
+macro_rules! impl_config_table_value {
+    ($($t:ty),*) => {
+        $(impl ConfigTableValue for $t {})*
+    };
 }
 
+impl_config_table_value!(bool, i8, i16, i32, i64, u8, u16, u32, u64, f32, f64, String, Value);
+
+impl<T: ConfigTableValue> ConfigTableValue for Vec<T> {}
+
+impl<T: ConfigTableValue> ConfigTableValue for Option<T> {}
+
+impl<T: ConfigTableValue> ConfigTableValue for HashMap<String, T> {}
+
+pub fn assert_config_table_value<T: ConfigTableValue>() {}
+
+/// Without a channel the device returns every channel of an array table.
 #[derive(Serialize, Debug)]
 pub struct GetConfigRequest {
     pub name: &'static str,
-    #[serde(skip_serializing_if = "is_zero")]
-    pub channel: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel: Option<i32>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -20,7 +41,7 @@ pub struct GetConfigResponse<T> {
 }
 
 impl GetConfigRequest {
-    pub fn new(name: &'static str, channel: i32) -> Self {
+    pub fn new(name: &'static str, channel: Option<i32>) -> Self {
         Self { name, channel }
     }
 
@@ -56,8 +77,8 @@ impl GetConfigRequest {
 #[derive(Serialize, Debug)]
 pub struct SetConfigRequest<T: Serialize> {
     pub name: &'static str,
-    #[serde(skip_serializing_if = "is_zero")]
-    pub channel: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel: Option<i32>,
     pub table: T,
 }
 
@@ -65,7 +86,7 @@ impl<T> SetConfigRequest<T>
 where
     T: Serialize,
 {
-    pub fn new(name: &'static str, channel: i32, table: T) -> Self {
+    pub fn new(name: &'static str, channel: Option<i32>, table: T) -> Self {
         Self {
             name,
             channel,
@@ -75,9 +96,33 @@ where
 
     pub async fn set(self, rpc: RequestBuilder) -> Result<(), Error> {
         rpc.method("configManager.setConfig")
+            .non_idempotent()
             .params(to_value(self).expect("could not serialize SetConfigRequest"))
             .send::<Value>()
             .await
             .map(|_| Ok(()))?
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn it_config_request_channel() {
+        assert_eq!(
+            to_value(GetConfigRequest::new("VideoInMode", None)).unwrap(),
+            json!({ "name": "VideoInMode" })
+        );
+        assert_eq!(
+            to_value(GetConfigRequest::new("VideoInMode", Some(0))).unwrap(),
+            json!({ "name": "VideoInMode", "channel": 0 })
+        );
+        assert_eq!(
+            to_value(SetConfigRequest::new("VideoInMode", Some(1), json!({}))).unwrap(),
+            json!({ "name": "VideoInMode", "channel": 1, "table": {} })
+        );
+    }
+}
@@ -0,0 +1,80 @@
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{Error, RequestBuilder};
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EventAction {
+    Start,
+    Stop,
+    Pulse,
+    #[serde(other)]
+    Unknown,
+}
+
+impl EventAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventAction::Start => "Start",
+            EventAction::Stop => "Stop",
+            EventAction::Pulse => "Pulse",
+            EventAction::Unknown => "Unknown",
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct Event {
+    #[serde(rename = "Code")]
+    pub code: String,
+    #[serde(rename = "Action")]
+    pub action: EventAction,
+    #[serde(default, rename = "Index")]
+    pub index: i32,
+    #[serde(default, rename = "Data")]
+    pub data: Value,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct NotifyEventStream {
+    #[serde(rename = "eventList", default)]
+    pub event_list: Vec<Event>,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct Notify {
+    #[serde(default)]
+    pub method: String,
+    pub params: Option<Value>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Attach {
+    #[serde(rename = "SID", default)]
+    sid: i64,
+}
+
+/// Subscribes the current session to the given event codes (e.g. "All", "VideoMotion").
+/// Returns the subscription id.
+pub async fn attach(rpc: RequestBuilder, codes: &[&str]) -> Result<i64, Error> {
+    let res = rpc
+        .method("eventManager.attach")
+        .params(json!({
+            "codes": codes,
+        }))
+        .send::<Attach>()
+        .await?;
+
+    Ok(res.params.as_ref().map(|p| p.sid).unwrap_or_default())
+}
+
+pub async fn detach(rpc: RequestBuilder, codes: &[&str]) -> Result<bool, Error> {
+    Ok(rpc
+        .method("eventManager.detach")
+        .params(json!({
+            "codes": codes,
+        }))
+        .send::<Value>()
+        .await?
+        .result())
+}
@@ -10,7 +10,13 @@ pub struct GetCurrentTime {
 
 #[derive(Deserialize, Debug)]
 struct KeepAlive {
-    timeout: i32,
+    timeout: u64,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct SecondLogin {
+    keep_alive_interval: Option<u64>,
 }
 
 pub async fn get_current_time(rpc: RequestBuilder) -> Result<GetCurrentTime, Error> {
@@ -43,9 +49,12 @@ pub(crate) async fn second_login(
     password: &str,
     login_type: &str,
     authority_type: &str,
-) -> Result<bool, Error> {
+) -> Result<Option<u64>, Error> {
+    // Session lifetime in seconds, older firmware does not send it
     Ok(rpc_login
         .method("global.login")
+        // The challenge can only be answered once and failed logins count towards a lock
+        .non_idempotent()
         .params(json!({
             "userName": username,
             "password": password,
@@ -53,19 +62,21 @@ pub(crate) async fn second_login(
             "loginType": login_type,
             "authorityType": authority_type,
         }))
-        .send::<Value>()
+        .send::<SecondLogin>()
         .await?
-        .result())
+        .params
+        .and_then(|p| p.keep_alive_interval))
 }
 
 pub(crate) async fn logout(rpc: RequestBuilder) -> Result<bool, Error> {
     Ok(rpc.method("global.logout").send::<Value>().await?.result())
 }
 
-pub(crate) async fn keep_alive(rpc: RequestBuilder) -> Result<i32, Error> {
+/// Returns the session lifetime in seconds granted by the device.
+pub(crate) async fn keep_alive(rpc: RequestBuilder, timeout: u64) -> Result<u64, Error> {
     rpc.method("global.keepAlive")
         .params(json!({
-            "timeout": 300,
+            "timeout": timeout,
             "active": true
         }))
         .send::<KeepAlive>()
@@ -1,6 +1,11 @@
 use serde::{Deserialize, Serialize};
 
-use crate::{Error, RequestBuilder};
+use serde_json::Value;
+
+use crate::{
+    multicall::{Call, Multicall},
+    Error, RequestBuilder,
+};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Info {
@@ -36,3 +41,11 @@ pub async fn get_license_info(rpc: RequestBuilder) -> Result<Vec<InfoContainer>,
         .await?
         .params()
 }
+
+pub fn get_license_info_call(mc: &mut Multicall) -> Call<Vec<InfoContainer>> {
+    mc.add(
+        "License.getLicenseInfo",
+        Value::Null,
+        |p: Vec<InfoContainer>| p,
+    )
+}
@@ -1,7 +1,11 @@
 use serde::Deserialize;
 use serde_json::{json, Value};
 
-use crate::{utils::de_int_float_to_i64, Error, RequestBuilder};
+use crate::{
+    multicall::{Call, Multicall},
+    utils::de_int_float_to_i64,
+    Error, RequestBuilder,
+};
 
 #[derive(Deserialize, Debug)]
 struct NeedReboot {
@@ -77,6 +81,7 @@ struct GetMarketArea {
 pub async fn reboot(rpc: RequestBuilder) -> Result<bool, Error> {
     Ok(rpc
         .method("magicBox.reboot")
+        .non_idempotent()
         .send::<Value>()
         .await?
         .result())
@@ -90,17 +95,11 @@ pub async fn need_reboot(rpc: RequestBuilder) -> Result<bool, Error> {
 }
 
 pub async fn get_serial_no(rpc: RequestBuilder) -> Result<String, Error> {
-    rpc.method("magicBox.getSerialNo")
-        .send::<GetSerialNo>()
-        .await?
-        .params_map(|p, _| p.sn)
+    Multicall::send_one(rpc, get_serial_no_call).await
 }
 
 pub async fn get_device_type(rpc: RequestBuilder) -> Result<String, Error> {
-    rpc.method("magicBox.getDeviceType")
-        .send::<GetType>()
-        .await?
-        .params_map(|p, _| p.r#type)
+    Multicall::send_one(rpc, get_device_type_call).await
 }
 
 pub async fn get_memory_info(rpc: RequestBuilder) -> Result<GetMemoryInfo, Error> {
@@ -121,43 +120,75 @@ pub async fn get_cpu_usage(rpc: RequestBuilder) -> Result<i32, Error> {
 }
 
 pub async fn get_device_class(rpc: RequestBuilder) -> Result<String, Error> {
-    rpc.method("magicBox.getDeviceClass")
-        .send::<GetType>()
-        .await?
-        .params_map(|p, _| p.r#type)
+    Multicall::send_one(rpc, get_device_class_call).await
 }
 
 pub async fn get_process_info(rpc: RequestBuilder) -> Result<String, Error> {
-    rpc.method("magicBox.getProcessInfo")
-        .send::<GetProcessInfo>()
-        .await?
-        .params_map(|p, _| p.info)
+    Multicall::send_one(rpc, get_process_info_call).await
 }
 
 pub async fn get_hardware_version(rpc: RequestBuilder) -> Result<String, Error> {
-    rpc.method("magicBox.getHardwareVersion")
-        .send::<GetHardwareVersion>()
-        .await?
-        .params_map(|p, _| p.version)
+    Multicall::send_one(rpc, get_hardware_version_call).await
 }
 
 pub async fn get_vendor(rpc: RequestBuilder) -> Result<String, Error> {
-    rpc.method("magicBox.getVendor")
-        .send::<GetVendor>()
-        .await?
-        .params_map(|p, _| p.vendor)
+    Multicall::send_one(rpc, get_vendor_call).await
 }
 
 pub async fn get_software_version(rpc: RequestBuilder) -> Result<GetSoftwareVersion, Error> {
-    rpc.method("magicBox.getSoftwareVersion")
-        .send::<GetSoftwareVersionInternal>()
-        .await?
-        .params_map(|p, _| p.version)
+    Multicall::send_one(rpc, get_software_version_call).await
 }
 
 pub async fn get_market_area(rpc: RequestBuilder) -> Result<String, Error> {
-    rpc.method("magicBox.getMarketArea")
-        .send::<GetMarketArea>()
-        .await?
-        .params_map(|p, _| p.abroad_info)
+    Multicall::send_one(rpc, get_market_area_call).await
+}
+
+// Multicall versions of the getters above, the getters send them on their own
+
+pub fn get_serial_no_call(mc: &mut Multicall) -> Call<String> {
+    mc.add("magicBox.getSerialNo", Value::Null, |p: GetSerialNo| p.sn)
+}
+
+pub fn get_device_type_call(mc: &mut Multicall) -> Call<String> {
+    mc.add("magicBox.getDeviceType", Value::Null, |p: GetType| p.r#type)
+}
+
+pub fn get_device_class_call(mc: &mut Multicall) -> Call<String> {
+    mc.add("magicBox.getDeviceClass", Value::Null, |p: GetType| {
+        p.r#type
+    })
+}
+
+pub fn get_process_info_call(mc: &mut Multicall) -> Call<String> {
+    mc.add(
+        "magicBox.getProcessInfo",
+        Value::Null,
+        |p: GetProcessInfo| p.info,
+    )
+}
+
+pub fn get_hardware_version_call(mc: &mut Multicall) -> Call<String> {
+    mc.add(
+        "magicBox.getHardwareVersion",
+        Value::Null,
+        |p: GetHardwareVersion| p.version,
+    )
+}
+
+pub fn get_vendor_call(mc: &mut Multicall) -> Call<String> {
+    mc.add("magicBox.getVendor", Value::Null, |p: GetVendor| p.vendor)
+}
+
+pub fn get_software_version_call(mc: &mut Multicall) -> Call<GetSoftwareVersion> {
+    mc.add(
+        "magicBox.getSoftwareVersion",
+        Value::Null,
+        |p: GetSoftwareVersionInternal| p.version,
+    )
+}
+
+pub fn get_market_area_call(mc: &mut Multicall) -> Call<String> {
+    mc.add("magicBox.getMarketArea", Value::Null, |p: GetMarketArea| {
+        p.abroad_info
+    })
 }
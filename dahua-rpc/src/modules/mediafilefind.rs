@@ -52,6 +52,11 @@ impl Condition {
         }
     }
 
+    pub fn channel(mut self, channel: i32) -> Condition {
+        self.channel = channel;
+        self
+    }
+
     pub fn video(mut self) -> Condition {
         self.types = vec!["dav"];
         self
@@ -105,10 +110,7 @@ pub struct FindNextFileInfo {
 impl FindNextFileInfo {
     fn to_num(s: Option<&str>) -> u32 {
         match s {
-            Some(s) => match s.parse() {
-                Ok(o) => o,
-                Err(_) => 0,
-            },
+            Some(s) => s.parse().unwrap_or_default(),
             None => 0,
         }
     }
@@ -128,10 +130,8 @@ impl FindNextFileInfo {
         (
             self.start_time
                 .with_nanosecond(ns)
-                .unwrap_or_else(|| self.start_time),
-            self.end_time
-                .with_nanosecond(ns)
-                .unwrap_or_else(|| self.end_time),
+                .unwrap_or(self.start_time),
+            self.end_time.with_nanosecond(ns).unwrap_or(self.end_time),
         )
     }
 }
@@ -150,6 +150,7 @@ struct GetCount {
 pub async fn create(rpc: RequestBuilder) -> Result<i64, Error> {
     Ok(rpc
         .method("mediaFileFind.factory.create")
+        .non_idempotent()
         .send::<Value>()
         .await?
         .result_number())
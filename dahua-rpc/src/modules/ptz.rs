@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{utils::de_null_to_default, Error, RequestBuilder};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Code {
+    Up,
+    Down,
+    Left,
+    Right,
+    LeftUp,
+    RightUp,
+    LeftDown,
+    RightDown,
+    ZoomTele,
+    ZoomWide,
+    FocusNear,
+    FocusFar,
+    IrisLarge,
+    IrisSmall,
+}
+
+impl Code {
+    fn is_diagonal(&self) -> bool {
+        matches!(
+            self,
+            Code::LeftUp | Code::RightUp | Code::LeftDown | Code::RightDown
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Preset {
+    #[serde(rename = "Index")]
+    pub index: i32,
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Tour {
+    #[serde(rename = "Index")]
+    pub index: i32,
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GetPresets {
+    #[serde(default, deserialize_with = "de_null_to_default")]
+    presets: Vec<Preset>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GetTours {
+    #[serde(default, deserialize_with = "de_null_to_default")]
+    tours: Vec<Tour>,
+}
+
+pub async fn instance(rpc: RequestBuilder, channel: i32) -> Result<i64, Error> {
+    Ok(rpc
+        .method("ptz.factory.instance")
+        .params(json!({
+            "channel": channel,
+        }))
+        .send::<Value>()
+        .await?
+        .result_number())
+}
+
+/// Starts moving, zooming or focusing until stop is called.
+/// Speed is usually between 1 and 8.
+pub async fn start(
+    rpc: RequestBuilder,
+    object: i64,
+    channel: i32,
+    code: Code,
+    speed: i32,
+) -> Result<bool, Error> {
+    // Diagonal moves take the vertical speed in arg1 and the horizontal speed in arg2
+    let arg1 = if code.is_diagonal() { speed } else { 0 };
+
+    Ok(rpc
+        .method("ptz.start")
+        .non_idempotent()
+        .params(json!({
+            "channel": channel,
+            "code": code,
+            "arg1": arg1,
+            "arg2": speed,
+            "arg3": 0,
+        }))
+        .object(object)
+        .send::<Value>()
+        .await?
+        .result())
+}
+
+pub async fn stop(
+    rpc: RequestBuilder,
+    object: i64,
+    channel: i32,
+    code: Code,
+) -> Result<bool, Error> {
+    Ok(rpc
+        .method("ptz.stop")
+        .params(json!({
+            "channel": channel,
+            "code": code,
+            "arg1": 0,
+            "arg2": 0,
+            "arg3": 0,
+        }))
+        .object(object)
+        .send::<Value>()
+        .await?
+        .result())
+}
+
+pub async fn get_presets(rpc: RequestBuilder, object: i64) -> Result<Vec<Preset>, Error> {
+    rpc.method("ptz.getPresets")
+        .object(object)
+        .send::<GetPresets>()
+        .await?
+        .params_map(|p, _| p.presets)
+}
+
+pub async fn goto_preset(
+    rpc: RequestBuilder,
+    object: i64,
+    index: i32,
+    speed: i32,
+) -> Result<bool, Error> {
+    Ok(rpc
+        .method("ptz.gotoPreset")
+        .non_idempotent()
+        .params(json!({
+            "index": index,
+            "speed": speed,
+        }))
+        .object(object)
+        .send::<Value>()
+        .await?
+        .result())
+}
+
+/// Saves the current position as the given preset.
+pub async fn set_preset(rpc: RequestBuilder, object: i64, preset: Preset) -> Result<bool, Error> {
+    Ok(rpc
+        .method("ptz.setPreset")
+        .non_idempotent()
+        .params(json!({
+            "preset": preset,
+        }))
+        .object(object)
+        .send::<Value>()
+        .await?
+        .result())
+}
+
+pub async fn remove_preset(rpc: RequestBuilder, object: i64, index: i32) -> Result<bool, Error> {
+    Ok(rpc
+        .method("ptz.removePreset")
+        .non_idempotent()
+        .params(json!({
+            "index": index,
+        }))
+        .object(object)
+        .send::<Value>()
+        .await?
+        .result())
+}
+
+pub async fn get_tours(rpc: RequestBuilder, object: i64) -> Result<Vec<Tour>, Error> {
+    rpc.method("ptz.getTours")
+        .object(object)
+        .send::<GetTours>()
+        .await?
+        .params_map(|p, _| p.tours)
+}
+
+pub async fn start_tour(rpc: RequestBuilder, object: i64, index: i32) -> Result<bool, Error> {
+    Ok(rpc
+        .method("ptz.startTour")
+        .non_idempotent()
+        .params(json!({
+            "index": index,
+        }))
+        .object(object)
+        .send::<Value>()
+        .await?
+        .result())
+}
+
+pub async fn stop_tour(rpc: RequestBuilder, object: i64, index: i32) -> Result<bool, Error> {
+    Ok(rpc
+        .method("ptz.stopTour")
+        .params(json!({
+            "index": index,
+        }))
+        .object(object)
+        .send::<Value>()
+        .await?
+        .result())
+}
+
+pub async fn set_tour(rpc: RequestBuilder, object: i64, tour: Tour) -> Result<bool, Error> {
+    Ok(rpc
+        .method("ptz.setTour")
+        .non_idempotent()
+        .params(json!({
+            "tour": tour,
+        }))
+        .object(object)
+        .send::<Value>()
+        .await?
+        .result())
+}
+
+pub async fn remove_tour(rpc: RequestBuilder, object: i64, index: i32) -> Result<bool, Error> {
+    Ok(rpc
+        .method("ptz.removeTour")
+        .non_idempotent()
+        .params(json!({
+            "index": index,
+        }))
+        .object(object)
+        .send::<Value>()
+        .await?
+        .result())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_get_presets() {
+        let presets: GetPresets = serde_json::from_value(json!({
+            "presets": [
+                { "Index": 1, "Name": "Preset1", "Type": 0 },
+                { "Index": 2, "Name": "Door", "Type": 0 }
+            ]
+        }))
+        .unwrap();
+
+        assert_eq!(presets.presets.len(), 2);
+        assert_eq!(presets.presets[1].index, 2);
+        assert_eq!(presets.presets[1].name, "Door");
+        assert_eq!(
+            serde_json::to_value(&presets.presets[0]).unwrap(),
+            json!({ "Index": 1, "Name": "Preset1", "Type": 0 })
+        );
+
+        let presets: GetPresets = serde_json::from_value(json!({ "presets": null })).unwrap();
+        assert!(presets.presets.is_empty());
+    }
+}
@@ -0,0 +1,43 @@
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+    multicall::{Call, Multicall},
+    Error, RequestBuilder,
+};
+
+#[derive(Deserialize, Debug)]
+struct ListMethod {
+    #[serde(default)]
+    method: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ListService {
+    #[serde(default)]
+    service: Vec<String>,
+}
+
+pub async fn list_method(rpc: RequestBuilder) -> Result<Vec<String>, Error> {
+    rpc.method("system.listMethod")
+        .send::<ListMethod>()
+        .await?
+        .params_map(|p, _| p.method)
+}
+
+pub async fn list_service(rpc: RequestBuilder) -> Result<Vec<String>, Error> {
+    rpc.method("system.listService")
+        .send::<ListService>()
+        .await?
+        .params_map(|p, _| p.service)
+}
+
+pub fn list_method_call(mc: &mut Multicall) -> Call<Vec<String>> {
+    mc.add("system.listMethod", Value::Null, |p: ListMethod| p.method)
+}
+
+pub fn list_service_call(mc: &mut Multicall) -> Call<Vec<String>> {
+    mc.add("system.listService", Value::Null, |p: ListService| {
+        p.service
+    })
+}
@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{utils::de_null_to_default, Error, RequestBuilder};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct User {
+    #[serde(rename = "Id", default)]
+    pub id: i64,
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Group", default)]
+    pub group: String,
+    #[serde(rename = "Memo", default)]
+    pub memo: String,
+    #[serde(
+        rename = "AuthorityList",
+        default,
+        deserialize_with = "de_null_to_default"
+    )]
+    pub authority_list: Vec<String>,
+    #[serde(rename = "Reserved", default)]
+    pub reserved: bool,
+    #[serde(rename = "Sharable", default)]
+    pub sharable: bool,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Group {
+    #[serde(rename = "Id", default)]
+    pub id: i64,
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Memo", default)]
+    pub memo: String,
+    #[serde(
+        rename = "AuthorityList",
+        default,
+        deserialize_with = "de_null_to_default"
+    )]
+    pub authority_list: Vec<String>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// User that is logged in right now.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ActiveUser {
+    #[serde(rename = "Id", default)]
+    pub id: i64,
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Group", default)]
+    pub group: String,
+    #[serde(rename = "ClientAddress", default)]
+    pub client_address: String,
+    #[serde(rename = "ClientType", default)]
+    pub client_type: String,
+    /// Camera local time.
+    #[serde(rename = "LoginTime", default)]
+    pub login_time: String,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// User to add, Group is the name of an existing group.
+#[derive(Serialize, Clone, Debug)]
+pub struct NewUser {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Password")]
+    pub password: String,
+    #[serde(rename = "Group")]
+    pub group: String,
+    #[serde(rename = "Memo")]
+    pub memo: String,
+    /// Falls back to the authorities of the group when empty.
+    #[serde(rename = "AuthorityList")]
+    pub authority_list: Vec<String>,
+    #[serde(rename = "Reserved")]
+    pub reserved: bool,
+    #[serde(rename = "Sharable")]
+    pub sharable: bool,
+}
+
+#[derive(Deserialize, Debug)]
+struct GetUsers {
+    #[serde(default, deserialize_with = "de_null_to_default")]
+    users: Vec<User>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GetGroups {
+    #[serde(default, deserialize_with = "de_null_to_default")]
+    group: Vec<Group>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GetActiveUsers {
+    #[serde(default, deserialize_with = "de_null_to_default")]
+    users: Vec<ActiveUser>,
+}
+
+pub async fn get_user_info_all(rpc: RequestBuilder) -> Result<Vec<User>, Error> {
+    rpc.method("userManager.getUserInfoAll")
+        .send::<GetUsers>()
+        .await?
+        .params_map(|p, _| p.users)
+}
+
+pub async fn get_group_info_all(rpc: RequestBuilder) -> Result<Vec<Group>, Error> {
+    rpc.method("userManager.getGroupInfoAll")
+        .send::<GetGroups>()
+        .await?
+        .params_map(|p, _| p.group)
+}
+
+pub async fn get_active_user_info_all(rpc: RequestBuilder) -> Result<Vec<ActiveUser>, Error> {
+    rpc.method("userManager.getActiveUserInfoAll")
+        .send::<GetActiveUsers>()
+        .await?
+        .params_map(|p, _| p.users)
+}
+
+pub async fn add_user(rpc: RequestBuilder, user: NewUser) -> Result<bool, Error> {
+    Ok(rpc
+        .method("userManager.addUser")
+        .non_idempotent()
+        .params(json!({
+            "user": user,
+        }))
+        .send::<Value>()
+        .await?
+        .result())
+}
+
+pub async fn delete_user(rpc: RequestBuilder, name: &str) -> Result<bool, Error> {
+    Ok(rpc
+        .method("userManager.deleteUser")
+        .non_idempotent()
+        .params(json!({
+            "name": name,
+        }))
+        .send::<Value>()
+        .await?
+        .result())
+}
+
+/// Changes the password of a user, the device rejects it when the old password is wrong.
+///
+/// Not retried, a retry after the device changed the password would fail with the old one.
+pub async fn modify_password(
+    rpc: RequestBuilder,
+    name: &str,
+    old_password: &str,
+    new_password: &str,
+) -> Result<bool, Error> {
+    Ok(rpc
+        .method("userManager.modifyPassword")
+        .non_idempotent()
+        .params(json!({
+            "name": name,
+            "pwd": new_password,
+            "pwdOld": old_password,
+        }))
+        .send::<Value>()
+        .await?
+        .result())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_get_users() {
+        let users: GetUsers = serde_json::from_value(json!({
+            "users": [{
+                "AuthorityList": ["Monitor_01", "Replay_01"],
+                "Group": "admin",
+                "Id": 1,
+                "Memo": "admin 's account",
+                "Name": "admin",
+                "Password": "******",
+                "Reserved": true,
+                "Sharable": true
+            }]
+        }))
+        .unwrap();
+        assert_eq!(users.users.len(), 1);
+        assert_eq!(users.users[0].name, "admin");
+        assert_eq!(users.users[0].authority_list.len(), 2);
+        assert!(users.users[0].extra.contains_key("Password"));
+
+        let groups: GetGroups = serde_json::from_value(
+            json!({ "group": [{ "Id": 2, "Name": "user", "AuthorityList": null }] }),
+        )
+        .unwrap();
+        assert_eq!(groups.group[0].name, "user");
+        assert!(groups.group[0].authority_list.is_empty());
+
+        let active: GetActiveUsers = serde_json::from_value(json!({ "users": null })).unwrap();
+        assert!(active.users.is_empty());
+    }
+}
@@ -0,0 +1,269 @@
+use std::{any::Any, marker::PhantomData};
+
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+use crate::{Client, Error, RequestBuilder, Response};
+
+pub const METHOD: &str = "system.multicall";
+
+type Parser = Box<dyn FnOnce(Response<Value>) -> Result<Box<dyn Any + Send>, Error> + Send>;
+
+/// Packs several requests into a single system.multicall request.
+///
+/// ```ignore
+/// let mut mc = Multicall::new();
+/// let sn = magicbox::get_serial_no_call(&mut mc);
+/// let vendor = magicbox::get_vendor_call(&mut mc);
+/// let mut res = client.multicall(mc).await?;
+/// let (sn, vendor) = (res.take(sn)?, res.take(vendor)?);
+/// ```
+#[derive(Default)]
+pub struct Multicall {
+    calls: Vec<(&'static str, Value)>,
+    parsers: Vec<Option<Parser>>,
+}
+
+/// Handle to the result of a request added to a Multicall.
+pub struct Call<T> {
+    index: usize,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl Multicall {
+    pub fn new() -> Multicall {
+        Multicall::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.calls.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.calls.is_empty()
+    }
+
+    /// Adds a request whose params are deserialized into P and then mapped into T.
+    ///
+    /// Multicalls are retried like any other request, only add requests that are safe to send
+    /// twice.
+    pub fn add<P, T, F>(&mut self, method: &'static str, params: Value, map: F) -> Call<T>
+    where
+        P: DeserializeOwned,
+        T: Send + 'static,
+        F: FnOnce(P) -> T + Send + 'static,
+    {
+        let index = self.calls.len();
+        self.calls.push((method, params));
+        self.parsers
+            .push(Some(Box::new(move |res: Response<Value>| {
+                let value = res.params()?;
+                let params = serde_json::from_value::<P>(value)
+                    .map_err(|e| Error::Parse(format!("{method}: {e}")))?;
+                Ok(Box::new(map(params)) as Box<dyn Any + Send>)
+            })));
+
+        Call {
+            index,
+            _type: PhantomData,
+        }
+    }
+
+    /// Sends the single request added by build on its own, used by getters that also have a
+    /// Multicall version.
+    pub(crate) async fn send_one<T, F>(rpc: RequestBuilder, build: F) -> Result<T, Error>
+    where
+        T: 'static,
+        F: FnOnce(&mut Multicall) -> Call<T>,
+    {
+        let mut mc = Multicall::new();
+        let call = build(&mut mc);
+        let (method, params) = mc
+            .calls
+            .pop()
+            .ok_or_else(|| Error::Parse(format!("No request added to {METHOD}")))?;
+        let res = rpc
+            .method(method)
+            .params(params)
+            .send_raw::<Value>()
+            .await?;
+
+        MulticallResponse {
+            responses: vec![Some(res)],
+            parsers: mc.parsers,
+        }
+        .take(call)
+    }
+
+    /// Sends every request at once, errors of single requests are returned by MulticallResponse::take.
+    pub async fn send(self, rpc: RequestBuilder) -> Result<MulticallResponse, Error> {
+        let responses = send_calls(&self.calls, rpc).await?;
+
+        Ok(MulticallResponse {
+            responses,
+            parsers: self.parsers,
+        })
+    }
+}
+
+async fn send_calls(
+    calls: &[(&'static str, Value)],
+    rpc: RequestBuilder,
+) -> Result<Vec<Option<Response<Value>>>, Error> {
+    let session = rpc.req.session.clone();
+    let calls = calls
+        .iter()
+        .enumerate()
+        .map(|(index, (method, params))| {
+            json!({
+                "id": index + 1,
+                "method": method,
+                "params": params,
+                "session": session,
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut responses = Vec::new();
+    responses.resize_with(calls.len(), || None);
+
+    // Devices do not have to answer in order, the id of a response is its index plus one
+    for res in rpc
+        .method(METHOD)
+        .params(Value::Array(calls))
+        .send::<Vec<Response<Value>>>()
+        .await?
+        .params()?
+    {
+        let slot = usize::try_from(res.id - 1)
+            .ok()
+            .and_then(|index| responses.get_mut(index));
+        if let Some(slot @ None) = slot {
+            *slot = Some(res);
+        }
+    }
+
+    Ok(responses)
+}
+
+impl Client {
+    /// Sends the requests in one multicall or one by one when the device does not support it.
+    pub async fn multicall(&self, mc: Multicall) -> Result<MulticallResponse, Error> {
+        if mc.is_empty() {
+            return Ok(MulticallResponse {
+                responses: vec![],
+                parsers: vec![],
+            });
+        }
+
+        let Multicall { calls, parsers } = mc;
+        let responses = match send_calls(&calls, self.rpc().await?).await {
+            Err(Error::Response(_)) => self.send_each(&calls).await?,
+            res => res?,
+        };
+
+        Ok(MulticallResponse { responses, parsers })
+    }
+
+    async fn send_each(
+        &self,
+        calls: &[(&'static str, Value)],
+    ) -> Result<Vec<Option<Response<Value>>>, Error> {
+        let mut responses = vec![];
+        for (method, params) in calls {
+            let res = self
+                .rpc()
+                .await?
+                .method(method)
+                .params(params.clone())
+                .send_raw::<Value>()
+                .await?;
+            responses.push(Some(res));
+        }
+
+        Ok(responses)
+    }
+}
+
+pub struct MulticallResponse {
+    responses: Vec<Option<Response<Value>>>,
+    parsers: Vec<Option<Parser>>,
+}
+
+impl MulticallResponse {
+    /// Result of a single request, Error::Response when the device returned an error for it.
+    pub fn take<T: 'static>(&mut self, call: Call<T>) -> Result<T, Error> {
+        let (res, parser) = match (
+            self.responses.get_mut(call.index).and_then(Option::take),
+            self.parsers.get_mut(call.index).and_then(Option::take),
+        ) {
+            (Some(res), Some(parser)) => (res, parser),
+            _ => {
+                return Err(Error::Parse(format!(
+                    "No response for call {} in {METHOD}",
+                    call.index
+                )))
+            }
+        };
+
+        if let Some(err) = res.error {
+            return Err(Error::from_response_error(err));
+        }
+
+        parser(res)?
+            .downcast::<T>()
+            .map(|value| *value)
+            .map_err(|_| Error::Parse(format!("Wrong type for call {}", call.index)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::transport::MemoryTransport;
+
+    use super::*;
+
+    #[derive(serde::Deserialize)]
+    struct Sn {
+        sn: String,
+    }
+
+    #[tokio::test]
+    async fn it_multicall() {
+        let transport = MemoryTransport::new(|_, req| {
+            assert_eq!(req.method, METHOD);
+            let calls = req.params.as_array().unwrap();
+            assert_eq!(calls.len(), 3);
+            assert_eq!(calls[1]["session"], "abc");
+
+            Ok(json!({
+                "id": req.id,
+                "result": true,
+                "params": [
+                    { "id": 2, "result": false, "error": { "code": 268894210, "message": "Method not found!" } },
+                    { "id": 1, "result": true, "params": { "sn": "ABC123" } },
+                    { "id": 9, "result": true, "params": { "sn": "XYZ" } },
+                ],
+                "session": "abc",
+            }))
+        });
+        let rpc = RequestBuilder::new(1, "".to_string(), Arc::new(transport), "abc".to_string());
+
+        let mut mc = Multicall::new();
+        let sn = mc.add("magicBox.getSerialNo", Value::Null, |p: Sn| p.sn);
+        let missing = mc.add("magicBox.getVendor", Value::Null, |p: Sn| p.sn);
+        let short = mc.add("magicBox.getDeviceType", Value::Null, |p: Sn| p.sn);
+        let mut res = mc.send(rpc).await.unwrap();
+
+        assert_eq!(res.take(sn).unwrap(), "ABC123");
+        assert!(matches!(
+            res.take(missing),
+            Err(Error::Response(crate::ResponseError {
+                kind: crate::ResponseKind::MethodNotFound,
+                ..
+            }))
+        ));
+        assert!(matches!(res.take(short), Err(Error::Parse(_))));
+    }
+}
@@ -0,0 +1,229 @@
+use std::{
+    collections::hash_map::RandomState,
+    error::Error as StdError,
+    hash::{BuildHasher, Hasher},
+    io,
+    time::Duration,
+};
+
+use serde_json::Value;
+
+use crate::{transport::Transport, Error, Request};
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum RequestKind {
+    /// The device closed the connection, usually a stale keep alive connection.
+    ConnectionReset,
+    Timeout,
+    /// Nothing is listening, the device might be booting.
+    ConnectionRefused,
+    #[default]
+    Unknown,
+}
+
+/// Why a request could not be made.
+#[derive(thiserror::Error, Debug)]
+#[error("{message}")]
+pub struct RequestError {
+    pub kind: RequestKind,
+    pub message: String,
+}
+
+impl RequestError {
+    /// Errors that might not happen again when the request is sent again.
+    pub fn is_transient(&self) -> bool {
+        self.kind != RequestKind::Unknown
+    }
+}
+
+impl From<String> for RequestError {
+    fn from(message: String) -> Self {
+        RequestError {
+            kind: RequestKind::Unknown,
+            message,
+        }
+    }
+}
+
+impl From<reqwest::Error> for RequestError {
+    fn from(err: reqwest::Error) -> Self {
+        RequestError {
+            kind: classify(&err),
+            message: err.to_string(),
+        }
+    }
+}
+
+fn classify(err: &reqwest::Error) -> RequestKind {
+    if err.is_timeout() {
+        return RequestKind::Timeout;
+    }
+
+    let mut source = err.source();
+    while let Some(err) = source {
+        if let Some(err) = err.downcast_ref::<io::Error>() {
+            match err.kind() {
+                io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::BrokenPipe
+                | io::ErrorKind::UnexpectedEof => return RequestKind::ConnectionReset,
+                io::ErrorKind::ConnectionRefused => return RequestKind::ConnectionRefused,
+                io::ErrorKind::TimedOut => return RequestKind::Timeout,
+                _ => {}
+            }
+        }
+        // hyper does not expose its error kinds
+        if err
+            .to_string()
+            .contains("connection closed before message completed")
+        {
+            return RequestKind::ConnectionReset;
+        }
+        source = err.source();
+    }
+
+    RequestKind::Unknown
+}
+
+/// How often and how fast requests that failed with a transient RequestError are sent again.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt.
+    pub retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            retries: 2,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(2),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn none() -> RetryPolicy {
+        RetryPolicy {
+            retries: 0,
+            ..Default::default()
+        }
+    }
+
+    /// Exponential backoff with jitter between half and the full delay so clones do not retry in
+    /// lockstep.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_delay);
+        let jitter = RandomState::new().build_hasher().finish() % 1000;
+
+        delay / 2 + delay / 2 * jitter as u32 / 1000
+    }
+
+    pub(crate) async fn send(
+        &self,
+        transport: &dyn Transport,
+        url: &str,
+        req: &Request,
+    ) -> Result<Value, Error> {
+        let mut retry = 0;
+        loop {
+            match transport.send(url, req).await {
+                Err(Error::Request(err)) if err.is_transient() && retry < self.retries => {
+                    tokio::time::sleep(self.delay(retry)).await;
+                    retry += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use serde_json::json;
+
+    use crate::{transport::MemoryTransport, RequestBuilder};
+
+    use super::*;
+
+    #[test]
+    fn it_delay() {
+        let policy = RetryPolicy {
+            retries: 5,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(300),
+        };
+
+        for (retry, max) in [(0, 100), (1, 200), (2, 300), (10, 300)] {
+            let delay = policy.delay(retry);
+            assert!(delay >= Duration::from_millis(max / 2), "{retry} {delay:?}");
+            assert!(delay <= Duration::from_millis(max), "{retry} {delay:?}");
+        }
+    }
+
+    fn flaky(kind: RequestKind, failures: usize) -> (MemoryTransport, Arc<AtomicUsize>) {
+        let count = Arc::new(AtomicUsize::new(0));
+        let transport = MemoryTransport::new({
+            let count = count.clone();
+            move |_, req| {
+                if count.fetch_add(1, Ordering::SeqCst) < failures {
+                    return Err(Error::Request(RequestError {
+                        kind,
+                        message: "flaky".to_string(),
+                    }));
+                }
+                Ok(json!({ "id": req.id, "result": true, "params": null }))
+            }
+        });
+
+        (transport, count)
+    }
+
+    #[tokio::test]
+    async fn it_retry() {
+        let policy = RetryPolicy {
+            retries: 2,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(1),
+        };
+        let rpc = |transport: MemoryTransport| {
+            RequestBuilder::new(1, "".to_string(), Arc::new(transport), "".to_string())
+                .method("magicBox.getSerialNo")
+                .retry(policy)
+        };
+
+        // Transient errors are retried
+        let (transport, count) = flaky(RequestKind::ConnectionReset, 2);
+        assert!(rpc(transport).send::<Value>().await.is_ok());
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+
+        // Until the retries run out
+        let (transport, count) = flaky(RequestKind::Timeout, 3);
+        assert!(rpc(transport).send::<Value>().await.is_err());
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+
+        // Other errors are not
+        let (transport, count) = flaky(RequestKind::Unknown, 1);
+        assert!(rpc(transport).send::<Value>().await.is_err());
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        // Neither are requests that are marked as non idempotent
+        let (transport, count) = flaky(RequestKind::ConnectionReset, 1);
+        assert!(rpc(transport)
+            .non_idempotent()
+            .send::<Value>()
+            .await
+            .is_err());
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,25 @@
+use reqwest::header;
+
+use crate::{Client, Error};
+
+impl Client {
+    /// Channels start at 0 like the rest of the RPC API, snapshot.cgi starts at 1.
+    pub fn snapshot_url(&self, channel: i32) -> String {
+        self.endpoint
+            .url(&format!("/cgi-bin/snapshot.cgi?channel={}", channel + 1))
+    }
+
+    /// Requests a current still from the camera. The body of the response is the JPEG.
+    pub async fn snapshot(&self, channel: i32) -> Result<reqwest::Response, Error> {
+        let cookie = self.cookie().await?;
+
+        self.client
+            .get(self.snapshot_url(channel))
+            .header(header::COOKIE, cookie)
+            .send()
+            .await
+            .map_err(|e| Error::Request(e.into()))?
+            .error_for_status()
+            .map_err(|e| Error::Request(e.into()))
+    }
+}
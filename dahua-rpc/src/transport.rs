@@ -0,0 +1,262 @@
+use std::{collections::VecDeque, fmt, future::Future, pin::Pin, sync::Arc, time::Duration};
+
+use reqwest::header;
+use serde_json::Value;
+
+use crate::{Error, Request};
+
+pub type TransportFuture<'a> = Pin<Box<dyn Future<Output = Result<Value, Error>> + Send + 'a>>;
+pub type StreamFuture<'a> = Pin<Box<dyn Future<Output = Result<Stream, Error>> + Send + 'a>>;
+pub type ChunkFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Option<Vec<u8>>, Error>> + Send + 'a>>;
+
+// The event stream is a long poll so it should outlive the timeout of the reqwest client
+const STREAM_TIMEOUT: Duration = Duration::from_secs(60 * 60 * 24);
+
+/// Sends a RPC request to a url such as `http://{ip}/RPC2` and returns the raw JSON response.
+///
+/// Errors should be Error::Request when the request could not be made and Error::Parse when
+/// the response is not JSON.
+pub trait Transport: Send + Sync {
+    fn send<'a>(&'a self, url: &'a str, req: &'a Request) -> TransportFuture<'a>;
+
+    /// Opens a long poll such as `/SubscribeNotify.cgi` with the session cookie.
+    fn stream<'a>(&'a self, url: &'a str, _cookie: &'a str) -> StreamFuture<'a> {
+        Box::pin(async move {
+            Err(Error::Request(
+                format!("streaming {url} is not supported by the transport").into(),
+            ))
+        })
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for Arc<T> {
+    fn send<'a>(&'a self, url: &'a str, req: &'a Request) -> TransportFuture<'a> {
+        (**self).send(url, req)
+    }
+
+    fn stream<'a>(&'a self, url: &'a str, cookie: &'a str) -> StreamFuture<'a> {
+        (**self).stream(url, cookie)
+    }
+}
+
+/// Body of a long poll that is read as the device sends it.
+pub trait Chunks: Send {
+    /// Returns the next chunk or None when the body ends.
+    fn chunk(&mut self) -> ChunkFuture<'_>;
+}
+
+impl Chunks for reqwest::Response {
+    fn chunk(&mut self) -> ChunkFuture<'_> {
+        Box::pin(async move {
+            reqwest::Response::chunk(self)
+                .await
+                .map(|chunk| chunk.map(|chunk| chunk.to_vec()))
+                .map_err(|e| Error::Request(e.into()))
+        })
+    }
+}
+
+impl Chunks for VecDeque<Vec<u8>> {
+    fn chunk(&mut self) -> ChunkFuture<'_> {
+        let chunk = self.pop_front();
+        Box::pin(async move { Ok(chunk) })
+    }
+}
+
+/// Response of a long poll.
+pub struct Stream {
+    pub content_type: Option<String>,
+    pub body: Box<dyn Chunks>,
+}
+
+impl fmt::Debug for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Stream")
+            .field("content_type", &self.content_type)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Sends requests over HTTP.
+#[derive(Clone, Debug)]
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+}
+
+impl ReqwestTransport {
+    pub fn new(client: reqwest::Client) -> ReqwestTransport {
+        ReqwestTransport { client }
+    }
+}
+
+impl Transport for ReqwestTransport {
+    fn send<'a>(&'a self, url: &'a str, req: &'a Request) -> TransportFuture<'a> {
+        Box::pin(async move {
+            self.client
+                .post(url)
+                .json(req)
+                .send()
+                .await
+                .map_err(|e| Error::Request(e.into()))?
+                .json::<Value>()
+                .await
+                .map_err(|e| Error::Parse(e.to_string()))
+        })
+    }
+
+    fn stream<'a>(&'a self, url: &'a str, cookie: &'a str) -> StreamFuture<'a> {
+        Box::pin(async move {
+            let res = self
+                .client
+                .post(url)
+                .header(header::COOKIE, cookie)
+                .timeout(STREAM_TIMEOUT)
+                .send()
+                .await
+                .map_err(|e| Error::Request(e.into()))?
+                .error_for_status()
+                .map_err(|e| Error::Request(e.into()))?;
+
+            Ok(Stream {
+                content_type: res
+                    .headers()
+                    .get(header::CONTENT_TYPE)
+                    .and_then(|v| v.to_str().ok())
+                    .map(str::to_string),
+                body: Box::new(res),
+            })
+        })
+    }
+}
+
+type Handler = dyn Fn(&str, &Request) -> Result<Value, Error> + Send + Sync;
+type StreamHandler = dyn Fn(&str) -> Result<Vec<Vec<u8>>, Error> + Send + Sync;
+
+/// Answers requests with a function instead of a device.
+#[derive(Clone)]
+pub struct MemoryTransport {
+    handler: Arc<Handler>,
+    stream: Option<Arc<StreamHandler>>,
+}
+
+impl MemoryTransport {
+    pub fn new<F>(handler: F) -> MemoryTransport
+    where
+        F: Fn(&str, &Request) -> Result<Value, Error> + Send + Sync + 'static,
+    {
+        MemoryTransport {
+            handler: Arc::new(handler),
+            stream: None,
+        }
+    }
+
+    /// Answers long polls with the chunks the function returns for the url.
+    pub fn stream<F>(mut self, handler: F) -> MemoryTransport
+    where
+        F: Fn(&str) -> Result<Vec<Vec<u8>>, Error> + Send + Sync + 'static,
+    {
+        self.stream = Some(Arc::new(handler));
+        self
+    }
+}
+
+impl fmt::Debug for MemoryTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryTransport").finish_non_exhaustive()
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn send<'a>(&'a self, url: &'a str, req: &'a Request) -> TransportFuture<'a> {
+        let res = (self.handler)(url, req);
+        Box::pin(async move { res })
+    }
+
+    fn stream<'a>(&'a self, url: &'a str, _cookie: &'a str) -> StreamFuture<'a> {
+        let res = match &self.stream {
+            Some(handler) => handler(url).map(|chunks| Stream {
+                content_type: None,
+                body: Box::new(VecDeque::from(chunks)),
+            }),
+            None => Err(Error::Request(
+                format!("streaming {url} is not supported by the transport").into(),
+            )),
+        };
+        Box::pin(async move { res })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use serde_json::json;
+
+    use crate::{modules::magicbox, Client, Endpoint};
+
+    use super::*;
+
+    /// Middleware that counts requests before passing them on.
+    struct Counter<T> {
+        inner: T,
+        count: AtomicUsize,
+    }
+
+    impl<T: Transport> Transport for Counter<T> {
+        fn send<'a>(&'a self, url: &'a str, req: &'a Request) -> TransportFuture<'a> {
+            self.count.fetch_add(1, Ordering::SeqCst);
+            self.inner.send(url, req)
+        }
+    }
+
+    fn camera() -> MemoryTransport {
+        MemoryTransport::new(|url, req| {
+            let res = match (url.ends_with("/RPC2_Login"), req.method) {
+                (true, "global.login") if req.session.is_empty() => json!({
+                    "error": { "code": 268632079, "message": "Component error: login challenge!" },
+                    "params": { "encryption": "Default", "random": "1172275829", "realm": "Login to test" },
+                    "result": false,
+                    "session": "abc",
+                }),
+                (true, "global.login") => json!({ "result": true, "session": "abc" }),
+                (false, "magicBox.getSerialNo") => json!({
+                    "params": { "sn": "ABC123" },
+                    "result": true,
+                    "session": "abc",
+                }),
+                _ => {
+                    return Err(Error::Request(
+                        format!("unexpected {url} {}", req.method).into(),
+                    ))
+                }
+            };
+
+            Ok(res)
+        })
+    }
+
+    #[tokio::test]
+    async fn it_memory_transport() {
+        let transport = Arc::new(Counter {
+            inner: camera(),
+            count: AtomicUsize::new(0),
+        });
+        let client = Client::new(
+            reqwest::Client::new(),
+            Endpoint::new("camera".to_string()),
+            "admin".to_string(),
+            "123".to_string(),
+        )
+        .transport(transport.clone());
+
+        let sn = magicbox::get_serial_no(client.rpc().await.unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(sn, "ABC123");
+        assert_eq!(client.connection().session, "abc");
+        // Two logins, the lock policy and the serial number
+        assert_eq!(transport.count.load(Ordering::SeqCst), 4);
+    }
+}
@@ -8,21 +8,40 @@ async fn main() {
     let ip = std::env::var("IPC_IPS").expect("IPC_IPS not set");
     let username = std::env::var("IPC_USERNAME").expect("IPC_USERNAME not set");
     let password = std::env::var("IPC_PASSWORD").expect("IPC_PASSWORD not set");
+    // Directory to save sanitized responses to for the dahua-rpc fixtures
+    let fixtures = std::env::var("IPC_FIXTURES").ok();
+    // Accept self-signed certificates of cameras using https
+    let accept_invalid_certs = std::env::var("IPC_ACCEPT_INVALID_CERTS").is_ok();
 
     let ips = ip.split(",");
 
     for ip in ips {
+        let endpoint = match ip.parse::<dahua_rpc::Endpoint>() {
+            Ok(o) => o.accept_invalid_certs(accept_invalid_certs),
+            Err(err) => {
+                eprintln!("---------- {ip}: {err}");
+                continue;
+            }
+        };
         let mut client = dahua_rpc::Client::new(
             dahua_rpc::recommended_reqwest_client_builder()
+                .danger_accept_invalid_certs(accept_invalid_certs)
                 .build()
                 .expect("failed to create reqwest client"),
-            ip.to_string(),
+            endpoint,
             username.to_string(),
             password.to_string(),
         );
 
         eprintln!("++++++++++ {ip}");
 
+        if let Some(fixtures) = &fixtures {
+            if let Err(err) = client.record(fixtures).await {
+                eprintln!("---------- {ip}: {err}");
+                continue;
+            }
+        }
+
         let res = run(&mut client).await;
         client.logout().await;
 
@@ -30,6 +49,6 @@ async fn main() {
             eprintln!("---------- {ip}: {err}");
         }
 
-        eprintln!("")
+        eprintln!()
     }
 }
@@ -1,20 +1,21 @@
 use axum::{
     body::StreamBody,
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::{header, HeaderMap, StatusCode},
     response::IntoResponse,
     Json,
 };
 use ipcmanview::{
     db,
+    ipc::IpcUsers,
     models::{
-        Camera, CameraDetail, CameraLicense, CameraShow, CameraSoftware, CreateCameraRequest,
-        UpdateCameraRequest,
+        Camera, CameraAddressChange, CameraCapabilities, CameraDetail, CameraLicense, CameraShow,
+        CameraSoftware, CreateCameraRequest, UpdateCameraRequest, ValidationError,
     },
 };
 use serde_json::json;
 
-use crate::{app::AppState, dto};
+use crate::{app::AppState, dto, proxy};
 
 use super::api::{Error, ResultExt};
 
@@ -36,7 +37,9 @@ pub async fn total(State(state): State<AppState>) -> Result<impl IntoResponse, E
 
 pub async fn fs(
     Path((id, file_path)): Path<(i64, String)>,
+    Query(query): Query<dto::FileQuery>,
     State(state): State<AppState>,
+    headers: HeaderMap,
 ) -> Result<impl IntoResponse, Error> {
     let file = state
         .manager(id)
@@ -46,26 +49,36 @@ pub async fn fs(
         .or_error(StatusCode::INTERNAL_SERVER_ERROR)?;
 
     // TODO: maybe use hyper HTTP connector
+    match query.format {
+        Some(dto::FileFormat::Mp4) => proxy::mp4(state.file_client(&file), file).await,
+        None => proxy::file(state.file_client(&file), file, &file_path, &headers).await,
+    }
+    .or_error(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+pub async fn snapshot(
+    Path(id): Path<i64>,
+    Query(query): Query<dto::ChannelQuery>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, Error> {
     // Make request to camera
     let resp = state
-        .client
-        .get(file.url)
-        .header(header::COOKIE, file.cookie)
-        .send()
+        .manager(id)
+        .await?
+        .snapshot(query.channel)
         .await
-        .or_error(StatusCode::INTERNAL_SERVER_ERROR)?
-        .error_for_status()
         .or_error(StatusCode::INTERNAL_SERVER_ERROR)?;
 
     let mut headers = HeaderMap::new();
 
-    // Get Content-Type from file path
-    if let Some(content_type) = mime_guess::from_path(file_path).first() {
-        headers.insert(
-            header::CONTENT_TYPE,
-            content_type.to_string().parse().unwrap(),
-        );
-    };
+    // Get Content-Type from request
+    headers.insert(
+        header::CONTENT_TYPE,
+        resp.headers()
+            .get(header::CONTENT_TYPE)
+            .cloned()
+            .unwrap_or(header::HeaderValue::from_static("image/jpeg")),
+    );
 
     // Get Content-Length from request
     if let Some(content_length) = resp
@@ -87,9 +100,10 @@ pub async fn create(
     State(state): State<AppState>,
     Json(json): Json<CreateCameraRequest>,
 ) -> Result<impl IntoResponse, Error> {
-    // TODO: validate request
     let id = json.create(&state.pool, &state.store).await.map_err(|e| {
-        if db::Conflict == e {
+        if e.is::<ValidationError>() {
+            Error::from((StatusCode::BAD_REQUEST, e))
+        } else if db::Conflict == e {
             Error::from((StatusCode::CONFLICT, e))
         } else {
             Error::from((StatusCode::INTERNAL_SERVER_ERROR, e))
@@ -135,9 +149,10 @@ pub async fn update(
     State(state): State<AppState>,
     Json(json): Json<UpdateCameraRequest>,
 ) -> Result<impl IntoResponse, Error> {
-    // TODO: validate request
     json.update(&state.pool, &state.store).await.map_err(|e| {
-        if db::NotFound == e {
+        if e.is::<ValidationError>() {
+            Error::from((StatusCode::BAD_REQUEST, e))
+        } else if db::NotFound == e {
             Error::from((StatusCode::NOT_FOUND, e))
         } else if db::Conflict == e {
             Error::from((StatusCode::CONFLICT, e))
@@ -194,6 +209,59 @@ pub async fn licenses(
     Ok(Json(json!(licenses)))
 }
 
+pub async fn login(
+    Path(id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, Error> {
+    let login = state.manager(id).await?.login();
+
+    Ok(Json(json!(login)))
+}
+
+pub async fn address_changes(
+    Path(id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, Error> {
+    let changes = CameraAddressChange::list(&state.pool, id)
+        .await
+        .or_error(StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(json!(changes)))
+}
+
+/// Users and groups on the device and who is logged in right now.
+pub async fn users(
+    Path(id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, Error> {
+    let users = IpcUsers::get(&state.manager(id).await?)
+        .await
+        .or_error(StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(json!({
+        "users": users.users,
+        "groups": users.groups,
+        "active": users.active,
+    })))
+}
+
+pub async fn capabilities(
+    Path(id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, Error> {
+    let capabilities = CameraCapabilities::find(&state.pool, id)
+        .await
+        .map_err(|e| {
+            if db::NotFound == e {
+                Error::from((StatusCode::NOT_FOUND, e))
+            } else {
+                Error::from((StatusCode::INTERNAL_SERVER_ERROR, e))
+            }
+        })?;
+
+    Ok(Json(json!(capabilities)))
+}
+
 pub async fn refresh(
     Path(id): Path<i64>,
     State(state): State<AppState>,
@@ -273,3 +341,144 @@ pub async fn refresh_software(
 
     Ok(StatusCode::NO_CONTENT)
 }
+
+pub async fn refresh_capabilities(
+    Path(id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, Error> {
+    state
+        .manager(id)
+        .await?
+        .refresh_capabilities(&state.pool)
+        .await
+        .map_err(|e| {
+            if db::NotFound == e {
+                Error::from((StatusCode::NOT_FOUND, e))
+            } else {
+                Error::from((StatusCode::INTERNAL_SERVER_ERROR, e))
+            }
+        })?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Looks for the camera by serial number, null when it was not found at a new address.
+pub async fn relocate(
+    Path(id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, Error> {
+    let change = state
+        .manager(id)
+        .await?
+        .relocate(&state.pool, &state.store, &state.locator)
+        .await
+        .map_err(|e| {
+            if db::NotFound == e {
+                Error::from((StatusCode::NOT_FOUND, e))
+            } else if db::Conflict == e {
+                Error::from((StatusCode::CONFLICT, e))
+            } else {
+                Error::from((StatusCode::INTERNAL_SERVER_ERROR, e))
+            }
+        })?;
+
+    Ok(Json(json!(change)))
+}
+
+pub async fn rotate_password(
+    Path(id): Path<i64>,
+    State(state): State<AppState>,
+    Json(json): Json<dto::PasswordRotateRequest>,
+) -> Result<impl IntoResponse, Error> {
+    state
+        .manager(id)
+        .await?
+        .rotate_password(&state.pool, &state.store, &json.password)
+        .await
+        .map_err(|e| {
+            if e.is::<ValidationError>() {
+                Error::from((StatusCode::BAD_REQUEST, e))
+            } else if db::NotFound == e {
+                Error::from((StatusCode::NOT_FOUND, e))
+            } else if db::Conflict == e {
+                Error::from((StatusCode::CONFLICT, e))
+            } else {
+                Error::from((StatusCode::INTERNAL_SERVER_ERROR, e))
+            }
+        })?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Conflicts when the device rejects the pending password, it is discarded.
+pub async fn confirm_pending_password(
+    Path(id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, Error> {
+    state
+        .manager(id)
+        .await?
+        .confirm_pending_password(&state.pool, &state.store)
+        .await
+        .map_err(|e| {
+            if db::NotFound == e {
+                Error::from((StatusCode::NOT_FOUND, e))
+            } else if db::Conflict == e {
+                Error::from((StatusCode::CONFLICT, e))
+            } else {
+                Error::from((StatusCode::INTERNAL_SERVER_ERROR, e))
+            }
+        })?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn discard_pending_password(
+    Path(id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, Error> {
+    state
+        .manager(id)
+        .await?
+        .discard_pending_password(&state.pool)
+        .await
+        .map_err(|e| {
+            if db::NotFound == e {
+                Error::from((StatusCode::NOT_FOUND, e))
+            } else {
+                Error::from((StatusCode::INTERNAL_SERVER_ERROR, e))
+            }
+        })?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Rotates the password of every camera in the group, one camera failing does not stop the rest.
+pub async fn rotate_passwords(
+    State(state): State<AppState>,
+    Json(json): Json<dto::GroupPasswordRotateRequest>,
+) -> Result<impl IntoResponse, Error> {
+    if json.password.is_empty() {
+        return Err(Error::from((
+            StatusCode::BAD_REQUEST,
+            ValidationError("password is required".to_string()),
+        )));
+    }
+
+    let mut results = Vec::with_capacity(json.camera_ids.len());
+    for camera_id in json.camera_ids {
+        let res = match state.store.get(camera_id).await {
+            Ok(man) => man
+                .rotate_password(&state.pool, &state.store, &json.password)
+                .await
+                .map_err(|e| format!("{e:#}")),
+            Err(e) => Err(format!("{e:#}")),
+        };
+        results.push(dto::PasswordRotateResult {
+            camera_id,
+            error: res.err(),
+        });
+    }
+
+    Ok(Json(results))
+}
@@ -0,0 +1,93 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use ipcmanview::{
+    db,
+    models::{CreateCredentialRequest, Credential, UpdateCredentialRequest, ValidationError},
+};
+use serde_json::json;
+
+use crate::app::AppState;
+
+use super::api::{Error, ResultExt};
+
+pub async fn list(State(state): State<AppState>) -> Result<impl IntoResponse, Error> {
+    let credentials = Credential::list(&state.pool)
+        .await
+        .or_error(StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(json!(credentials)))
+}
+
+pub async fn create(
+    State(state): State<AppState>,
+    Json(json): Json<CreateCredentialRequest>,
+) -> Result<impl IntoResponse, Error> {
+    let id = json.create(&state.pool, &state.store).await.map_err(|e| {
+        if e.is::<ValidationError>() {
+            Error::from((StatusCode::BAD_REQUEST, e))
+        } else if db::Conflict == e {
+            Error::from((StatusCode::CONFLICT, e))
+        } else {
+            Error::from((StatusCode::INTERNAL_SERVER_ERROR, e))
+        }
+    })?;
+
+    Ok((StatusCode::CREATED, Json(json!({ "id": id }))))
+}
+
+pub async fn show(
+    Path(id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, Error> {
+    let credential = Credential::find(&state.pool, id).await.map_err(|e| {
+        if db::NotFound == e {
+            Error::from((StatusCode::NOT_FOUND, e))
+        } else {
+            Error::from((StatusCode::INTERNAL_SERVER_ERROR, e))
+        }
+    })?;
+
+    Ok(Json(json!(credential)))
+}
+
+/// Cameras that use the credential log in again with it.
+pub async fn update(
+    State(state): State<AppState>,
+    Json(json): Json<UpdateCredentialRequest>,
+) -> Result<impl IntoResponse, Error> {
+    json.update(&state.pool, &state.store).await.map_err(|e| {
+        if e.is::<ValidationError>() {
+            Error::from((StatusCode::BAD_REQUEST, e))
+        } else if db::NotFound == e {
+            Error::from((StatusCode::NOT_FOUND, e))
+        } else if db::Conflict == e {
+            Error::from((StatusCode::CONFLICT, e))
+        } else {
+            Error::from((StatusCode::INTERNAL_SERVER_ERROR, e))
+        }
+    })?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Conflicts while cameras still use the credential.
+pub async fn delete(
+    Path(id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, Error> {
+    Credential::delete(&state.pool, id).await.map_err(|e| {
+        if db::NotFound == e {
+            Error::from((StatusCode::NOT_FOUND, e))
+        } else if db::Conflict == e {
+            Error::from((StatusCode::CONFLICT, e))
+        } else {
+            Error::from((StatusCode::INTERNAL_SERVER_ERROR, e))
+        }
+    })?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,29 @@
+use std::time::Duration;
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use ipcmanview::{dahua_rpc::discover::Discover, models::DiscoveredCamera};
+
+use crate::{app::AppState, dto};
+
+use super::api::{Error, ResultExt};
+
+/// Longest time a request waits for replies.
+const MAX_TIMEOUT: u64 = 30;
+
+pub async fn list(
+    Query(query): Query<dto::DiscoverQuery>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, Error> {
+    let discover =
+        Discover::new().timeout(Duration::from_secs(query.timeout.clamp(1, MAX_TIMEOUT)));
+    let devices = DiscoveredCamera::discover(&state.pool, &discover)
+        .await
+        .or_error(StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(devices))
+}
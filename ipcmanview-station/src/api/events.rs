@@ -1,9 +1,15 @@
-use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
-use ipcmanview::models::IpcEvent;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use axum_extra::extract::Query;
+use ipcmanview::models::{CameraEvent, CameraEventQuery, CameraEventQueryFilter, IpcEvent};
 use serde_json::json;
 
 use super::api::{Error, ResultExt};
-use crate::app::AppState;
+use crate::{app::AppState, dto};
 
 pub async fn list(State(state): State<AppState>) -> Result<impl IntoResponse, Error> {
     let events = IpcEvent::list(&state.pool)
@@ -12,3 +18,36 @@ pub async fn list(State(state): State<AppState>) -> Result<impl IntoResponse, Er
 
     Ok(Json(json!(events)))
 }
+
+pub async fn live_by_camera(
+    Path(id): Path<i64>,
+    mut event_query: Query<dto::CameraEventQuery>,
+    state: State<AppState>,
+) -> Result<impl IntoResponse, Error> {
+    event_query.camera_ids = vec![id];
+
+    live(event_query, state).await
+}
+
+pub async fn live(
+    Query(query): Query<dto::CameraEventQuery>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, Error> {
+    let filter = CameraEventQueryFilter::new()
+        .start(query.start)
+        .end(query.end)
+        .codes(query.codes)
+        .actions(query.actions)
+        .camera_ids(query.camera_ids);
+    let query = CameraEventQuery::new(&filter)
+        .maybe_before(query.before)
+        .or_error(StatusCode::BAD_REQUEST)?
+        .maybe_after(query.after)
+        .or_error(StatusCode::BAD_REQUEST)?
+        .maybe_limit(query.limit);
+    let events = CameraEvent::query(&state.pool, query)
+        .await
+        .or_error(StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(events))
+}
@@ -30,7 +30,8 @@ pub async fn query(
         .end(query.end)
         .kinds(query.kinds)
         .events(query.events)
-        .camera_ids(query.camera_ids);
+        .camera_ids(query.camera_ids)
+        .channels(query.channels);
     let query = CameraFileQuery::new(&filter)
         .maybe_before(query.before)
         .or_error(StatusCode::BAD_REQUEST)?
@@ -63,7 +64,8 @@ pub async fn total(
         .end(query.end)
         .kinds(query.kinds)
         .events(query.events)
-        .camera_ids(query.camera_ids);
+        .camera_ids(query.camera_ids)
+        .channels(query.channels);
     let total = CameraFile::total(&state.pool, &filter)
         .await
         .or_error(StatusCode::INTERNAL_SERVER_ERROR)?;
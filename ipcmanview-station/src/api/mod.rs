@@ -1,20 +1,25 @@
 use axum::{
-    routing::{get, post},
+    routing::{delete, get, post},
     Router,
 };
 
 use crate::app::AppState;
 
+#[allow(clippy::module_inception)]
 mod api;
 mod camera;
+mod credential;
+mod discover;
 mod events;
 mod file;
+mod ptz;
 mod scan;
 
 pub fn router() -> Router<AppState> {
     Router::new()
         .route("/cameras", get(camera::list).post(camera::create))
         .route("/cameras-total", get(camera::total))
+        .route("/cameras-password", post(camera::rotate_passwords))
         .route(
             "/cameras/:id",
             get(camera::show)
@@ -24,18 +29,67 @@ pub fn router() -> Router<AppState> {
         .route("/cameras/:id/detail", get(camera::detail))
         .route("/cameras/:id/software", get(camera::software))
         .route("/cameras/:id/licenses", get(camera::licenses))
+        .route("/cameras/:id/capabilities", get(camera::capabilities))
+        .route("/cameras/:id/login", get(camera::login))
+        .route("/cameras/:id/address-changes", get(camera::address_changes))
+        .route("/cameras/:id/users", get(camera::users))
         .route("/cameras/:id/ipc", post(camera::refresh))
         .route("/cameras/:id/ipc/detail", post(camera::refresh_detail))
         .route("/cameras/:id/ipc/licenses", post(camera::refresh_licenses))
         .route("/cameras/:id/ipc/software", post(camera::refresh_software))
+        .route(
+            "/cameras/:id/ipc/capabilities",
+            post(camera::refresh_capabilities),
+        )
+        .route("/cameras/:id/ipc/relocate", post(camera::relocate))
+        .route("/cameras/:id/ipc/password", post(camera::rotate_password))
+        .route(
+            "/cameras/:id/ipc/password/pending",
+            post(camera::confirm_pending_password).delete(camera::discard_pending_password),
+        )
         .route("/cameras/:id/fs/*file_path", get(camera::fs))
+        .route("/cameras/:id/snapshot", get(camera::snapshot))
         .route("/cameras/:id/files", get(file::query_by_camera))
         .route("/cameras/:id/files-total", get(file::total_by_camera))
+        .route("/cameras/:id/events", get(events::live_by_camera))
+        .route("/cameras/:id/ptz/start", post(ptz::start))
+        .route("/cameras/:id/ptz/stop", post(ptz::stop))
+        .route(
+            "/cameras/:id/ptz/presets",
+            get(ptz::presets).post(ptz::set_preset),
+        )
+        .route(
+            "/cameras/:id/ptz/presets/:index",
+            delete(ptz::remove_preset),
+        )
+        .route(
+            "/cameras/:id/ptz/presets/:index/goto",
+            post(ptz::goto_preset),
+        )
+        .route(
+            "/cameras/:id/ptz/tours",
+            get(ptz::tours).post(ptz::set_tour),
+        )
+        .route("/cameras/:id/ptz/tours/:index", delete(ptz::remove_tour))
+        .route("/cameras/:id/ptz/tours/:index/start", post(ptz::start_tour))
+        .route("/cameras/:id/ptz/tours/:index/stop", post(ptz::stop_tour))
         .route("/cameras/:id/scans/full", post(scan::full))
         .route("/cameras/:id/scans/manual", post(scan::manual))
+        .route(
+            "/credentials",
+            get(credential::list).post(credential::create),
+        )
+        .route(
+            "/credentials/:id",
+            get(credential::show)
+                .post(credential::update)
+                .delete(credential::delete),
+        )
+        .route("/discover", get(discover::list))
         .route("/files", get(file::query))
         .route("/files-total", get(file::total))
         .route("/events", get(events::list))
+        .route("/events/live", get(events::live))
         .route("/scans/pending", get(scan::pending_list))
         .route("/scans/active", get(scan::active_list))
         .route("/scans/completed", get(scan::completed_list))
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use ipcmanview::{
+    dahua_rpc::{self, modules::ptz},
+    ipc::IpcPtz,
+};
+
+use crate::{app::AppState, dto};
+
+use super::api::{Error, ResultExt};
+
+async fn instance(state: &AppState, id: i64, channel: i32) -> Result<IpcPtz, Error> {
+    let man = state.manager(id).await?;
+    IpcPtz::new(man, channel)
+        .await
+        .or_error(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// Cameras answer commands they refuse with false instead of an error.
+fn command(res: Result<bool, dahua_rpc::Error>) -> Result<StatusCode, Error> {
+    if res.or_error(StatusCode::INTERNAL_SERVER_ERROR)? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(Error::from((
+            StatusCode::BAD_GATEWAY,
+            "Camera refused the PTZ command.",
+        )))
+    }
+}
+
+pub async fn start(
+    Path(id): Path<i64>,
+    State(state): State<AppState>,
+    Json(json): Json<dto::PtzMoveRequest>,
+) -> Result<impl IntoResponse, Error> {
+    let ptz = instance(&state, id, json.channel).await?;
+    command(ptz.start(json.code, json.speed).await)
+}
+
+pub async fn stop(
+    Path(id): Path<i64>,
+    State(state): State<AppState>,
+    Json(json): Json<dto::PtzMoveRequest>,
+) -> Result<impl IntoResponse, Error> {
+    let ptz = instance(&state, id, json.channel).await?;
+    command(ptz.stop(json.code).await)
+}
+
+pub async fn presets(
+    Path(id): Path<i64>,
+    Query(query): Query<dto::ChannelQuery>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, Error> {
+    let presets = instance(&state, id, query.channel)
+        .await?
+        .presets()
+        .await
+        .or_error(StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(presets))
+}
+
+pub async fn set_preset(
+    Path(id): Path<i64>,
+    State(state): State<AppState>,
+    Json(json): Json<dto::PtzPresetRequest>,
+) -> Result<impl IntoResponse, Error> {
+    let ptz = instance(&state, id, json.channel).await?;
+    command(
+        ptz.set_preset(ptz::Preset {
+            index: json.index,
+            name: json.name,
+            extra: HashMap::new(),
+        })
+        .await,
+    )
+}
+
+pub async fn goto_preset(
+    Path((id, index)): Path<(i64, i32)>,
+    State(state): State<AppState>,
+    Json(json): Json<dto::PtzGotoRequest>,
+) -> Result<impl IntoResponse, Error> {
+    let ptz = instance(&state, id, json.channel).await?;
+    command(ptz.goto_preset(index, json.speed).await)
+}
+
+pub async fn remove_preset(
+    Path((id, index)): Path<(i64, i32)>,
+    Query(query): Query<dto::ChannelQuery>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, Error> {
+    let ptz = instance(&state, id, query.channel).await?;
+    command(ptz.remove_preset(index).await)
+}
+
+pub async fn tours(
+    Path(id): Path<i64>,
+    Query(query): Query<dto::ChannelQuery>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, Error> {
+    let tours = instance(&state, id, query.channel)
+        .await?
+        .tours()
+        .await
+        .or_error(StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(tours))
+}
+
+pub async fn set_tour(
+    Path(id): Path<i64>,
+    State(state): State<AppState>,
+    Json(json): Json<dto::PtzTourRequest>,
+) -> Result<impl IntoResponse, Error> {
+    let ptz = instance(&state, id, json.channel).await?;
+    command(
+        ptz.set_tour(ptz::Tour {
+            index: json.index,
+            name: json.name,
+            extra: json.extra,
+        })
+        .await,
+    )
+}
+
+pub async fn start_tour(
+    Path((id, index)): Path<(i64, i32)>,
+    Query(query): Query<dto::ChannelQuery>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, Error> {
+    let ptz = instance(&state, id, query.channel).await?;
+    command(ptz.start_tour(index).await)
+}
+
+pub async fn stop_tour(
+    Path((id, index)): Path<(i64, i32)>,
+    Query(query): Query<dto::ChannelQuery>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, Error> {
+    let ptz = instance(&state, id, query.channel).await?;
+    command(ptz.stop_tour(index).await)
+}
+
+pub async fn remove_tour(
+    Path((id, index)): Path<(i64, i32)>,
+    Query(query): Query<dto::ChannelQuery>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, Error> {
+    let ptz = instance(&state, id, query.channel).await?;
+    command(ptz.remove_tour(index).await)
+}
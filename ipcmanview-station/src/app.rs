@@ -1,17 +1,32 @@
 use ipcmanview::{
-    ipc::{IpcManager, IpcStore},
+    ipc::{IpcFile, IpcManager, IpcStore},
+    locate::Locator,
     sqlx,
 };
 
+use crate::thumbnail::Thumbnails;
+
 #[derive(Clone)]
 pub struct AppState {
     pub pool: sqlx::SqlitePool,
     pub store: IpcStore,
     pub client: reqwest::Client,
+    pub insecure_client: reqwest::Client,
+    pub thumbnails: Thumbnails,
+    pub locator: Locator,
 }
 
 impl AppState {
     pub async fn manager_mpa(&self, id: i64) -> anyhow::Result<IpcManager> {
         self.store.get(id).await
     }
+
+    /// Client for downloading the file, cameras with self-signed certificates need the insecure one.
+    pub fn file_client(&self, file: &IpcFile) -> &reqwest::Client {
+        if file.accept_invalid_certs {
+            &self.insecure_client
+        } else {
+            &self.client
+        }
+    }
 }
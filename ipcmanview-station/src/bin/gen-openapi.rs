@@ -9,18 +9,42 @@ use utoipa::OpenApi;
     ipcmanview::models::CameraDetail,
     ipcmanview::models::CameraSoftware,
     ipcmanview::models::CameraLicense,
+    ipcmanview::models::CameraCapabilities,
+    ipcmanview::models::CameraLogin,
+    ipcmanview::models::CameraAddressChange,
+    ipcmanview::models::CameraLoginState,
     ipcmanview::models::CameraFile,
     ipcmanview::models::ScanCompletedPageResult,
     ipcmanview::models::CameraFileQueryResult,
+    ipcmanview::models::CameraEvent,
+    ipcmanview::models::CameraEventQueryResult,
     ipcmanview::models::ScanCompleted,
     ipcmanview::models::ScanActive,
     ipcmanview::models::ScanPending,
+    ipcmanview::models::CameraScheme,
+    ipcmanview::models::DiscoveredCamera,
     ipcmanview::models::CreateCameraRequest,
     ipcmanview::models::UpdateCameraRequest,
+    ipcmanview::models::CredentialSource,
+    ipcmanview::models::Credential,
+    ipcmanview::models::CreateCredentialRequest,
+    ipcmanview::models::UpdateCredentialRequest,
     ipcmanview_station::dto::PageQuery,
     ipcmanview_station::dto::DateTimeRange,
+    ipcmanview_station::dto::ChannelQuery,
+    ipcmanview_station::dto::DiscoverQuery,
+    ipcmanview_station::dto::FileFormat,
+    ipcmanview_station::dto::FileQuery,
+    ipcmanview_station::dto::PasswordRotateRequest,
+    ipcmanview_station::dto::GroupPasswordRotateRequest,
+    ipcmanview_station::dto::PasswordRotateResult,
+    ipcmanview_station::dto::PtzMoveRequest,
+    ipcmanview_station::dto::PtzPresetRequest,
+    ipcmanview_station::dto::PtzGotoRequest,
+    ipcmanview_station::dto::PtzTourRequest,
     ipcmanview_station::dto::CameraFileTotalQuery,
     ipcmanview_station::dto::CameraFileQuery,
+    ipcmanview_station::dto::CameraEventQuery,
     ipcmanview_station::dto::TotalQueryResult
 )))]
 struct ApiDoc;
@@ -0,0 +1,26 @@
+//! Seals the camera and credential passwords with a new station secret.
+//!
+//! The station has to be stopped, the current secret is read from `STATION_SECRET` or
+//! `STATION_SECRET_FILE` and the new one from `NEW_STATION_SECRET` or `NEW_STATION_SECRET_FILE`.
+
+use dotenvy::dotenv;
+use ipcmanview::{db, secret, secret::Secret};
+
+#[tokio::main]
+async fn main() {
+    dotenv().ok();
+
+    let database_url =
+        std::env::var("DATABASE_URL").unwrap_or("sqlite://ipcmanview.db".to_string());
+    let old = Secret::from_env("STATION_SECRET").expect("Invalid STATION_SECRET");
+    let new = Secret::from_env("NEW_STATION_SECRET").expect("Invalid NEW_STATION_SECRET");
+
+    let pool = db::new(&database_url, &old)
+        .await
+        .expect("Failed to open database");
+    let count = secret::rotate(&pool, &old, &new)
+        .await
+        .expect("Failed to rotate secret");
+
+    println!("Sealed {count} passwords with the new secret.");
+}
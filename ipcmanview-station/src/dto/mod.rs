@@ -1,5 +1,9 @@
+use std::collections::HashMap;
+
 use chrono::{DateTime, Utc};
+use ipcmanview::dahua_rpc::modules::ptz;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use utoipa::ToSchema;
 
 use crate::utils;
@@ -20,6 +24,97 @@ pub struct DateTimeRange {
     pub end: DateTime<Utc>,
 }
 
+#[derive(Deserialize, ToSchema, Debug)]
+pub struct DiscoverQuery {
+    /// Seconds to wait for replies.
+    #[serde(default = "default_discover_timeout")]
+    pub timeout: u64,
+}
+
+fn default_discover_timeout() -> u64 {
+    3
+}
+
+/// Format a camera file is converted to, dav recordings can be served as fragmented MP4.
+#[derive(Deserialize, ToSchema, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum FileFormat {
+    Mp4,
+}
+
+#[derive(Deserialize, ToSchema, Debug)]
+pub struct FileQuery {
+    pub format: Option<FileFormat>,
+}
+
+#[derive(Deserialize, ToSchema, Debug)]
+pub struct ChannelQuery {
+    #[serde(default)]
+    pub channel: i32,
+}
+
+#[derive(Deserialize, ToSchema, Debug)]
+pub struct PasswordRotateRequest {
+    pub password: String,
+}
+
+#[derive(Deserialize, ToSchema, Debug)]
+pub struct GroupPasswordRotateRequest {
+    pub camera_ids: Vec<i64>,
+    pub password: String,
+}
+
+/// Outcome of a password rotation for one camera in a group.
+#[derive(Serialize, ToSchema, Debug)]
+pub struct PasswordRotateResult {
+    pub camera_id: i64,
+    /// Null when the camera accepted the new password.
+    pub error: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema, Debug)]
+pub struct PtzMoveRequest {
+    #[serde(default)]
+    pub channel: i32,
+    #[schema(value_type = String)]
+    pub code: ptz::Code,
+    #[serde(default = "default_ptz_speed")]
+    pub speed: i32,
+}
+
+#[derive(Deserialize, ToSchema, Debug)]
+pub struct PtzPresetRequest {
+    #[serde(default)]
+    pub channel: i32,
+    pub index: i32,
+    #[serde(default)]
+    pub name: String,
+}
+
+#[derive(Deserialize, ToSchema, Debug)]
+pub struct PtzGotoRequest {
+    #[serde(default)]
+    pub channel: i32,
+    #[serde(default = "default_ptz_speed")]
+    pub speed: i32,
+}
+
+#[derive(Deserialize, ToSchema, Debug)]
+pub struct PtzTourRequest {
+    #[serde(default)]
+    pub channel: i32,
+    pub index: i32,
+    #[serde(default)]
+    pub name: String,
+    #[serde(flatten)]
+    #[schema(value_type = Object)]
+    pub extra: HashMap<String, Value>,
+}
+
+fn default_ptz_speed() -> i32 {
+    5
+}
+
 #[derive(Serialize, ToSchema, Debug)]
 pub struct TotalQueryResult {
     pub total: i32,
@@ -37,6 +132,28 @@ pub struct CameraFileTotalQuery {
     pub events: Vec<String>,
     #[serde(default)]
     pub camera_ids: Vec<i64>,
+    #[serde(default)]
+    pub channels: Vec<i64>,
+}
+
+#[derive(Deserialize, ToSchema, Debug)]
+pub struct CameraEventQuery {
+    #[serde(default, deserialize_with = "utils::empty_string_as_none")]
+    pub before: Option<String>,
+    #[serde(default, deserialize_with = "utils::empty_string_as_none")]
+    pub after: Option<String>,
+    #[serde(default, deserialize_with = "utils::empty_string_as_none")]
+    pub limit: Option<i32>,
+    #[serde(default, deserialize_with = "utils::empty_string_as_none")]
+    pub start: Option<DateTime<Utc>>,
+    #[serde(default, deserialize_with = "utils::empty_string_as_none")]
+    pub end: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub codes: Vec<String>,
+    #[serde(default)]
+    pub actions: Vec<String>,
+    #[serde(default)]
+    pub camera_ids: Vec<i64>,
 }
 
 #[derive(Deserialize, ToSchema, Debug)]
@@ -57,4 +174,6 @@ pub struct CameraFileQuery {
     pub events: Vec<String>,
     #[serde(default)]
     pub camera_ids: Vec<i64>,
+    #[serde(default)]
+    pub channels: Vec<i64>,
 }
@@ -2,4 +2,6 @@ pub mod api;
 pub mod app;
 pub mod dto;
 pub mod mpa;
+pub mod proxy;
+pub mod thumbnail;
 mod utils;
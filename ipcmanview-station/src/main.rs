@@ -3,8 +3,13 @@ use tower_http::trace::TraceLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 use dotenvy::dotenv;
-use ipcmanview::{db, ipc::IpcStore};
-use ipcmanview_station::{api, app::AppState, mpa};
+use ipcmanview::{
+    db,
+    ipc::IpcStore,
+    locate::{Locator, Subnet},
+    secret::Secret,
+};
+use ipcmanview_station::{api, app::AppState, mpa, thumbnail::Thumbnails};
 
 #[tokio::main]
 async fn main() {
@@ -29,26 +34,52 @@ async fn main() {
         |_| "127.0.0.1:8000".parse().unwrap(),
         |address| address.parse().expect("Invalid HTTP_ADDRESS"),
     );
+    let config_thumbnail_dir = std::env::var("THUMBNAIL_DIR").unwrap_or("thumbnails".to_string());
+    // Thumbnails of video files are decoded with ffmpeg, the station runs without it
+    let config_ffmpeg = std::env::var("FFMPEG").unwrap_or("ffmpeg".to_string());
+    // Comma separated subnets that are swept for cameras that changed address
+    let config_locate_subnets: Vec<Subnet> = std::env::var("LOCATE_SUBNETS").map_or_else(
+        |_| vec![],
+        |subnets| {
+            subnets
+                .split(',')
+                .filter(|subnet| !subnet.trim().is_empty())
+                .map(|subnet| subnet.trim().parse().expect("Invalid LOCATE_SUBNETS"))
+                .collect()
+        },
+    );
+
+    // Seals camera passwords in the database
+    let config_secret = Secret::from_env("STATION_SECRET").expect("Invalid STATION_SECRET");
 
     // Setup
-    let pool = db::new(&config_database_url)
+    let pool = db::new(&config_database_url, &config_secret)
         .await
         .expect("Failed to open database");
-    let store = IpcStore::new(pool.clone())
+    let locator = Locator::new(config_locate_subnets);
+    let store = IpcStore::new(pool.clone(), locator.clone(), config_secret)
         .await
         .expect("Failed to create store");
-    let client = reqwest::ClientBuilder::new()
-        .no_deflate()
-        // HACK: prevent connection reset when requesting too fast
-        .pool_max_idle_per_host(0)
+    let client_builder = || reqwest::ClientBuilder::new().no_deflate();
+    let client = client_builder()
         .build()
         .expect("Failed to create reqwest client");
+    let insecure_client = client_builder()
+        .danger_accept_invalid_certs(true)
+        .build()
+        .expect("Failed to create insecure reqwest client");
+
+    let thumbnails = Thumbnails::new(config_thumbnail_dir, config_ffmpeg);
+    thumbnails.spawn_prune(pool.clone());
 
     // App
     let app_state = AppState {
         pool,
         store: store.clone(),
         client,
+        insecure_client,
+        thumbnails,
+        locator,
     };
     let app = mpa::router()
         .nest("/api", api::router())
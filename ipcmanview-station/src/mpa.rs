@@ -1,7 +1,8 @@
+use anyhow::anyhow;
 use askama::Template;
 use axum::response::Response;
 use axum::{
-    body::StreamBody,
+    body::Bytes,
     extract::{Path, State},
     http::{header, HeaderMap, StatusCode},
     response::{IntoResponse, Redirect},
@@ -10,15 +11,18 @@ use axum::{
 };
 use axum_extra::extract::Query;
 use chrono::{DateTime, Utc};
-use ipcmanview::models::Page;
+use ipcmanview::{
+    dahua_rpc::{discover::Discover, endpoint::split_host_port},
+    models::Page,
+};
 use serde::{Deserialize, Serialize};
 
-use crate::{app::AppState, utils};
+use crate::{app::AppState, proxy, utils};
 use ipcmanview::{
     models::{
         Camera, CameraFile, CameraFileQuery, CameraFileQueryFilter, CameraFileQueryResult,
-        CameraShow, CreateCameraRequest, IpcEvent, ScanActive, ScanCompleted, ScanPending,
-        UpdateCameraRequest,
+        CameraScheme, CameraShow, CreateCameraRequest, CredentialSource, DiscoveredCamera,
+        IpcEvent, ScanActive, ScanCompleted, ScanPending, UpdateCameraRequest, ValidationError,
     },
     scan::{Scan, ScanKindPending},
 };
@@ -27,6 +31,10 @@ struct MpaError(anyhow::Error);
 
 impl IntoResponse for MpaError {
     fn into_response(self) -> Response {
+        if self.0.is::<ValidationError>() {
+            return (StatusCode::BAD_REQUEST, format!("{}", self.0)).into_response();
+        }
+
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             format!("Something went wrong: {}", self.0),
@@ -48,6 +56,8 @@ pub fn router() -> Router<AppState> {
     Router::new()
         .route("/", get(index_page))
         .route("/files", get(files_page))
+        .route("/files/:id/thumbnail", get(file_thumbnail))
+        .route("/discover", get(discover_page).post(discover_create))
         .route("/cameras", post(camera_create))
         .route("/cameras/:id", get(camera_page).post(camera_update))
         .route("/cameras/:id/delete", post(camera_delete))
@@ -97,6 +107,8 @@ struct FilesPageQuery {
     pub events: Vec<String>,
     #[serde(default)]
     pub camera_ids: Vec<i64>,
+    #[serde(default)]
+    pub channels: Vec<i64>,
 }
 
 async fn files_page(
@@ -112,7 +124,8 @@ async fn files_page(
         .end(query.end)
         .kinds(query.kinds)
         .events(query.events)
-        .camera_ids(query.camera_ids);
+        .camera_ids(query.camera_ids)
+        .channels(query.channels);
 
     let query = CameraFileQuery::new(&filter)
         .maybe_before(query.before)?
@@ -122,6 +135,7 @@ async fn files_page(
 
     let ipc_events = IpcEvent::list(&state.pool).await?;
     let cameras = Camera::list(&state.pool).await?;
+    let channels = cameras.iter().map(|c| c.channels).max().unwrap_or(1);
 
     let files_total = CameraFile::total(&state.pool, &filter).await?;
 
@@ -135,6 +149,7 @@ async fn files_page(
 
     Ok(FilesPageTemplate {
         cameras,
+        channels,
         ipc_events,
         files_total,
         files,
@@ -147,6 +162,7 @@ async fn files_page(
 #[template(path = "files.jinja.html")]
 struct FilesPageTemplate {
     cameras: Vec<Camera>,
+    channels: i64,
     ipc_events: Vec<String>,
     files_total: i32,
     files: CameraFileQueryResult,
@@ -157,43 +173,36 @@ struct FilesPageTemplate {
 async fn camera_file(
     Path((id, file_path)): Path<(i64, String)>,
     State(state): State<AppState>,
+    headers: HeaderMap,
 ) -> Result<impl IntoResponse, MpaError> {
     let file = state.manager_mpa(id).await?.file(&file_path).await?;
 
     // TODO: maybe use hyper HTTP connector
-    // Make request to camera
-    let resp = state
-        .client
-        .get(file.url)
-        .header(header::COOKIE, file.cookie)
-        .send()
+    Ok(proxy::file(state.file_client(&file), file, &file_path, &headers).await?)
+}
+
+async fn file_thumbnail(
+    Path(id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<Response, MpaError> {
+    let file = CameraFile::find(&state.pool, id).await?;
+    let ipc_file = state
+        .manager_mpa(file.camera_id)
         .await?
-        .error_for_status()?;
-
-    let mut headers = HeaderMap::new();
-
-    // Get Content-Type from file path
-    if let Some(content_type) = mime_guess::from_path(file_path).first() {
-        headers.insert(
-            header::CONTENT_TYPE,
-            content_type.to_string().parse().unwrap(),
-        );
-    };
-
-    // Get Content-Length from request
-    if let Some(content_length) = resp
-        .headers()
-        .get("content-length")
-        .and_then(|f| f.to_str().ok())
+        .file(&file.file_path)
+        .await?;
+
+    match state
+        .thumbnails
+        .get(state.file_client(&ipc_file), &file, ipc_file)
+        .await
     {
-        headers.insert(header::CONTENT_LENGTH, content_length.parse().unwrap());
+        Ok(jpeg) => Ok(([(header::CONTENT_TYPE, "image/jpeg")], jpeg).into_response()),
+        Err(err) => {
+            tracing::warn!("Failed to get thumbnail of file {id}: {err:#}");
+            Ok(Redirect::to(filters::PLACEHOLDER_IMAGE).into_response())
+        }
     }
-
-    // Get stream from request body
-    let stream = resp.bytes_stream();
-    let body = StreamBody::new(stream);
-
-    Ok((headers, body))
 }
 
 async fn camera_page(
@@ -211,20 +220,118 @@ struct CameraPageTemplate {
     show_cameras: CameraShow,
 }
 
+#[derive(Deserialize, Debug)]
+struct CameraCreate {
+    pub ip: String,
+    #[serde(default, deserialize_with = "utils::empty_string_as_none")]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub scheme: CameraScheme,
+    #[serde(default)]
+    pub accept_invalid_certs: bool,
+    pub username: String,
+    pub password: String,
+}
+
 async fn camera_create(
     State(state): State<AppState>,
-    Form(form): Form<CreateCameraRequest>,
+    Form(form): Form<CameraCreate>,
 ) -> Result<impl IntoResponse, MpaError> {
-    let id = form.create(&state.pool, &state.store).await?; // TODO: map to either BadRequest, Conflict, or InternalServerError
+    let id = CreateCameraRequest {
+        ip: form.ip,
+        port: form.port,
+        scheme: form.scheme,
+        accept_invalid_certs: form.accept_invalid_certs,
+        username: form.username,
+        password: form.password,
+        credential: CredentialSource::Literal,
+    }
+    .create(&state.pool, &state.store)
+    .await?; // TODO: map to either Conflict or InternalServerError
 
     Ok(Redirect::to(format!("/cameras/{id}").as_str()))
 }
 
+async fn discover_page(State(state): State<AppState>) -> Result<impl IntoResponse, MpaError> {
+    let devices = DiscoveredCamera::discover(&state.pool, &Discover::new())
+        .await?
+        .into_iter()
+        .filter(|device| device.camera_id.is_none())
+        .collect();
+
+    Ok(DiscoverPageTemplate { devices })
+}
+
+#[derive(Template)]
+#[template(path = "discover.jinja.html")]
+struct DiscoverPageTemplate {
+    devices: Vec<DiscoveredCamera>,
+}
+
+#[derive(Deserialize, Debug)]
+struct DiscoverCreate {
+    /// IP address and HTTP port of each device.
+    #[serde(default)]
+    pub addresses: Vec<String>,
+    #[serde(default)]
+    pub scheme: CameraScheme,
+    #[serde(default)]
+    pub accept_invalid_certs: bool,
+    pub username: String,
+    pub password: String,
+}
+
+/// Adds every selected device with the same credentials.
+async fn discover_create(
+    State(state): State<AppState>,
+    body: Bytes,
+) -> Result<impl IntoResponse, MpaError> {
+    // Form can not parse repeated fields
+    let form: DiscoverCreate =
+        serde_html_form::from_bytes(&body).map_err(|err| ValidationError(err.to_string()))?;
+
+    let mut errors = Vec::new();
+    for address in form.addresses {
+        let (host, http_port) =
+            split_host_port(&address).map_err(|err| ValidationError(err.to_string()))?;
+        // Discovery only knows the HTTP port, HTTPS cameras use the default port
+        let port = match form.scheme {
+            CameraScheme::Http => http_port.filter(|port| *port != 80),
+            CameraScheme::Https => None,
+        };
+        let created = CreateCameraRequest {
+            ip: host.to_string(),
+            port,
+            scheme: form.scheme,
+            accept_invalid_certs: form.accept_invalid_certs,
+            username: form.username.clone(),
+            password: form.password.clone(),
+            credential: CredentialSource::Literal,
+        }
+        .create(&state.pool, &state.store)
+        .await;
+        if let Err(err) = created {
+            errors.push(format!("{address}: {err:#}"));
+        }
+    }
+    if !errors.is_empty() {
+        return Err(anyhow!("Failed to add cameras:\n{}", errors.join("\n")).into());
+    }
+
+    Ok(Redirect::to("/"))
+}
+
 #[derive(Deserialize, Debug)]
 struct CameraUpdate {
     #[serde(default, deserialize_with = "utils::empty_string_as_none")]
     pub ip: Option<String>,
     #[serde(default, deserialize_with = "utils::empty_string_as_none")]
+    pub port: Option<u16>,
+    #[serde(default, deserialize_with = "utils::empty_string_as_none")]
+    pub scheme: Option<CameraScheme>,
+    #[serde(default, deserialize_with = "utils::empty_string_as_none")]
+    pub accept_invalid_certs: Option<bool>,
+    #[serde(default, deserialize_with = "utils::empty_string_as_none")]
     pub username: Option<String>,
     #[serde(default, deserialize_with = "utils::empty_string_as_none")]
     pub password: Option<String>,
@@ -238,11 +345,16 @@ async fn camera_update(
     UpdateCameraRequest {
         id,
         ip: form.ip,
+        // A blank port keeps the current one unless the ip changes
+        port: form.port.map(Some),
+        scheme: form.scheme,
+        accept_invalid_certs: form.accept_invalid_certs,
         username: form.username,
         password: form.password,
+        credential: None,
     }
     .update(&state.pool, &state.store)
-    .await?; // TODO: map to either BadRequest, Conflict, or InternalServerError
+    .await?; // TODO: map to either Conflict or InternalServerError
 
     Ok(Redirect::to(format!("/cameras/{id}").as_str()))
 }
@@ -300,7 +412,7 @@ async fn scan_completed_retry(
 ) -> Result<impl IntoResponse, MpaError> {
     ScanCompleted::retry(&state.pool, &state.store, id).await?;
 
-    Ok(Redirect::to(format!("/scans").as_str()))
+    Ok(Redirect::to("/scans"))
 }
 
 mod filters {
@@ -309,6 +421,8 @@ mod filters {
 
     use ipcmanview::models::CameraFile;
 
+    pub const PLACEHOLDER_IMAGE: &str = "https://placehold.co/600x400.png";
+
     pub fn url_camera_file(file: &CameraFile) -> ::askama::Result<String> {
         Ok(format!(
             "/cameras/{}/file/{}",
@@ -317,10 +431,10 @@ mod filters {
     }
 
     pub fn url_camera_file_image(file: &CameraFile) -> ::askama::Result<String> {
-        if file.kind == "jpg" {
-            url_camera_file(file)
-        } else {
-            Ok("https://placehold.co/600x400.png".to_string())
+        match file.kind.as_str() {
+            "jpg" => url_camera_file(file),
+            "dav" => Ok(format!("/files/{}/thumbnail", file.id)),
+            _ => Ok(PLACEHOLDER_IMAGE.to_string()),
         }
     }
 
@@ -332,7 +446,7 @@ mod filters {
     }
 
     pub fn duration(start: &DateTime<Utc>, end: &DateTime<Utc>) -> ::askama::Result<String> {
-        Ok((end.clone() - start.clone())
+        Ok((*end - *start)
             .to_std()
             .map_or("".to_string(), |d| format_duration(d).to_string()))
     }
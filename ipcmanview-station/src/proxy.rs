@@ -0,0 +1,184 @@
+use std::future::ready;
+
+use axum::{
+    body::{Bytes, StreamBody},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use futures_util::{stream, Stream, StreamExt};
+use ipcmanview::{
+    dahua_rpc::{
+        dav::mp4::DavToMp4,
+        file::{content_range, ByteRange},
+    },
+    ipc::IpcFile,
+};
+
+/// Streams a file from the camera, honoring Range requests.
+///
+/// The Range and If-Range headers are forwarded to the camera, when the camera ignores them the
+/// range is cut out of the full body instead.
+pub async fn file(
+    client: &reqwest::Client,
+    file: IpcFile,
+    file_path: &str,
+    req_headers: &HeaderMap,
+) -> Result<Response, reqwest::Error> {
+    let range = req_headers.get(header::RANGE).and_then(|range| {
+        range
+            .to_str()
+            .ok()?
+            .parse::<ByteRange>()
+            .ok()
+            .map(|parsed| (range, parsed))
+    });
+
+    // Make request to camera
+    let mut req = client.get(&file.url).header(header::COOKIE, file.cookie);
+    if let Some((value, _)) = range {
+        req = req.header(header::RANGE, value);
+        if let Some(if_range) = req_headers.get(header::IF_RANGE) {
+            req = req.header(header::IF_RANGE, if_range);
+        }
+    }
+    let resp = req.send().await?;
+    if resp.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        let mut headers = HeaderMap::new();
+        copy_header(resp.headers(), &mut headers, header::CONTENT_RANGE);
+        return Ok((StatusCode::RANGE_NOT_SATISFIABLE, headers).into_response());
+    }
+    let resp = resp.error_for_status()?;
+
+    let mut headers = HeaderMap::new();
+
+    // Get Content-Type from file path
+    if let Some(content_type) = mime_guess::from_path(file_path).first() {
+        headers.insert(
+            header::CONTENT_TYPE,
+            content_type.to_string().parse().unwrap(),
+        );
+    };
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    copy_header(resp.headers(), &mut headers, header::ETAG);
+    copy_header(resp.headers(), &mut headers, header::LAST_MODIFIED);
+
+    // The camera did the work
+    if resp.status() == StatusCode::PARTIAL_CONTENT {
+        copy_header(resp.headers(), &mut headers, header::CONTENT_RANGE);
+        copy_header(resp.headers(), &mut headers, header::CONTENT_LENGTH);
+        let body = StreamBody::new(resp.bytes_stream());
+
+        return Ok((StatusCode::PARTIAL_CONTENT, headers, body).into_response());
+    }
+
+    let len = resp.content_length();
+    match (range, len) {
+        (Some((_, range)), Some(len)) if if_range_matches(req_headers, resp.headers()) => {
+            let Some((start, end)) = range.resolve(len) else {
+                headers.insert(
+                    header::CONTENT_RANGE,
+                    format!("bytes */{len}").parse().unwrap(),
+                );
+                return Ok((StatusCode::RANGE_NOT_SATISFIABLE, headers).into_response());
+            };
+
+            headers.insert(
+                header::CONTENT_RANGE,
+                content_range(start, end, len).parse().unwrap(),
+            );
+            headers.insert(header::CONTENT_LENGTH, (end - start + 1).into());
+            let body = StreamBody::new(slice(resp.bytes_stream(), start, end - start + 1));
+
+            Ok((StatusCode::PARTIAL_CONTENT, headers, body).into_response())
+        }
+        _ => {
+            if let Some(len) = len {
+                headers.insert(header::CONTENT_LENGTH, len.into());
+            }
+            let body = StreamBody::new(resp.bytes_stream());
+
+            Ok((StatusCode::OK, headers, body).into_response())
+        }
+    }
+}
+
+/// Streams a dav file from the camera remuxed into fragmented MP4.
+///
+/// The size is unknown up front so ranges are not supported.
+pub async fn mp4(client: &reqwest::Client, file: IpcFile) -> Result<Response, reqwest::Error> {
+    let resp = client
+        .get(&file.url)
+        .header(header::COOKIE, file.cookie)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("video/mp4"));
+
+    let body = StreamBody::new(remux(resp.bytes_stream()));
+
+    Ok((StatusCode::OK, headers, body).into_response())
+}
+
+fn remux<S, E>(stream: S) -> impl Stream<Item = Result<Bytes, E>>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+{
+    stream::unfold(Some((stream, DavToMp4::new())), |state| async move {
+        let (mut stream, mut remux) = state?;
+        loop {
+            match stream.next().await {
+                Some(Ok(chunk)) => {
+                    let out = remux.push(&chunk);
+                    if !out.is_empty() {
+                        return Some((Ok(out.into()), Some((stream, remux))));
+                    }
+                }
+                Some(Err(err)) => return Some((Err(err), None)),
+                None => return Some((Ok(remux.finish().into()), None)),
+            }
+        }
+    })
+}
+
+fn copy_header(from: &HeaderMap, to: &mut HeaderMap, name: header::HeaderName) {
+    if let Some(value) = from.get(&name) {
+        to.insert(name, value.clone());
+    }
+}
+
+/// A range is only served when If-Range still matches the ETag or Last-Modified of the file.
+fn if_range_matches(req_headers: &HeaderMap, resp_headers: &HeaderMap) -> bool {
+    match req_headers.get(header::IF_RANGE) {
+        Some(if_range) => [header::ETAG, header::LAST_MODIFIED]
+            .iter()
+            .any(|name| resp_headers.get(name) == Some(if_range)),
+        None => true,
+    }
+}
+
+/// Skips start bytes of the stream and ends it after len bytes.
+fn slice<S, E>(stream: S, start: u64, len: u64) -> impl Stream<Item = Result<Bytes, E>>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+{
+    stream
+        .scan((start, len), |(skip, left), chunk| {
+            if *left == 0 {
+                return ready(None);
+            }
+
+            let chunk = chunk.map(|mut chunk| {
+                let skipped = (*skip).min(chunk.len() as u64);
+                *skip -= skipped;
+                chunk = chunk.slice(skipped as usize..);
+                chunk.truncate((*left).min(chunk.len() as u64) as usize);
+                *left -= chunk.len() as u64;
+                chunk
+            });
+
+            ready(Some(chunk))
+        })
+        .filter(|chunk| ready(!matches!(chunk, Ok(chunk) if chunk.is_empty())))
+}
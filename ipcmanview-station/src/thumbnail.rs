@@ -0,0 +1,406 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use axum::http::header;
+use futures_util::StreamExt;
+use ipcmanview::{
+    dahua_rpc::dav::{Parser, VideoCodec},
+    ipc::IpcFile,
+    models::CameraFile,
+    sqlx::SqlitePool,
+};
+use tokio::{io::AsyncWriteExt, process::Command, sync::Semaphore};
+
+/// Stop looking for a key frame after this many bytes.
+const MAX_SEARCH_LEN: usize = 8 * 1024 * 1024;
+/// Decoding is CPU heavy, a page of files should not start a decoder per file.
+const MAX_DECODES: usize = 2;
+const WIDTH: u32 = 600;
+/// Files that failed are not downloaded and decoded again until this passes.
+const FAILURE_TTL: Duration = Duration::from_secs(10 * 60);
+/// Scans delete the rows of files the camera has overwritten, their thumbnails go after this.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Thumbnails of video files on disk, keyed by the camera, path and start time of the file so a
+/// rescan that recreates the row reuses them.
+#[derive(Clone)]
+pub struct Thumbnails {
+    dir: PathBuf,
+    ffmpeg: String,
+    permits: Arc<Semaphore>,
+    failures: Arc<Mutex<HashMap<PathBuf, Instant>>>,
+}
+
+impl Thumbnails {
+    pub fn new(dir: impl Into<PathBuf>, ffmpeg: String) -> Thumbnails {
+        Thumbnails {
+            dir: dir.into(),
+            ffmpeg,
+            permits: Arc::new(Semaphore::new(MAX_DECODES)),
+            failures: Default::default(),
+        }
+    }
+
+    fn path(&self, file: &CameraFile) -> PathBuf {
+        let name: String = file
+            .file_path
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+
+        self.dir
+            .join(file.camera_id.to_string())
+            .join(format!("{}{name}.jpg", file.start_time.timestamp()))
+    }
+
+    fn failures(&self) -> std::sync::MutexGuard<'_, HashMap<PathBuf, Instant>> {
+        self.failures.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn failed_recently(&self, path: &Path) -> bool {
+        let mut failures = self.failures();
+        failures.retain(|_, failed| failed.elapsed() < FAILURE_TTL);
+        failures.contains_key(path)
+    }
+
+    /// JPEG of the first key frame of a dav file, it is created on first use.
+    pub async fn get(
+        &self,
+        client: &reqwest::Client,
+        file: &CameraFile,
+        ipc_file: IpcFile,
+    ) -> Result<Vec<u8>> {
+        let path = self.path(file);
+        if let Ok(jpeg) = tokio::fs::read(&path).await {
+            return Ok(jpeg);
+        }
+        if self.failed_recently(&path) {
+            bail!("Thumbnail failed recently.");
+        }
+
+        let _permit = self.permits.acquire().await?;
+        // Another request might have created it while waiting
+        if let Ok(jpeg) = tokio::fs::read(&path).await {
+            return Ok(jpeg);
+        }
+        if self.failed_recently(&path) {
+            bail!("Thumbnail failed recently.");
+        }
+
+        match self.create(client, &path, ipc_file).await {
+            Ok(jpeg) => Ok(jpeg),
+            Err(err) => {
+                self.failures().insert(path, Instant::now());
+                Err(err)
+            }
+        }
+    }
+
+    async fn create(
+        &self,
+        client: &reqwest::Client,
+        path: &Path,
+        file: IpcFile,
+    ) -> Result<Vec<u8>> {
+        let (codec, data) = keyframe(client, file).await?;
+        let jpeg = self.decode(codec, data).await?;
+        save(path, &jpeg).await?;
+
+        Ok(jpeg)
+    }
+
+    /// Removes thumbnails of files that are no longer in camera_files, such as files the camera
+    /// has overwritten or files of deleted cameras. Returns how many were removed.
+    pub async fn prune(&self, pool: &SqlitePool) -> Result<usize> {
+        let mut cameras = match tokio::fs::read_dir(&self.dir).await {
+            Ok(cameras) => cameras,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut removed = 0;
+        while let Some(camera) = cameras.next_entry().await? {
+            let Some(camera_id) = camera
+                .file_name()
+                .to_str()
+                .and_then(|name| name.parse::<i64>().ok())
+            else {
+                continue;
+            };
+            let keep: HashSet<PathBuf> = CameraFile::list_kind(pool, camera_id, "dav")
+                .await?
+                .iter()
+                .map(|file| self.path(file))
+                .collect();
+
+            let mut thumbnails = tokio::fs::read_dir(camera.path()).await?;
+            while let Some(thumbnail) = thumbnails.next_entry().await? {
+                let path = thumbnail.path();
+                // Temporary files belong to a thumbnail that is being saved
+                if path.extension().and_then(|e| e.to_str()) != Some("jpg") || keep.contains(&path)
+                {
+                    continue;
+                }
+                tokio::fs::remove_file(&path).await?;
+                removed += 1;
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// Prunes the cache every PRUNE_INTERVAL for as long as the station runs.
+    pub fn spawn_prune(&self, pool: SqlitePool) {
+        let thumbnails = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+            loop {
+                interval.tick().await;
+                match thumbnails.prune(&pool).await {
+                    Ok(0) => {}
+                    Ok(removed) => tracing::info!("Removed {removed} stale thumbnails."),
+                    Err(err) => tracing::warn!("Failed to prune thumbnails: {err:#}"),
+                }
+            }
+        });
+    }
+
+    /// Decodes the frame with ffmpeg in software, hardware decoders are never used.
+    async fn decode(&self, codec: VideoCodec, data: Vec<u8>) -> Result<Vec<u8>> {
+        let format = match codec {
+            VideoCodec::H264 => "h264",
+            VideoCodec::H265 => "hevc",
+            codec => bail!("Unsupported video codec {codec:?}."),
+        };
+
+        let mut child = Command::new(&self.ffmpeg)
+            .args(["-hide_banner", "-loglevel", "error", "-hwaccel", "none"])
+            .args(["-f", format, "-i", "pipe:0"])
+            .args(["-frames:v", "1", "-vf", &format!("scale={WIDTH}:-2")])
+            .args(["-f", "mjpeg", "pipe:1"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("Failed to run {}.", self.ffmpeg))?;
+
+        // Write in the background so a full stdout pipe can not block the write
+        let mut stdin = child.stdin.take().context("Failed to open ffmpeg stdin.")?;
+        let writer = tokio::spawn(async move {
+            // ffmpeg may exit before reading everything
+            let _ = stdin.write_all(&data).await;
+        });
+
+        let output = child.wait_with_output().await?;
+        writer.await?;
+        if !output.status.success() || output.stdout.is_empty() {
+            bail!(
+                "Failed to decode key frame: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        Ok(output.stdout)
+    }
+}
+
+/// Downloads the start of the file until the first key frame.
+async fn keyframe(client: &reqwest::Client, file: IpcFile) -> Result<(VideoCodec, Vec<u8>)> {
+    let mut stream = client
+        .get(&file.url)
+        .header(header::COOKIE, file.cookie)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes_stream();
+
+    let mut parser = Parser::new();
+    let mut codec = None;
+    let mut read = 0;
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        read += chunk.len();
+        parser.push(&chunk);
+
+        while let Some(frame) = parser.next_frame() {
+            codec = frame.info.video_codec.or(codec);
+            if let (true, Some(codec)) = (frame.is_key(), codec) {
+                return Ok((codec, frame.data));
+            }
+        }
+
+        if read > MAX_SEARCH_LEN {
+            break;
+        }
+    }
+
+    bail!("Failed to find a key frame.")
+}
+
+/// Writes to a temporary file first so a partial thumbnail is never served.
+async fn save(path: &Path, jpeg: &[u8]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let tmp = path.with_extension("jpg.tmp");
+    tokio::fs::write(&tmp, jpeg).await?;
+    tokio::fs::rename(&tmp, path).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use chrono::{TimeZone, Utc};
+    use ipcmanview::sqlx;
+
+    use super::*;
+
+    fn file(id: i64, file_path: &str) -> CameraFile {
+        let start_time = Utc.with_ymd_and_hms(2023, 6, 18, 10, 0, 0).unwrap();
+        CameraFile {
+            id,
+            camera_id: 1,
+            channel: 0,
+            file_path: file_path.to_string(),
+            kind: "dav".to_string(),
+            size: 0,
+            start_time,
+            end_time: start_time,
+            updated_at: start_time,
+            events: Default::default(),
+        }
+    }
+
+    fn thumbnails() -> Thumbnails {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "ipcmanview-thumbnails-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::SeqCst)
+        ));
+
+        Thumbnails::new(dir, "ffmpeg".to_string())
+    }
+
+    /// Nothing listens on port 1 of localhost.
+    fn unreachable() -> IpcFile {
+        IpcFile {
+            cookie: String::new(),
+            url: "http://127.0.0.1:1/file.dav".to_string(),
+            accept_invalid_certs: false,
+        }
+    }
+
+    #[test]
+    fn it_path() {
+        let thumbnails = thumbnails();
+        let a = file(
+            1,
+            "/mnt/dvr/2023-06-18/001/dav/10/10.00.00-10.05.00[R][0@0][0].dav",
+        );
+
+        // A row recreated by a rescan keeps its thumbnail
+        assert_eq!(thumbnails.path(&a), thumbnails.path(&file(2, &a.file_path)));
+        assert_ne!(
+            thumbnails.path(&a),
+            thumbnails.path(&file(1, "/mnt/dvr/other.dav"))
+        );
+        assert!(thumbnails.path(&a).starts_with(thumbnails.dir.join("1")));
+    }
+
+    #[tokio::test]
+    async fn it_get() {
+        let thumbnails = thumbnails();
+        let client = reqwest::Client::new();
+        let cached = file(1, "/cached.dav");
+        save(&thumbnails.path(&cached), b"jpeg").await.unwrap();
+
+        assert_eq!(
+            thumbnails
+                .get(&client, &cached, unreachable())
+                .await
+                .unwrap(),
+            b"jpeg"
+        );
+
+        // Failures are remembered instead of downloading the file again
+        let broken = file(2, "/broken.dav");
+        let err = thumbnails
+            .get(&client, &broken, unreachable())
+            .await
+            .unwrap_err();
+        assert!(!err.to_string().contains("recently"), "{err}");
+        let err = thumbnails
+            .get(&client, &broken, unreachable())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("recently"), "{err}");
+
+        // Until they expire
+        thumbnails
+            .failures()
+            .insert(thumbnails.path(&broken), Instant::now() - FAILURE_TTL);
+        assert!(!thumbnails.failed_recently(&thumbnails.path(&broken)));
+
+        tokio::fs::remove_dir_all(&thumbnails.dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_prune() {
+        let thumbnails = thumbnails();
+        let db = thumbnails.dir.with_extension("db");
+        let pool = ipcmanview::db::new(
+            &format!("sqlite://{}", db.display()),
+            &ipcmanview::secret::Secret::new("aXBjbWFudmlldyB0ZXN0IHNlY3JldCwgMzIgYnl0ZXM=")
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(thumbnails.prune(&pool).await.unwrap(), 0);
+
+        sqlx::query(
+            "INSERT INTO cameras (id, ip, username, password, scan_cursor) VALUES (1, 'cam', 'admin', '', 0)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let kept = file(1, "/kept.dav");
+        sqlx::query(
+            "INSERT INTO camera_files (camera_id, channel, file_path, kind, size, start_time, end_time, updated_at, events) VALUES (1, 0, ?, 'dav', 0, ?, ?, ?, '[]')",
+        )
+        .bind(&kept.file_path)
+        .bind(kept.start_time)
+        .bind(kept.end_time)
+        .bind(kept.updated_at)
+        .execute(&pool)
+        .await
+        .unwrap();
+        let overwritten = file(2, "/overwritten.dav");
+        let deleted_camera = CameraFile {
+            camera_id: 2,
+            ..file(3, "/kept.dav")
+        };
+        for file in [&kept, &overwritten, &deleted_camera] {
+            save(&thumbnails.path(file), b"jpeg").await.unwrap();
+        }
+
+        assert_eq!(thumbnails.prune(&pool).await.unwrap(), 2);
+        assert!(thumbnails.path(&kept).exists());
+        assert!(!thumbnails.path(&overwritten).exists());
+        assert!(!thumbnails.path(&deleted_camera).exists());
+
+        pool.close().await;
+        tokio::fs::remove_file(&db).await.unwrap();
+        tokio::fs::remove_dir_all(&thumbnails.dir).await.unwrap();
+    }
+}
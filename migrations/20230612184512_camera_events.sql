@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS camera_events (
+    id INTEGER PRIMARY KEY,
+    camera_id INTEGER NOT NULL,
+    code TEXT NOT NULL,
+    action TEXT NOT NULL, -- Start, Stop, Pulse
+    idx INTEGER NOT NULL,
+    start_time DATETIME NOT NULL,
+    end_time DATETIME,
+    data JSON NOT NULL,
+    FOREIGN KEY (camera_id) REFERENCES cameras (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS camera_events_start_time ON camera_events (start_time);
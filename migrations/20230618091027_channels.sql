@@ -0,0 +1,3 @@
+ALTER TABLE cameras ADD COLUMN channels INTEGER NOT NULL DEFAULT 1;
+
+ALTER TABLE camera_files ADD COLUMN channel INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,7 @@
+-- ip holds the host and the optional port of the camera, the scheme is stored separately
+ALTER TABLE cameras ADD COLUMN scheme TEXT NOT NULL DEFAULT 'http';
+ALTER TABLE cameras ADD COLUMN accept_invalid_certs BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Move schemes that were typed into ip
+UPDATE cameras SET scheme = 'https', ip = rtrim(substr(ip, 9), '/') WHERE ip LIKE 'https://%';
+UPDATE cameras SET ip = rtrim(substr(ip, 8), '/') WHERE ip LIKE 'http://%';
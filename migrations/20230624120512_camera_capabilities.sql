@@ -0,0 +1,8 @@
+-- Methods and services from system.listMethod and system.listService, empty when unknown
+CREATE TABLE IF NOT EXISTS camera_capabilities (
+    id INTEGER PRIMARY KEY,
+    methods JSON NOT NULL DEFAULT '[]',
+    services JSON NOT NULL DEFAULT '[]',
+    updated_at DATETIME NOT NULL DEFAULT (DATETIME(0, 'unixepoch')),
+    FOREIGN KEY (id) REFERENCES cameras (id) ON DELETE CASCADE
+);
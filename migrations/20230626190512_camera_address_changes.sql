@@ -0,0 +1,12 @@
+-- Cameras found at a new address by serial number, usually after a DHCP lease changed
+CREATE TABLE IF NOT EXISTS camera_address_changes (
+    id INTEGER PRIMARY KEY,
+    camera_id INTEGER NOT NULL,
+    sn TEXT NOT NULL,
+    old_ip TEXT NOT NULL,
+    new_ip TEXT NOT NULL,
+    created_at DATETIME NOT NULL,
+    FOREIGN KEY (camera_id) REFERENCES cameras (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS camera_details_sn ON camera_details (sn);
@@ -0,0 +1,6 @@
+-- Camera passwords are sealed with the station secret, the existing plaintext rows are sealed by
+-- the station the first time it opens the database since there is no secret in SQL
+CREATE TABLE IF NOT EXISTS secret_checks (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    value TEXT NOT NULL
+);
@@ -0,0 +1,16 @@
+-- Username and password shared by many cameras, the password is sealed with the station secret
+CREATE TABLE IF NOT EXISTS credentials (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    username TEXT NOT NULL,
+    password TEXT NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Where the credentials of a camera come from, literal uses the username and password columns,
+-- shared uses credential_id, env and file read the password from credential_ref
+ALTER TABLE cameras ADD COLUMN credential_kind TEXT NOT NULL DEFAULT 'literal';
+ALTER TABLE cameras ADD COLUMN credential_id INTEGER REFERENCES credentials (id) ON DELETE RESTRICT;
+ALTER TABLE cameras ADD COLUMN credential_ref TEXT;
+
+CREATE INDEX IF NOT EXISTS cameras_credential_id ON cameras (credential_id);
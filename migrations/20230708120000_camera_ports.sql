@@ -0,0 +1,39 @@
+-- The port moves out of ip into its own column so cameras behind one address can use different
+-- ports. SQLite can not drop the unique constraint of ip, the table is rebuilt instead which is why
+-- db::new runs migrations with foreign keys off.
+CREATE TABLE cameras_new (
+    id INTEGER PRIMARY KEY,
+    ip TEXT NOT NULL,
+    port INTEGER,
+    username TEXT NOT NULL,
+    password TEXT NOT NULL,
+    scan_cursor DATETIME NOT NULL,
+    refreshed_at DATETIME NOT NULL DEFAULT (DATETIME(0, 'unixepoch')),
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    channels INTEGER NOT NULL DEFAULT 1,
+    scheme TEXT NOT NULL DEFAULT 'http',
+    accept_invalid_certs BOOLEAN NOT NULL DEFAULT FALSE,
+    credential_kind TEXT NOT NULL DEFAULT 'literal',
+    credential_id INTEGER REFERENCES credentials (id) ON DELETE RESTRICT,
+    credential_ref TEXT
+);
+
+INSERT INTO cameras_new
+(id, ip, username, password, scan_cursor, refreshed_at, created_at, channels, scheme, accept_invalid_certs, credential_kind, credential_id, credential_ref)
+SELECT id, ip, username, password, scan_cursor, refreshed_at, created_at, channels, scheme, accept_invalid_certs, credential_kind, credential_id, credential_ref
+FROM cameras;
+
+-- [::1]:8080
+UPDATE cameras_new
+SET port = CAST(substr(ip, instr(ip, ']:') + 2) AS INTEGER), ip = substr(ip, 1, instr(ip, ']'))
+WHERE ip LIKE '[%]:%';
+-- host:8080, bare IPv6 addresses have more than one colon
+UPDATE cameras_new
+SET port = CAST(substr(ip, instr(ip, ':') + 1) AS INTEGER), ip = substr(ip, 1, instr(ip, ':') - 1)
+WHERE ip NOT LIKE '[%' AND instr(ip, ':') > 0 AND instr(substr(ip, instr(ip, ':') + 1), ':') = 0;
+
+DROP TABLE cameras;
+ALTER TABLE cameras_new RENAME TO cameras;
+
+CREATE INDEX IF NOT EXISTS cameras_credential_id ON cameras (credential_id);
+CREATE UNIQUE INDEX IF NOT EXISTS cameras_address ON cameras (ip, coalesce(port, 0));
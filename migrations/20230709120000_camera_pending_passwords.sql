@@ -0,0 +1,3 @@
+-- Password that was sent to the camera but is not confirmed yet, it is kept so a failure after the
+-- camera changed its password does not lose the new one
+ALTER TABLE cameras ADD COLUMN pending_password TEXT;
@@ -0,0 +1,25 @@
+-- Files on different channels of a NVR can share a path and a start time, so both unique
+-- constraints include the channel. SQLite can not change a unique constraint, the table is rebuilt.
+CREATE TABLE camera_files_new (
+    id INTEGER PRIMARY KEY,
+    camera_id INTEGER NOT NULL,
+    channel INTEGER NOT NULL DEFAULT 0,
+    file_path TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    start_time DATETIME NOT NULL,
+    end_time DATETIME NOT NULL,
+    updated_at DATETIME NOT NULL,
+    events JSON NOT NULL,
+    UNIQUE (camera_id, channel, file_path),
+    UNIQUE (camera_id, channel, start_time),
+    FOREIGN KEY (camera_id) REFERENCES cameras (id) ON DELETE CASCADE
+);
+
+INSERT INTO camera_files_new
+(id, camera_id, channel, file_path, kind, size, start_time, end_time, updated_at, events)
+SELECT id, camera_id, channel, file_path, kind, size, start_time, end_time, updated_at, events
+FROM camera_files;
+
+DROP TABLE camera_files;
+ALTER TABLE camera_files_new RENAME TO camera_files;
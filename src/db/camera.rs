@@ -1,34 +1,45 @@
-use anyhow::{Context, Result};
-use chrono::Utc;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
 use sqlx::{QueryBuilder, Sqlite, SqlitePool};
 
 use crate::{
     models::{
-        Camera, CameraDetail, CameraFile, CameraFileQuery, CameraFileQueryCursor,
-        CameraFileQueryFilter, CameraFileQueryResult, CameraLicense, CameraSoftware,
-        CreateCameraRequest, ICamera, UpdateCameraRequest,
+        Camera, CameraAddressChange, CameraCapabilities, CameraDetail, CameraFile, CameraFileQuery,
+        CameraFileQueryCursor, CameraFileQueryFilter, CameraFileQueryResult, CameraLicense,
+        CameraScheme, CameraSoftware, CreateCameraRequest, CredentialSource, DiscoveredCamera,
+        ICamera, UpdateCameraRequest,
     },
     scan::Scan,
+    secret::Secret,
 };
 
-use super::utils::CountRow;
-use super::NotFound;
+use super::utils::{query_cursor, CountRow};
+use super::{Conflict, NotFound};
 
 impl CreateCameraRequest {
-    pub(crate) async fn create_db(self, pool: &SqlitePool) -> Result<i64> {
+    pub(crate) async fn create_db(self, pool: &SqlitePool, secret: &Secret) -> Result<i64> {
+        let (host, port) = self.address()?;
+        let credential_kind = self.credential.kind();
+        let credential_id = self.credential.credential_id();
+        let credential_ref = self.credential.credential_ref();
         let mut pool = pool.begin().await?;
 
         let cursor = Scan::cursor();
         let camera_id = sqlx::query!(
             r#"
             INSERT INTO cameras
-            (ip, username, password, scan_cursor)
+            (ip, port, scheme, accept_invalid_certs, username, password, credential_kind, credential_id, credential_ref, scan_cursor)
             VALUES
-            (?, ?, ?, ?)
+            (?, ?, ?, ?, ?, '', ?, ?, ?, ?)
             "#,
-            self.ip,
+            host,
+            port,
+            self.scheme,
+            self.accept_invalid_certs,
             self.username,
-            self.password,
+            credential_kind,
+            credential_id,
+            credential_ref,
             cursor
         )
         .execute(&mut *pool)
@@ -36,6 +47,17 @@ impl CreateCameraRequest {
         .context("Failed to create camera.")?
         .last_insert_rowid();
 
+        // The password is sealed for the id of the row
+        let password = secret.seal(&self.password, "cameras", camera_id);
+        sqlx::query!(
+            "UPDATE cameras SET password = ? WHERE id = ?",
+            password,
+            camera_id
+        )
+        .execute(&mut *pool)
+        .await
+        .context("Failed to seal camera's password.")?;
+
         sqlx::query!(
             r#"
             INSERT INTO camera_details
@@ -69,25 +91,70 @@ impl CreateCameraRequest {
 }
 
 impl UpdateCameraRequest {
-    pub(crate) async fn update_db(self, pool: &SqlitePool) -> Result<()> {
+    pub(crate) async fn update_db(self, pool: &SqlitePool, secret: &Secret) -> Result<()> {
+        let (host, port) = self.address()?;
+        let set_port = port.is_some();
+        let port = port.flatten();
+        let password = self
+            .password
+            .as_deref()
+            .map(|password| secret.seal(password, "cameras", self.id));
+
+        let mut pool = pool.begin().await?;
+
         sqlx::query!(
             r#"
             UPDATE cameras SET
             ip = coalesce(?, ip),
+            port = CASE WHEN ? THEN ? ELSE port END,
+            scheme = coalesce(?, scheme),
+            accept_invalid_certs = coalesce(?, accept_invalid_certs),
             username = coalesce(?, username),
             password = coalesce(?, password)
             WHERE id = ?
             "#,
-            self.ip,
+            host,
+            set_port,
+            port,
+            self.scheme,
+            self.accept_invalid_certs,
             self.username,
-            self.password,
+            password,
             self.id,
         )
-        .execute(pool)
+        .execute(&mut *pool)
         .await
         .with_context(|| format!("Failed to update camera with id {}.", self.id))
         .map(NotFound::check_query)?
-        .with_context(|| format!("Failed to find camera with id {}.", self.id))
+        .with_context(|| format!("Failed to find camera with id {}.", self.id))?;
+
+        if let Some(credential) = &self.credential {
+            let credential_kind = credential.kind();
+            let credential_id = credential.credential_id();
+            let credential_ref = credential.credential_ref();
+            sqlx::query!(
+                r#"
+                UPDATE cameras SET
+                credential_kind = ?,
+                credential_id = ?,
+                credential_ref = ?
+                WHERE id = ?
+                "#,
+                credential_kind,
+                credential_id,
+                credential_ref,
+                self.id,
+            )
+            .execute(&mut *pool)
+            .await
+            .with_context(|| {
+                format!("Failed to update credential source of camera {}.", self.id)
+            })?;
+        }
+
+        pool.commit().await?;
+
+        Ok(())
     }
 }
 
@@ -96,7 +163,7 @@ impl Camera {
         sqlx::query!(r#"SELECT COUNT(id) as count FROM cameras "#)
             .fetch_one(pool)
             .await
-            .with_context(|| format!("Failed to count cameras."))
+            .with_context(|| "Failed to count cameras.".to_string())
             .map(|c| c.count)
     }
 
@@ -104,20 +171,20 @@ impl Camera {
         sqlx::query_as_unchecked!(
             Self,
             r#"
-            SELECT id, ip, username, refreshed_at, created_at
+            SELECT id, ip, port, scheme, accept_invalid_certs, username, channels, refreshed_at, created_at
             FROM cameras
             "#
         )
         .fetch_all(pool)
         .await
-        .with_context(|| format!("Failed to list cameras."))
+        .with_context(|| "Failed to list cameras.".to_string())
     }
 
     pub async fn find(pool: &SqlitePool, camera_id: i64) -> Result<Self> {
         sqlx::query_as_unchecked!(
             Self,
             r#"
-            SELECT id, ip, username, refreshed_at, created_at
+            SELECT id, ip, port, scheme, accept_invalid_certs, username, channels, refreshed_at, created_at
             FROM cameras
             WHERE id = ?
             "#,
@@ -158,35 +225,188 @@ impl Camera {
         .map(NotFound::check_query)?
         .with_context(|| format!("Failed to find camera with id {camera_id}."))
     }
+
+    /// Stores the new password of a rotation as pending, only one rotation of a camera can be
+    /// pending at a time.
+    pub(crate) async fn begin_password_rotation_db(
+        pool: &SqlitePool,
+        secret: &Secret,
+        camera_id: i64,
+        password: &str,
+    ) -> Result<()> {
+        let password = secret.seal(password, "cameras", camera_id);
+        let updated = sqlx::query!(
+            "UPDATE cameras SET pending_password = ? WHERE id = ? AND pending_password IS NULL",
+            password,
+            camera_id
+        )
+        .execute(pool)
+        .await
+        .with_context(|| {
+            format!("Failed to update pending password for camera with id {camera_id}.")
+        })?
+        .rows_affected();
+        if updated > 0 {
+            return Ok(());
+        }
+
+        Self::find(pool, camera_id).await?;
+        Err(Conflict).with_context(|| {
+            format!("Camera with id {camera_id} has a pending password that has to be confirmed or discarded first.")
+        })
+    }
+
+    pub(crate) async fn pending_password_db(
+        pool: &SqlitePool,
+        secret: &Secret,
+        camera_id: i64,
+    ) -> Result<Option<String>> {
+        sqlx::query_scalar!(
+            "SELECT pending_password FROM cameras WHERE id = ?",
+            camera_id
+        )
+        .fetch_optional(pool)
+        .await
+        .with_context(|| format!("Failed to find pending password of camera with id {camera_id}."))?
+        .ok_or(NotFound)
+        .with_context(|| format!("Failed to find camera with id {camera_id}."))?
+        .map(|password| secret.open(&password, "cameras", camera_id))
+        .transpose()
+    }
+
+    pub(crate) async fn discard_pending_password_db(
+        pool: &SqlitePool,
+        camera_id: i64,
+    ) -> Result<()> {
+        sqlx::query!(
+            "UPDATE cameras SET pending_password = NULL WHERE id = ? AND pending_password IS NOT NULL",
+            camera_id
+        )
+        .execute(pool)
+        .await
+        .with_context(|| format!("Failed to discard pending password of camera with id {camera_id}."))
+        .map(NotFound::check_query)?
+        .with_context(|| format!("Failed to find pending password of camera with id {camera_id}."))
+    }
+
+    /// Makes the pending password the password of the camera.
+    pub(crate) async fn confirm_password_db(pool: &SqlitePool, camera_id: i64) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE cameras SET password = pending_password, pending_password = NULL
+            WHERE id = ? AND pending_password IS NOT NULL
+            "#,
+            camera_id
+        )
+        .execute(pool)
+        .await
+        .with_context(|| format!("Failed to confirm password for camera with id {camera_id}."))
+        .map(NotFound::check_query)?
+        .with_context(|| format!("Failed to find pending password of camera with id {camera_id}."))
+    }
+}
+
+/// Camera joined with its shared credential, the passwords are sealed.
+struct ICameraRow {
+    id: i64,
+    ip: String,
+    port: Option<u16>,
+    scheme: CameraScheme,
+    accept_invalid_certs: bool,
+    username: String,
+    password: String,
+    credential_kind: String,
+    credential_id: Option<i64>,
+    credential_ref: Option<String>,
+    shared_username: Option<String>,
+    shared_password: Option<String>,
+}
+
+impl ICameraRow {
+    /// Opens the password of the camera or of its shared credential.
+    fn open(self, secret: &Secret) -> Result<ICamera> {
+        let credential = CredentialSource::from_columns(
+            &self.credential_kind,
+            self.credential_id,
+            self.credential_ref,
+        )?;
+        let (username, password) = match (&credential, self.shared_username, self.shared_password) {
+            (CredentialSource::Literal, _, _) => (
+                self.username,
+                secret.open(&self.password, "cameras", self.id)?,
+            ),
+            (CredentialSource::Shared { credential_id }, Some(username), Some(password)) => (
+                username,
+                secret.open(&password, "credentials", *credential_id)?,
+            ),
+            (CredentialSource::Shared { .. }, _, _) => bail!("Shared credential is missing."),
+            // Resolved when the client is built
+            _ => (self.username, String::new()),
+        };
+
+        Ok(ICamera {
+            id: self.id,
+            ip: self.ip,
+            port: self.port,
+            scheme: self.scheme,
+            accept_invalid_certs: self.accept_invalid_certs,
+            username,
+            password,
+            credential,
+        })
+    }
 }
 
 impl ICamera {
-    pub async fn find_optional(pool: &SqlitePool, camera_id: i64) -> Result<Option<Self>> {
+    pub async fn find_optional(
+        pool: &SqlitePool,
+        secret: &Secret,
+        camera_id: i64,
+    ) -> Result<Option<Self>> {
         sqlx::query_as!(
-            Self,
+            ICameraRow,
             r#"
-            SELECT id, ip, username, password
+            SELECT cameras.id, ip, port as "port: u16", scheme as "scheme: CameraScheme", accept_invalid_certs,
+            cameras.username, cameras.password, credential_kind, credential_id, credential_ref,
+            credentials.username as "shared_username?", credentials.password as "shared_password?"
             FROM cameras
-            WHERE id = ?
+            LEFT JOIN credentials ON credentials.id = cameras.credential_id
+            WHERE cameras.id = ?
             "#,
             camera_id,
         )
         .fetch_optional(pool)
         .await
-        .with_context(|| format!("Failed to find internal camera with id {camera_id}."))
+        .with_context(|| format!("Failed to find internal camera with id {camera_id}."))?
+        .map(|row| {
+            row.open(secret).with_context(|| {
+                format!("Failed to open credentials of camera with id {camera_id}.")
+            })
+        })
+        .transpose()
     }
 
-    pub async fn list(pool: &SqlitePool) -> Result<Vec<Self>> {
+    pub async fn list(pool: &SqlitePool, secret: &Secret) -> Result<Vec<Self>> {
         sqlx::query_as!(
-            Self,
+            ICameraRow,
             r#"
-            SELECT id, ip, username, password
+            SELECT cameras.id, ip, port as "port: u16", scheme as "scheme: CameraScheme", accept_invalid_certs,
+            cameras.username, cameras.password, credential_kind, credential_id, credential_ref,
+            credentials.username as "shared_username?", credentials.password as "shared_password?"
             FROM cameras
+            LEFT JOIN credentials ON credentials.id = cameras.credential_id
             "#
         )
         .fetch_all(pool)
         .await
-        .with_context(|| format!("Failed to list internal cameras."))
+        .with_context(|| "Failed to list internal cameras.".to_string())?
+        .into_iter()
+        .map(|row| {
+            let id = row.id;
+            row.open(secret)
+                .with_context(|| format!("Failed to open credentials of camera with id {id}."))
+        })
+        .collect()
     }
 }
 
@@ -209,6 +429,138 @@ impl CameraDetail {
     }
 }
 
+impl CameraAddressChange {
+    /// Moves the camera to the new address and records the change.
+    pub(crate) async fn create_db(
+        pool: &SqlitePool,
+        camera_id: i64,
+        sn: &str,
+        old_ip: &str,
+        new_ip: &str,
+    ) -> Result<Self> {
+        let mut tx = pool.begin().await?;
+        let created_at = Utc::now();
+
+        sqlx::query!(
+            r#"
+            UPDATE cameras SET ip = ?
+            WHERE id = ? AND ip = ?
+            "#,
+            new_ip,
+            camera_id,
+            old_ip,
+        )
+        .execute(&mut *tx)
+        .await
+        .with_context(|| format!("Failed to update address of camera with id {camera_id}."))
+        .map(NotFound::check_query)?
+        .with_context(|| format!("Failed to find camera with id {camera_id}."))?;
+
+        let id = sqlx::query!(
+            r#"
+            INSERT INTO camera_address_changes
+            (camera_id, sn, old_ip, new_ip, created_at)
+            VALUES
+            (?, ?, ?, ?, ?)
+            "#,
+            camera_id,
+            sn,
+            old_ip,
+            new_ip,
+            created_at,
+        )
+        .execute(&mut *tx)
+        .await
+        .context("Failed to create camera address change.")?
+        .last_insert_rowid();
+
+        tx.commit().await?;
+
+        Ok(CameraAddressChange {
+            id,
+            camera_id,
+            sn: sn.to_string(),
+            old_ip: old_ip.to_string(),
+            new_ip: new_ip.to_string(),
+            created_at,
+        })
+    }
+
+    pub async fn list(pool: &SqlitePool, camera_id: i64) -> Result<Vec<Self>> {
+        sqlx::query_as_unchecked!(
+            Self,
+            r#"
+            SELECT id, camera_id, sn, old_ip, new_ip, created_at
+            FROM camera_address_changes
+            WHERE camera_id = ?
+            ORDER BY id DESC
+            "#,
+            camera_id,
+        )
+        .fetch_all(pool)
+        .await
+        .with_context(|| format!("Failed to list address changes of camera with id {camera_id}."))
+    }
+}
+
+impl DiscoveredCamera {
+    /// Sets camera_id of devices that are already cameras.
+    pub(crate) async fn link_db(pool: &SqlitePool, devices: &mut [DiscoveredCamera]) -> Result<()> {
+        let cameras = sqlx::query!(
+            r#"
+            SELECT cameras.id AS "id!", cameras.ip, camera_details.sn AS "sn?"
+            FROM cameras
+            LEFT JOIN camera_details ON camera_details.id = cameras.id
+            "#
+        )
+        .fetch_all(pool)
+        .await
+        .context("Failed to list cameras.")?;
+
+        for device in devices {
+            device.camera_id = cameras
+                .iter()
+                .find(|camera| {
+                    camera.ip == device.ip
+                        || camera
+                            .sn
+                            .as_ref()
+                            .is_some_and(|sn| !sn.is_empty() && *sn == device.sn)
+                })
+                .map(|camera| camera.id);
+        }
+
+        Ok(())
+    }
+}
+
+impl CameraCapabilities {
+    pub async fn find(pool: &SqlitePool, camera_id: i64) -> Result<Self> {
+        sqlx::query_as!(
+            Self,
+            r#"
+            SELECT
+                coalesce(camera_capabilities.methods, '[]') AS "methods!: sqlx::types::Json<Vec<String>>",
+                coalesce(camera_capabilities.services, '[]') AS "services!: sqlx::types::Json<Vec<String>>",
+                coalesce(camera_capabilities.updated_at, DATETIME(0, 'unixepoch')) AS "updated_at!: DateTime<Utc>"
+            FROM cameras
+            LEFT JOIN camera_capabilities ON camera_capabilities.id = cameras.id
+            WHERE cameras.id = ?
+            "#,
+            camera_id,
+        )
+        .fetch_optional(pool)
+        .await
+        .with_context(|| {
+            format!("Failed to find camera's capabilities with camera id {camera_id}.")
+        })?
+        .ok_or(NotFound)
+        .with_context(|| {
+            format!("Failed to find camera's capabilities with camera id {camera_id}.")
+        })
+    }
+}
+
 impl CameraSoftware {
     pub async fn find(pool: &SqlitePool, camera_id: i64) -> Result<Self> {
         sqlx::query_as!(
@@ -275,16 +627,25 @@ impl<'a> CameraFileFilter<'a> for QueryBuilder<'a, Sqlite> {
             self.push_bind(end);
         }
 
-        if filter.camera_ids.len() > 0 {
+        if !filter.camera_ids.is_empty() {
             self.push(" AND camera_id in (");
             let mut sep = self.separated(",");
             for id in filter.camera_ids.iter() {
-                sep.push_bind(id.clone());
+                sep.push_bind(*id);
             }
             sep.push_unseparated(")");
         }
 
-        if filter.kinds.len() > 0 {
+        if !filter.channels.is_empty() {
+            self.push(" AND channel in (");
+            let mut sep = self.separated(",");
+            for channel in filter.channels.iter() {
+                sep.push_bind(*channel);
+            }
+            sep.push_unseparated(")");
+        }
+
+        if !filter.kinds.is_empty() {
             self.push(" AND kind in (");
             let mut sep = self.separated(",");
             for kind in filter.kinds.iter() {
@@ -293,7 +654,7 @@ impl<'a> CameraFileFilter<'a> for QueryBuilder<'a, Sqlite> {
             sep.push_unseparated(")");
         }
 
-        if filter.events.len() > 0 {
+        if !filter.events.is_empty() {
             self.push(" AND (");
             for (idx, event) in filter.events.iter().enumerate() {
                 if idx != 0 {
@@ -311,9 +672,31 @@ impl<'a> CameraFileFilter<'a> for QueryBuilder<'a, Sqlite> {
 }
 
 impl CameraFile {
+    pub async fn find(pool: &SqlitePool, id: i64) -> Result<Self> {
+        sqlx::query_as::<_, CameraFile>("SELECT * FROM camera_files WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+            .with_context(|| format!("Failed to find camera file with id {id}."))?
+            .ok_or(NotFound)
+            .with_context(|| format!("Failed to find camera file with id {id}."))
+    }
+
+    /// Every file of a kind on the camera.
+    pub async fn list_kind(pool: &SqlitePool, camera_id: i64, kind: &str) -> Result<Vec<Self>> {
+        sqlx::query_as::<_, CameraFile>(
+            "SELECT * FROM camera_files WHERE camera_id = ? AND kind = ? ORDER BY start_time",
+        )
+        .bind(camera_id)
+        .bind(kind)
+        .fetch_all(pool)
+        .await
+        .with_context(|| format!("Failed to list {kind} files of camera {camera_id}."))
+    }
+
     pub async fn total(pool: &SqlitePool, filter: &CameraFileQueryFilter) -> Result<i32> {
         let count = QueryBuilder::new("SELECT COUNT(id) AS count FROM camera_files")
-            .push_camera_file_filter(&filter)
+            .push_camera_file_filter(filter)
             .build_query_as::<CountRow>()
             .fetch_one(pool)
             .await
@@ -327,101 +710,17 @@ impl CameraFile {
         pool: &SqlitePool,
         query: CameraFileQuery<'_>,
     ) -> Result<CameraFileQueryResult> {
-        let mut has_after = false;
-        let mut has_before = false;
-
-        let limit = query.limit + 1;
-        let mut qb =
-            QueryBuilder::new("SELECT * FROM camera_files").push_camera_file_filter(query.filter);
-        let files = match query.cursor {
-            CameraFileQueryCursor::After((id, time)) => {
-                let mut files = qb
-                    .push(" AND (start_time < ")
-                    .push_bind(time)
-                    .push(" OR (start_time = ")
-                    .push_bind(time)
-                    .push(" AND camera_id < ")
-                    .push_bind(id)
-                    .push(")) ORDER BY start_time DESC, camera_id DESC LIMIT ")
-                    .push_bind(limit)
-                    .build_query_as::<CameraFile>()
-                    .fetch_all(pool)
-                    .await?;
-
-                if files.len() == limit as usize {
-                    has_after = true;
-                    files.pop();
-                }
-
-                has_before = QueryBuilder::new("SELECT id FROM camera_files")
-                    .push_camera_file_filter(query.filter)
-                    .push(" AND (start_time > ")
-                    .push_bind(time)
-                    .push(" OR (start_time = ")
-                    .push_bind(time)
-                    .push(" AND camera_id > ")
-                    .push_bind(id)
-                    .push(")) LIMIT 1")
-                    .build()
-                    .fetch_optional(pool)
-                    .await?
-                    .is_some();
-
-                files
-            }
-            CameraFileQueryCursor::Before((id, time)) => {
-                let mut files = qb
-                    .push(" AND (start_time > ")
-                    .push_bind(time)
-                    .push(" OR (start_time = ")
-                    .push_bind(time)
-                    .push(" AND camera_id > ")
-                    .push_bind(id)
-                    .push(")) ORDER BY start_time ASC, camera_id ASC LIMIT ")
-                    .push_bind(limit)
-                    .build_query_as::<CameraFile>()
-                    .fetch_all(pool)
-                    .await?;
-
-                if files.len() == limit as usize {
-                    has_before = true;
-                    files.pop();
-                }
-
-                has_after = QueryBuilder::new("SELECT id FROM camera_files")
-                    .push_camera_file_filter(query.filter)
-                    .push(" AND (start_time < ")
-                    .push_bind(time)
-                    .push(" OR (start_time = ")
-                    .push_bind(time)
-                    .push(" AND camera_id < ")
-                    .push_bind(id)
-                    .push(")) LIMIT 1")
-                    .build()
-                    .fetch_optional(pool)
-                    .await?
-                    .is_some();
-
-                files.reverse();
-
-                files
-            }
-            CameraFileQueryCursor::None => {
-                let mut files = qb
-                    .push(" ORDER BY start_time DESC, camera_id DESC LIMIT ")
-                    .push_bind(limit)
-                    .build_query_as::<CameraFile>()
-                    .fetch_all(pool)
-                    .await?;
-
-                if files.len() == limit as usize {
-                    has_after = true;
-                    files.pop();
-                }
-
-                files
-            }
-        };
+        let page = query_cursor::<CameraFile>(
+            pool,
+            "camera_files",
+            "camera_id",
+            query.cursor,
+            query.limit,
+            |qb| qb.push_camera_file_filter(query.filter),
+        )
+        .await
+        .context("Failed to query camera files.")?;
+        let files = page.rows;
 
         let before = match files.first() {
             Some(first) => CameraFileQueryCursor::to(first.camera_id, first.start_time),
@@ -437,9 +736,9 @@ impl CameraFile {
 
         Ok(CameraFileQueryResult {
             files,
-            has_before,
+            has_before: page.has_before,
             before,
-            has_after,
+            has_after: page.has_after,
             after,
             count,
         })
@@ -0,0 +1,179 @@
+use anyhow::{bail, Context, Result};
+use sqlx::SqlitePool;
+
+use crate::{
+    models::{CreateCredentialRequest, Credential, CredentialSource, UpdateCredentialRequest},
+    secret::Secret,
+};
+
+use super::{Conflict, NotFound};
+
+impl CredentialSource {
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            CredentialSource::Literal => "literal",
+            CredentialSource::Shared { .. } => "shared",
+            CredentialSource::Env { .. } => "env",
+            CredentialSource::File { .. } => "file",
+        }
+    }
+
+    pub(crate) fn credential_id(&self) -> Option<i64> {
+        match self {
+            CredentialSource::Shared { credential_id } => Some(*credential_id),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn credential_ref(&self) -> Option<&str> {
+        match self {
+            CredentialSource::Env { name } => Some(name),
+            CredentialSource::File { path } => Some(path),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn from_columns(
+        kind: &str,
+        credential_id: Option<i64>,
+        credential_ref: Option<String>,
+    ) -> Result<Self> {
+        Ok(match (kind, credential_id, credential_ref) {
+            ("literal", _, _) => CredentialSource::Literal,
+            ("shared", Some(credential_id), _) => CredentialSource::Shared { credential_id },
+            ("env", _, Some(name)) => CredentialSource::Env { name },
+            ("file", _, Some(path)) => CredentialSource::File { path },
+            (kind, _, _) => bail!("Invalid credential source '{kind}'."),
+        })
+    }
+
+    pub async fn find(pool: &SqlitePool, camera_id: i64) -> Result<Self> {
+        let row = sqlx::query!(
+            "SELECT credential_kind, credential_id, credential_ref FROM cameras WHERE id = ?",
+            camera_id
+        )
+        .fetch_optional(pool)
+        .await
+        .with_context(|| format!("Failed to find credential source of camera {camera_id}."))?
+        .ok_or(NotFound)
+        .with_context(|| format!("Failed to find camera with id {camera_id}."))?;
+
+        Self::from_columns(&row.credential_kind, row.credential_id, row.credential_ref)
+    }
+}
+
+impl CreateCredentialRequest {
+    pub(crate) async fn create_db(self, pool: &SqlitePool, secret: &Secret) -> Result<i64> {
+        let mut tx = pool.begin().await?;
+
+        // The password is sealed for the id of the row
+        let id = sqlx::query!(
+            "INSERT INTO credentials (name, username, password) VALUES (?, ?, '')",
+            self.name,
+            self.username,
+        )
+        .execute(&mut *tx)
+        .await
+        .context("Failed to create credential.")?
+        .last_insert_rowid();
+        let password = secret.seal(&self.password, "credentials", id);
+        sqlx::query!(
+            "UPDATE credentials SET password = ? WHERE id = ?",
+            password,
+            id
+        )
+        .execute(&mut *tx)
+        .await
+        .context("Failed to seal password of credential.")?;
+
+        tx.commit().await?;
+
+        Ok(id)
+    }
+}
+
+impl UpdateCredentialRequest {
+    pub(crate) async fn update_db(self, pool: &SqlitePool, secret: &Secret) -> Result<()> {
+        let password = self
+            .password
+            .as_deref()
+            .map(|password| secret.seal(password, "credentials", self.id));
+        sqlx::query!(
+            r#"
+            UPDATE credentials SET
+            name = coalesce(?, name),
+            username = coalesce(?, username),
+            password = coalesce(?, password)
+            WHERE id = ?
+            "#,
+            self.name,
+            self.username,
+            password,
+            self.id,
+        )
+        .execute(pool)
+        .await
+        .with_context(|| format!("Failed to update credential with id {}.", self.id))
+        .map(NotFound::check_query)?
+        .with_context(|| format!("Failed to find credential with id {}.", self.id))
+    }
+}
+
+impl Credential {
+    pub async fn list(pool: &SqlitePool) -> Result<Vec<Self>> {
+        sqlx::query_as_unchecked!(
+            Self,
+            "SELECT id, name, username, created_at FROM credentials ORDER BY name"
+        )
+        .fetch_all(pool)
+        .await
+        .context("Failed to list credentials.")
+    }
+
+    pub async fn find(pool: &SqlitePool, id: i64) -> Result<Self> {
+        sqlx::query_as_unchecked!(
+            Self,
+            "SELECT id, name, username, created_at FROM credentials WHERE id = ?",
+            id
+        )
+        .fetch_optional(pool)
+        .await
+        .with_context(|| format!("Failed to find credential with id {id}."))?
+        .ok_or(NotFound)
+        .with_context(|| format!("Failed to find credential with id {id}."))
+    }
+
+    /// Fails with a conflict while cameras still use the credential.
+    pub(crate) async fn delete_db(pool: &SqlitePool, id: i64) -> Result<()> {
+        // Not left to the foreign key, sqlx steps a failed statement again the next time the
+        // connection is used and the credential would be deleted once no camera uses it
+        let deleted = sqlx::query!(
+            r#"
+            DELETE FROM credentials
+            WHERE id = ? AND NOT EXISTS (SELECT 1 FROM cameras WHERE credential_id = credentials.id)
+            "#,
+            id
+        )
+        .execute(pool)
+        .await
+        .with_context(|| format!("Failed to delete credential with id {id}."))?
+        .rows_affected();
+        if deleted > 0 {
+            return Ok(());
+        }
+
+        Self::find(pool, id).await?;
+        Err(Conflict).with_context(|| format!("Credential with id {id} is used by cameras."))
+    }
+
+    /// Cameras that use the credential.
+    pub(crate) async fn camera_ids(pool: &SqlitePool, id: i64) -> Result<Vec<i64>> {
+        sqlx::query_scalar!(
+            r#"SELECT id as "id!" FROM cameras WHERE credential_kind = 'shared' AND credential_id = ?"#,
+            id
+        )
+        .fetch_all(pool)
+        .await
+        .with_context(|| format!("Failed to list cameras of credential with id {id}."))
+    }
+}
@@ -0,0 +1,321 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Timelike, Utc};
+use dahua_rpc::modules::eventmanager::{self, EventAction};
+use sqlx::{QueryBuilder, Sqlite, SqlitePool};
+
+use crate::models::{
+    CameraEvent, CameraEventQuery, CameraEventQueryFilter, CameraEventQueryResult,
+    CameraFileQueryCursor,
+};
+
+use super::utils::query_cursor;
+
+impl CameraEvent {
+    pub(crate) async fn create_db(
+        pool: &SqlitePool,
+        camera_id: i64,
+        event: eventmanager::Event,
+        time: DateTime<Utc>,
+    ) -> Result<i64> {
+        // Cursors only have millisecond precision
+        let time = time
+            .with_nanosecond(time.nanosecond() / 1_000_000 * 1_000_000)
+            .unwrap_or(time);
+
+        let mut pool = pool.begin().await?;
+
+        let action = event.action.as_str();
+        let data = event.data.to_string();
+        let end_time = match event.action {
+            EventAction::Start => None,
+            EventAction::Stop => {
+                // Close the matching start event
+                let start = sqlx::query_scalar!(
+                    r#"
+                    SELECT id
+                    FROM camera_events
+                    WHERE camera_id = ? AND code = ? AND idx = ? AND action = 'Start' AND end_time IS NULL
+                    ORDER BY id DESC
+                    LIMIT 1
+                    "#,
+                    camera_id,
+                    event.code,
+                    event.index,
+                )
+                .fetch_optional(&mut pool)
+                .await
+                .with_context(|| {
+                    format!("Failed to find start camera event with camera id {camera_id}.")
+                })?;
+
+                if let Some(id) = start {
+                    sqlx::query!(
+                        "UPDATE camera_events SET end_time = ? WHERE id = ?",
+                        time,
+                        id
+                    )
+                    .execute(&mut pool)
+                    .await
+                    .with_context(|| {
+                        format!("Failed to end camera event with camera id {camera_id}.")
+                    })?;
+                    pool.commit().await?;
+
+                    return Ok(id);
+                }
+
+                // The start happened before the stream was opened
+                Some(time)
+            }
+            EventAction::Pulse | EventAction::Unknown => Some(time),
+        };
+
+        let id = sqlx::query!(
+            r#"
+            INSERT INTO camera_events
+            (camera_id, code, action, idx, start_time, end_time, data)
+            VALUES
+            (?, ?, ?, ?, ?, ?, ?)
+            "#,
+            camera_id,
+            event.code,
+            action,
+            event.index,
+            time,
+            end_time,
+            data,
+        )
+        .execute(&mut pool)
+        .await
+        .with_context(|| format!("Failed to create camera event with camera id {camera_id}."))?
+        .last_insert_rowid();
+
+        pool.commit().await?;
+
+        Ok(id)
+    }
+}
+
+trait CameraEventFilter<'a> {
+    fn push_camera_event_filter(
+        self,
+        query: &'a CameraEventQueryFilter,
+    ) -> QueryBuilder<'a, Sqlite>;
+}
+
+impl<'a> CameraEventFilter<'a> for QueryBuilder<'a, Sqlite> {
+    fn push_camera_event_filter(
+        mut self,
+        filter: &'a CameraEventQueryFilter,
+    ) -> QueryBuilder<'a, Sqlite> {
+        self.push(" WHERE 1=1");
+
+        if let Some(start) = filter.start {
+            self.push(" AND start_time > ");
+            self.push_bind(start);
+        }
+
+        if let Some(end) = filter.end {
+            self.push(" AND start_time < ");
+            self.push_bind(end);
+        }
+
+        if !filter.camera_ids.is_empty() {
+            self.push(" AND camera_id in (");
+            let mut sep = self.separated(",");
+            for id in filter.camera_ids.iter() {
+                sep.push_bind(*id);
+            }
+            sep.push_unseparated(")");
+        }
+
+        if !filter.codes.is_empty() {
+            self.push(" AND code in (");
+            let mut sep = self.separated(",");
+            for code in filter.codes.iter() {
+                sep.push_bind(code.clone());
+            }
+            sep.push_unseparated(")");
+        }
+
+        if !filter.actions.is_empty() {
+            self.push(" AND action in (");
+            let mut sep = self.separated(",");
+            for action in filter.actions.iter() {
+                sep.push_bind(action.clone());
+            }
+            sep.push_unseparated(")");
+        }
+
+        self
+    }
+}
+
+impl CameraEvent {
+    pub async fn query(
+        pool: &SqlitePool,
+        query: CameraEventQuery<'_>,
+    ) -> Result<CameraEventQueryResult> {
+        let page = query_cursor::<CameraEvent>(
+            pool,
+            "camera_events",
+            "id",
+            query.cursor,
+            query.limit,
+            |qb| qb.push_camera_event_filter(query.filter),
+        )
+        .await
+        .context("Failed to query camera events.")?;
+        let events = page.rows;
+
+        let before = match events.first() {
+            Some(first) => CameraFileQueryCursor::to(first.id, first.start_time),
+            None => "".to_string(),
+        };
+
+        let after = match events.last() {
+            Some(last) => CameraFileQueryCursor::to(last.id, last.start_time),
+            None => "".to_string(),
+        };
+
+        let count = events.len() as i32;
+
+        Ok(CameraEventQueryResult {
+            events,
+            has_before: page.has_before,
+            before,
+            has_after: page.has_after,
+            after,
+            count,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use serde_json::json;
+
+    use crate::{db::testing::TempDb, models::CameraEventQueryFilter, secret::Secret};
+
+    use super::*;
+
+    async fn setup() -> (TempDb, SqlitePool, i64) {
+        let (db, pool) =
+            TempDb::new(&Secret::new("aXBjbWFudmlldyB0ZXN0IHNlY3JldCwgMzIgYnl0ZXM=").unwrap())
+                .await;
+        let camera_id = sqlx::query(
+            "INSERT INTO cameras (ip, username, password, scan_cursor) VALUES ('a', 'admin', '', 0)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap()
+        .last_insert_rowid();
+
+        (db, pool, camera_id)
+    }
+
+    fn event(action: &str) -> eventmanager::Event {
+        serde_json::from_value(json!({ "Code": "VideoMotion", "Action": action, "Index": 0 }))
+            .unwrap()
+    }
+
+    async fn list(pool: &SqlitePool) -> Vec<CameraEvent> {
+        CameraEvent::query(pool, CameraEventQuery::new(&CameraEventQueryFilter::new()))
+            .await
+            .unwrap()
+            .events
+    }
+
+    #[tokio::test]
+    async fn it_start_stop() {
+        let (_db, pool, camera_id) = setup().await;
+        let start = Utc::now();
+        let end = start + Duration::seconds(5);
+
+        let id = CameraEvent::create_db(&pool, camera_id, event("Start"), start)
+            .await
+            .unwrap();
+        assert_eq!(
+            CameraEvent::create_db(&pool, camera_id, event("Stop"), end)
+                .await
+                .unwrap(),
+            id
+        );
+
+        let events = list(&pool).await;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].action, "Start");
+        assert_eq!(
+            events[0].end_time.unwrap() - events[0].start_time,
+            Duration::seconds(5)
+        );
+    }
+
+    #[tokio::test]
+    async fn it_orphan_stop_and_pulse() {
+        let (_db, pool, camera_id) = setup().await;
+        let time = Utc::now();
+
+        CameraEvent::create_db(&pool, camera_id, event("Stop"), time)
+            .await
+            .unwrap();
+        CameraEvent::create_db(
+            &pool,
+            camera_id,
+            event("Pulse"),
+            time + Duration::seconds(1),
+        )
+        .await
+        .unwrap();
+
+        let events = list(&pool).await;
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].action, "Pulse");
+        assert_eq!(events[1].action, "Stop");
+        for event in events {
+            assert_eq!(event.end_time, Some(event.start_time));
+        }
+    }
+
+    #[tokio::test]
+    async fn it_query_cursor() {
+        let (_db, pool, camera_id) = setup().await;
+        let time = Utc::now();
+        for i in 0..25 {
+            CameraEvent::create_db(
+                &pool,
+                camera_id,
+                event("Pulse"),
+                time + Duration::seconds(i),
+            )
+            .await
+            .unwrap();
+        }
+        let filter = CameraEventQueryFilter::new();
+
+        let query = || CameraEventQuery::new(&filter).maybe_limit(Some(10));
+
+        let first = CameraEvent::query(&pool, query()).await.unwrap();
+        assert_eq!(first.count, 10);
+        assert!(!first.has_before);
+        assert!(first.has_after);
+
+        let second = CameraEvent::query(
+            &pool,
+            query().maybe_after(Some(first.after.clone())).unwrap(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(second.count, 10);
+        assert!(second.has_before);
+        assert!(second.events[0].start_time < first.events[9].start_time);
+
+        let back = CameraEvent::query(&pool, query().maybe_before(Some(second.before)).unwrap())
+            .await
+            .unwrap();
+        assert!(!back.has_before);
+        assert!(back.has_after);
+        assert_eq!(back.before, first.before);
+    }
+}
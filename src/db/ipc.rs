@@ -2,12 +2,15 @@ use std::vec;
 
 use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
-use dahua_rpc::modules::mediafilefind;
+use dahua_rpc::{modules::mediafilefind, Capabilities};
 use sqlx::{sqlite::SqliteQueryResult, QueryBuilder, Sqlite, SqlitePool};
 
 use crate::{
-    ipc::{IpcDetail, IpcFileStream, IpcLicenses, IpcManager, IpcSoftware},
-    models::{CameraScanResult, IpcEvent},
+    ipc::{
+        IpcCapabilities, IpcChannels, IpcDetail, IpcFileStream, IpcLicenses, IpcManager,
+        IpcSoftware,
+    },
+    models::{CameraCapabilities, CameraScanResult, IpcEvent},
 };
 
 use super::NotFound;
@@ -70,6 +73,52 @@ impl IpcSoftware {
     }
 }
 
+impl IpcChannels {
+    pub async fn save(&self, pool: &SqlitePool, camera_id: i64) -> Result<()> {
+        sqlx::query!(
+            "UPDATE cameras SET channels = ? WHERE id = ?",
+            self.0,
+            camera_id
+        )
+        .execute(pool)
+        .await
+        .with_context(|| format!("Failed to update camera channels with camera id {camera_id}."))
+        .map(NotFound::check_query)?
+        .with_context(|| format!("Failed to find camera with id {camera_id}."))
+    }
+}
+
+impl IpcCapabilities {
+    pub async fn save(&self, pool: &SqlitePool, camera_id: i64) -> Result<()> {
+        let methods = serde_json::json!(self.0.methods).to_string();
+        let services = serde_json::json!(self.0.services).to_string();
+        let updated_at = Utc::now();
+        sqlx::query!(
+            r#"
+            INSERT INTO camera_capabilities
+            (id, methods, services, updated_at)
+            VALUES
+            (?, ?, ?, ?)
+            ON CONFLICT (id) DO UPDATE SET
+            methods = excluded.methods,
+            services = excluded.services,
+            updated_at = excluded.updated_at
+            "#,
+            camera_id,
+            methods,
+            services,
+            updated_at,
+        )
+        .execute(pool)
+        .await
+        .with_context(|| {
+            format!("Failed to update camera capabilities with camera id {camera_id}.")
+        })?;
+
+        Ok(())
+    }
+}
+
 impl IpcLicenses {
     pub async fn save(&self, pool: &SqlitePool, camera_id: i64) -> Result<()> {
         let mut pool = pool.begin().await?;
@@ -132,7 +181,7 @@ impl IpcManager {
         timestamp: &chrono::DateTime<Utc>,
     ) -> Result<SqliteQueryResult> {
         let mut qb: QueryBuilder<Sqlite> = QueryBuilder::new(
-            "INSERT INTO camera_files (camera_id, file_path, kind, size, updated_at, start_time, end_time, events) ",
+            "INSERT INTO camera_files (camera_id, channel, file_path, kind, size, updated_at, start_time, end_time, events) ",
         );
         let mut unique_events: Vec<String> = vec![];
 
@@ -142,6 +191,7 @@ impl IpcManager {
                 let (start_time, end_time) = file.unique_time();
                 let events = serde_json::json!(file.events).to_string();
                 b.push_bind(camera_id)
+                    .push_bind(file.channel)
                     .push_bind(file.file_path)
                     .push_bind(file.r#type)
                     .push_bind(file.length)
@@ -156,24 +206,28 @@ impl IpcManager {
                 }
             })
             .push(
-                "ON CONFLICT (camera_id, file_path) DO UPDATE SET updated_at=excluded.updated_at ",
+                "ON CONFLICT (camera_id, channel, file_path) DO UPDATE SET updated_at=excluded.updated_at ",
             )
             // If for some reason the unique_time function generates a duplicate time then we should ignore the file being upserted and buy a lottery ticket
-            .push("ON CONFLICT (camera_id, start_time) DO NOTHING")
+            .push("ON CONFLICT (camera_id, channel, start_time) DO NOTHING")
             .build()
             .execute(pool)
             .await
             .with_context(|| format!("Failed to upsert files with camera id {camera_id}."))?;
 
         // Upsert events
-        QueryBuilder::<Sqlite>::new("INSERT OR IGNORE INTO ipc_events (name)")
-            .push_values(unique_events, |mut b, event| {
-                b.push_bind(event);
-            })
-            .build()
-            .execute(pool)
-            .await
-            .with_context(|| format!("Failed to upsert ipc events with camera id {camera_id}."))?;
+        if !unique_events.is_empty() {
+            QueryBuilder::<Sqlite>::new("INSERT OR IGNORE INTO ipc_events (name)")
+                .push_values(unique_events, |mut b, event| {
+                    b.push_bind(event);
+                })
+                .build()
+                .execute(pool)
+                .await
+                .with_context(|| {
+                    format!("Failed to upsert ipc events with camera id {camera_id}.")
+                })?;
+        }
 
         Ok(res)
     }
@@ -193,7 +247,7 @@ impl IpcManager {
 
         let mut upserted: u64 = 0;
         while let Some(files) = stream.next().await {
-            if files.len() > 0 {
+            if !files.is_empty() {
                 upserted += Self::upsert_files(pool, self.id, files, &timestamp)
                     .await?
                     .rows_affected();
@@ -221,23 +275,42 @@ impl IpcManager {
     ) -> Result<CameraScanResult> {
         let timestamp = Utc::now();
 
-        // Upsert videos (dav)
-        let mut upserted = Self::scan_files_condition(
-            &self,
-            pool,
-            mediafilefind::Condition::new(start_time, end_time).video(),
-            timestamp,
-        )
-        .await?;
+        // Cameras without storage do not have mediaFileFind
+        let capabilities = Capabilities::from(CameraCapabilities::find(pool, self.id).await?);
+        if !capabilities.supports("mediaFileFind.factory.create") {
+            return Ok(CameraScanResult::default());
+        }
 
-        // Upsert pictures (jpg)
-        upserted += Self::scan_files_condition(
-            &self,
-            pool,
-            mediafilefind::Condition::new(start_time, end_time).picture(),
-            timestamp,
-        )
-        .await?;
+        let channels = sqlx::query!("SELECT channels FROM cameras WHERE id = ?", self.id)
+            .fetch_one(pool)
+            .await
+            .with_context(|| format!("Failed to find camera channels with camera id {}.", self.id))?
+            .channels;
+
+        let mut upserted = 0;
+        for channel in 0..channels as i32 {
+            // Upsert videos (dav)
+            upserted += Self::scan_files_condition(
+                self,
+                pool,
+                mediafilefind::Condition::new(start_time, end_time)
+                    .video()
+                    .channel(channel),
+                timestamp,
+            )
+            .await?;
+
+            // Upsert pictures (jpg)
+            upserted += Self::scan_files_condition(
+                self,
+                pool,
+                mediafilefind::Condition::new(start_time, end_time)
+                    .picture()
+                    .channel(channel),
+                timestamp,
+            )
+            .await?;
+        }
 
         let deleted = sqlx::query!(
             r#"
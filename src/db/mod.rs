@@ -1,16 +1,32 @@
 use std::str::FromStr;
 
-use crate::models::ScanActive;
+use sqlx::{ConnectOptions, Connection};
 
-pub async fn new(url: &str) -> anyhow::Result<sqlx::SqlitePool> {
-    // Connect
+use crate::{models::ScanActive, secret::Secret};
+
+/// Opens the database, it is sealed with secret on first use.
+pub async fn new(url: &str, secret: &Secret) -> anyhow::Result<sqlx::SqlitePool> {
     let options = sqlx::sqlite::SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+
+    // Migrate, tables that are rebuilt would cascade their deletes with foreign keys on
+    let mut conn = options.clone().foreign_keys(false).connect().await?;
+    sqlx::migrate!().run(&mut conn).await?;
+    let violations = sqlx::query("PRAGMA foreign_key_check")
+        .fetch_all(&mut conn)
+        .await?;
+    if !violations.is_empty() {
+        anyhow::bail!(
+            "Migrations left {} foreign key violations.",
+            violations.len()
+        );
+    }
+    conn.close().await?;
+
+    // Connect
     let pool = sqlx::sqlite::SqlitePoolOptions::new()
         .connect_with(options)
         .await?;
-
-    // Migrate
-    sqlx::migrate!().run(&pool).await?;
+    crate::secret::migrate(&pool, secret).await?;
 
     ScanActive::clear(&pool).await?;
 
@@ -37,10 +53,15 @@ impl PartialEq<anyhow::Error> for NotFound {
     }
 }
 
+#[derive(thiserror::Error, Default, Debug)]
+#[error("Conflict")]
 pub struct Conflict;
 
 impl PartialEq<anyhow::Error> for Conflict {
     fn eq(&self, other: &anyhow::Error) -> bool {
+        if other.downcast_ref::<Self>().is_some() {
+            return true;
+        }
         if let Some(e) = other.downcast_ref::<sqlx::Error>() {
             if let Some(e) = e.as_database_error() {
                 if let Some(code) = e.code() {
@@ -50,16 +71,161 @@ impl PartialEq<anyhow::Error> for Conflict {
                 }
             }
         }
-        return false;
+        false
     }
 }
 
 pub mod camera;
+pub mod credential;
+pub mod event;
 pub mod ipc;
 pub mod scan;
 mod utils {
+    use anyhow::Result;
+    use chrono::{DateTime, Utc};
+    use sqlx::{sqlite::SqliteRow, FromRow, QueryBuilder, Sqlite, SqlitePool};
+
+    use crate::models::CameraFileQueryCursor;
+
     #[derive(sqlx::FromRow)]
     pub struct CountRow {
         pub count: i32,
     }
+
+    /// Rows of a cursor query, newest first.
+    pub struct CursorPage<T> {
+        pub rows: Vec<T>,
+        pub has_before: bool,
+        pub has_after: bool,
+    }
+
+    fn push_cursor<'a>(
+        qb: &mut QueryBuilder<'a, Sqlite>,
+        key: &str,
+        op: &str,
+        id: i64,
+        time: DateTime<Utc>,
+    ) {
+        qb.push(format!(" AND (start_time {op} "))
+            .push_bind(time)
+            .push(" OR (start_time = ")
+            .push_bind(time)
+            .push(format!(" AND {key} {op} "))
+            .push_bind(id)
+            .push("))");
+    }
+
+    /// Pages through table ordered by start_time, key orders rows with the same start_time.
+    ///
+    /// filter has to push a WHERE clause since the cursor is appended with AND.
+    pub async fn query_cursor<'a, T>(
+        pool: &SqlitePool,
+        table: &str,
+        key: &str,
+        cursor: CameraFileQueryCursor,
+        limit: i32,
+        filter: impl Fn(QueryBuilder<'a, Sqlite>) -> QueryBuilder<'a, Sqlite>,
+    ) -> Result<CursorPage<T>>
+    where
+        T: for<'r> FromRow<'r, SqliteRow> + Send + Unpin,
+    {
+        // After goes back in time, Before goes forward in time
+        let (forward, position) = match cursor {
+            CameraFileQueryCursor::After(position) => (false, Some(position)),
+            CameraFileQueryCursor::Before(position) => (true, Some(position)),
+            CameraFileQueryCursor::None => (false, None),
+        };
+        let (op, other_op, order) = if forward {
+            (">", "<", "ASC")
+        } else {
+            ("<", ">", "DESC")
+        };
+
+        let limit = limit + 1;
+        let mut qb = filter(QueryBuilder::new(format!("SELECT * FROM {table}")));
+        if let Some((id, time)) = position {
+            push_cursor(&mut qb, key, op, id, time);
+        }
+        let mut rows = qb
+            .push(format!(
+                " ORDER BY start_time {order}, {key} {order} LIMIT "
+            ))
+            .push_bind(limit)
+            .build_query_as::<T>()
+            .fetch_all(pool)
+            .await?;
+
+        let has_more = rows.len() == limit as usize;
+        if has_more {
+            rows.pop();
+        }
+
+        // Anything on the other side of the cursor
+        let has_other = match position {
+            Some((id, time)) => {
+                let mut qb = filter(QueryBuilder::new(format!("SELECT id FROM {table}")));
+                push_cursor(&mut qb, key, other_op, id, time);
+                qb.push(" LIMIT 1")
+                    .build()
+                    .fetch_optional(pool)
+                    .await?
+                    .is_some()
+            }
+            None => false,
+        };
+
+        if forward {
+            rows.reverse();
+            Ok(CursorPage {
+                rows,
+                has_before: has_more,
+                has_after: has_other,
+            })
+        } else {
+            Ok(CursorPage {
+                rows,
+                has_before: has_other,
+                has_after: has_more,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod testing {
+    use std::{
+        path::PathBuf,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use sqlx::SqlitePool;
+
+    use crate::secret::Secret;
+
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+    /// Database file that is removed on drop.
+    pub struct TempDb(PathBuf);
+
+    impl TempDb {
+        pub async fn new(secret: &Secret) -> (TempDb, SqlitePool) {
+            let path = std::env::temp_dir().join(format!(
+                "ipcmanview-test-{}-{}.db",
+                std::process::id(),
+                NEXT.fetch_add(1, Ordering::Relaxed)
+            ));
+            std::fs::remove_file(&path).ok();
+            let pool = super::new(&format!("sqlite://{}", path.display()), secret)
+                .await
+                .unwrap();
+
+            (TempDb(path), pool)
+        }
+    }
+
+    impl Drop for TempDb {
+        fn drop(&mut self) {
+            std::fs::remove_file(&self.0).ok();
+        }
+    }
 }
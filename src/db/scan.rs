@@ -335,7 +335,7 @@ impl ScanCompleted {
         let total_items = sqlx::query!(r#"SELECT COUNT(id) AS count FROM completed_scans"#)
             .fetch_one(pool)
             .await
-            .with_context(|| format!("Failed to count completed scans."))
+            .with_context(|| "Failed to count completed scans.".to_string())
             .map(|c| c.count)?;
 
         Ok(page.new_result(items, total_items))
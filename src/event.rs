@@ -0,0 +1,179 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use dahua_rpc::{event::EventStream, Error, ResponseError, ResponseKind, State};
+use sqlx::SqlitePool;
+use tokio::task::JoinHandle;
+
+use crate::{
+    ipc::{IpcManager, IpcStore},
+    locate::Locator,
+    models::CameraEvent,
+};
+
+const CODES: &[&str] = &["All"];
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(10);
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(30);
+/// Looking for a camera sends a lot of probes, an offline camera should not do it every reconnect.
+const RELOCATE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// Logins the device rejects count towards locking the user, a pending password should not be
+/// tried every reconnect.
+const RECOVER_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Keeps an event subscription open for a single camera and saves every event to the database.
+///
+/// A camera that can not be reached is looked for by serial number, it might have a new address.
+/// A camera that rejects the login or can not be reached is tried with its pending password, the
+/// reply of a password change might have been lost.
+pub struct EventListener {
+    man: IpcManager,
+    pool: SqlitePool,
+    store: IpcStore,
+    locator: Locator,
+}
+
+impl EventListener {
+    pub fn spawn(
+        man: IpcManager,
+        pool: SqlitePool,
+        store: IpcStore,
+        locator: Locator,
+    ) -> JoinHandle<()> {
+        tokio::spawn(
+            EventListener {
+                man,
+                pool,
+                store,
+                locator,
+            }
+            .run(),
+        )
+    }
+
+    async fn run(self) {
+        let mut relocated_at: Option<Instant> = None;
+        let mut recovered_at: Option<Instant> = None;
+        loop {
+            match self.listen().await {
+                Ok(_) => {}
+                Err(err) if Self::unsupported(&err) => {
+                    tracing::warn!(
+                        "Camera {} does not support event subscriptions: {err:?}",
+                        self.man.id
+                    );
+                    return;
+                }
+                Err(err) => {
+                    tracing::warn!("{err:?}");
+
+                    if self.unreachable(&err)
+                        && relocated_at.is_none_or(|at| at.elapsed() >= RELOCATE_INTERVAL)
+                    {
+                        relocated_at = Some(Instant::now());
+                        match self
+                            .man
+                            .relocate(&self.pool, &self.store, &self.locator)
+                            .await
+                        {
+                            // The store replaces this listener
+                            Ok(Some(_)) => return,
+                            Ok(None) => {}
+                            Err(err) => tracing::warn!("{err:?}"),
+                        }
+                    }
+
+                    if Self::rejected_or_failed(&err)
+                        && recovered_at.is_none_or(|at| at.elapsed() >= RECOVER_INTERVAL)
+                    {
+                        recovered_at = Some(Instant::now());
+                        match self.man.recover_password(&self.pool, &self.store).await {
+                            // The store replaces this listener
+                            Ok(true) => return,
+                            Ok(false) => {}
+                            Err(err) => tracing::warn!("{err:?}"),
+                        }
+                    }
+                }
+            }
+
+            // Reconnecting while the camera is locked is pointless
+            let locked_for = self.man.client.state().locked_for().unwrap_or_default();
+            tokio::time::sleep(RECONNECT_INTERVAL.max(locked_for)).await;
+        }
+    }
+
+    /// Login failed because the request could not be made.
+    fn unreachable(&self, err: &anyhow::Error) -> bool {
+        matches!(err.downcast_ref::<Error>(), Some(Error::Request(_)))
+            && !matches!(self.man.client.state(), State::Login(_))
+    }
+
+    /// The camera might have a pending password when the login was rejected or the request
+    /// failed.
+    fn rejected_or_failed(err: &anyhow::Error) -> bool {
+        matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::Login(_) | Error::Request(_))
+        )
+    }
+
+    fn unsupported(err: &anyhow::Error) -> bool {
+        matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::Response(ResponseError {
+                kind: ResponseKind::MethodNotFound | ResponseKind::InterfaceNotFound,
+                ..
+            }))
+        )
+    }
+
+    async fn listen(&self) -> Result<()> {
+        let mut stream = self.man.event_stream(CODES).await.with_context(|| {
+            format!(
+                "Failed to open event stream with camera id {}.",
+                self.man.id
+            )
+        })?;
+
+        let res = self.read(&mut stream).await;
+        // Reconnecting attaches again so the old subscription has to go
+        if let Err(err) = stream.close().await {
+            tracing::warn!(
+                "Failed to detach event stream with camera id {}: {err:?}",
+                self.man.id
+            );
+        }
+
+        res
+    }
+
+    async fn read(&self, stream: &mut EventStream) -> Result<()> {
+        // The session has to be kept alive while the stream is open
+        let mut keep_alive = tokio::time::interval(KEEP_ALIVE_INTERVAL);
+
+        loop {
+            tokio::select! {
+                res = stream.next() => {
+                    let events = match res {
+                        Some(events) => events.with_context(|| {
+                            format!("Failed to read event stream with camera id {}.", self.man.id)
+                        })?,
+                        // Camera closed the stream
+                        None => return Ok(()),
+                    };
+
+                    let time = Utc::now();
+                    for event in events {
+                        CameraEvent::create_db(&self.pool, self.man.id, event, time).await?;
+                    }
+                }
+                _ = keep_alive.tick() => {
+                    self.man.rpc().await.with_context(|| {
+                        format!("Failed to keep event stream alive with camera id {}.", self.man.id)
+                    })?;
+                }
+            }
+        }
+    }
+}
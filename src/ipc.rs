@@ -1,21 +1,50 @@
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+};
 
 use anyhow::{anyhow, Context, Result};
 use dahua_rpc::{
-    modules::{license, magicbox, mediafilefind},
-    reqwest, Client, Error, RequestBuilder, ResponseError, ResponseKind,
+    event::EventStream,
+    modules::{config, license, magicbox, mediafilefind, ptz, usermanager},
+    multicall::{Call, Multicall, MulticallResponse},
+    reqwest, Capabilities, Client, Endpoint, Error, RequestBuilder, ResponseError, ResponseKind,
+    Scheme, State,
+};
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
 };
-use tokio::sync::{mpsc, oneshot, Mutex};
-
-/// If the error is of type ResponseError then it will return the Default::default() of type T.
-fn maybe<T>(check: Result<T, Error>) -> Result<T, Error>
-where
-    T: Default,
-{
-    match check {
-        Err(Error::Response(_)) => Ok(Default::default()),
-        Ok(o) => Ok(o),
-        Err(e) => Err(e),
+
+use crate::{event::EventListener, locate::Locator, secret::Secret};
+
+/// Call that is only added to the Multicall when the device supports its method.
+struct Supported<T> {
+    call: Option<Call<T>>,
+    unknown: bool,
+}
+
+impl<T: Default + 'static> Supported<T> {
+    fn new<F>(mc: &mut Multicall, capabilities: &Capabilities, method: &str, build: F) -> Self
+    where
+        F: FnOnce(&mut Multicall) -> Call<T>,
+    {
+        Supported {
+            call: capabilities.supports(method).then(|| build(mc)),
+            unknown: capabilities.is_unknown(),
+        }
+    }
+
+    /// Default::default() of T when the method is not supported.
+    fn take(self, res: &mut MulticallResponse) -> Result<T, Error> {
+        let Some(call) = self.call else {
+            return Ok(T::default());
+        };
+        match res.take(call) {
+            // Devices that can not list their methods are assumed to support everything
+            Err(Error::Response(_)) if self.unknown => Ok(T::default()),
+            res => res,
+        }
     }
 }
 
@@ -23,40 +52,73 @@ where
 pub struct IpcFile {
     pub cookie: String,
     pub url: String,
+    pub accept_invalid_certs: bool,
 }
 
 #[derive(Clone)]
 pub struct IpcManager {
     pub id: i64,
-    pub client: Arc<Mutex<Client>>,
+    pub client: Client,
+    /// PTZ object of each channel and the session it belongs to.
+    ptz_objects: Arc<Mutex<HashMap<i32, (String, i64)>>>,
 }
 
 impl IpcManager {
     pub fn new(id: i64, client: Client) -> IpcManager {
         IpcManager {
             id,
-            client: Arc::new(Mutex::new(client)),
+            client,
+            ptz_objects: Default::default(),
         }
     }
 
     pub async fn rpc(&self) -> Result<RequestBuilder, Error> {
-        let mut client = self.client.lock().await;
-        client.rpc().await
+        self.client.rpc().await
     }
 
-    pub async fn file(&self, file_path: &str) -> Result<IpcFile, Error> {
-        let mut client = self.client.lock().await;
+    pub async fn multicall(&self, mc: Multicall) -> Result<MulticallResponse, Error> {
+        self.client.multicall(mc).await
+    }
 
+    pub async fn file(&self, file_path: &str) -> Result<IpcFile, Error> {
         Ok(IpcFile {
-            cookie: client.cookie().await?,
-            url: client.file_url(file_path),
+            cookie: self.client.cookie().await?,
+            url: self.client.file_url(file_path),
+            accept_invalid_certs: self.client.endpoint.accept_invalid_certs,
         })
     }
 
+    pub async fn snapshot(&self, channel: i32) -> Result<reqwest::Response, Error> {
+        self.client.snapshot(channel).await
+    }
+
+    pub async fn event_stream(&self, codes: &[&str]) -> Result<EventStream, Error> {
+        self.client.event_stream(codes).await
+    }
+
     pub async fn close(&self) {
-        let mut client = self.client.lock().await;
-        client.logout().await;
-        client.state = dahua_rpc::State::Error(dahua_rpc::LoginError::Closed)
+        self.client.close().await
+    }
+
+    pub fn login(&self) -> CameraLogin {
+        let state = self.client.state();
+        let policy = self.client.lock_policy();
+
+        CameraLogin {
+            state: match state {
+                State::Logout => CameraLoginState::Logout,
+                State::Login(_) => CameraLoginState::Login,
+                State::Locked(_) => CameraLoginState::Locked,
+                State::Error(_) => CameraLoginState::Error,
+            },
+            error: match state {
+                State::Error(err) => Some(err.to_string()),
+                _ => None,
+            },
+            locked_seconds: state.locked_for().map(|d| d.as_secs()),
+            lock_enabled: policy.map(|p| p.enabled),
+            lock_time: policy.map(|p| p.lock_time.as_secs()),
+        }
     }
 }
 
@@ -70,37 +132,282 @@ pub struct IpcDetail {
     pub vendor: String,
 }
 
+pub struct IpcDetailCall {
+    sn: Supported<String>,
+    device_class: Supported<String>,
+    device_type: Supported<String>,
+    hardware_version: Supported<String>,
+    market_area: Supported<String>,
+    process_info: Supported<String>,
+    vendor: Supported<String>,
+}
+
 impl IpcDetail {
+    pub fn call(mc: &mut Multicall, capabilities: &Capabilities) -> IpcDetailCall {
+        IpcDetailCall {
+            sn: Supported::new(
+                mc,
+                capabilities,
+                "magicBox.getSerialNo",
+                magicbox::get_serial_no_call,
+            ),
+            device_class: Supported::new(
+                mc,
+                capabilities,
+                "magicBox.getDeviceClass",
+                magicbox::get_device_class_call,
+            ),
+            device_type: Supported::new(
+                mc,
+                capabilities,
+                "magicBox.getDeviceType",
+                magicbox::get_device_type_call,
+            ),
+            hardware_version: Supported::new(
+                mc,
+                capabilities,
+                "magicBox.getHardwareVersion",
+                magicbox::get_hardware_version_call,
+            ),
+            market_area: Supported::new(
+                mc,
+                capabilities,
+                "magicBox.getMarketArea",
+                magicbox::get_market_area_call,
+            ),
+            process_info: Supported::new(
+                mc,
+                capabilities,
+                "magicBox.getProcessInfo",
+                magicbox::get_process_info_call,
+            ),
+            vendor: Supported::new(
+                mc,
+                capabilities,
+                "magicBox.getVendor",
+                magicbox::get_vendor_call,
+            ),
+        }
+    }
+
     pub async fn get(man: &IpcManager) -> Result<Self, Error> {
+        let mut mc = Multicall::new();
+        let call = Self::call(&mut mc, &man.client.capabilities().await?);
+        call.take(&mut man.multicall(mc).await?)
+    }
+}
+
+impl IpcDetailCall {
+    pub fn take(self, res: &mut MulticallResponse) -> Result<IpcDetail, Error> {
         Ok(IpcDetail {
-            sn: maybe(magicbox::get_serial_no(man.rpc().await?).await)?,
-            device_class: maybe(magicbox::get_device_class(man.rpc().await?).await)?,
-            device_type: maybe(magicbox::get_device_type(man.rpc().await?).await)?,
-            hardware_version: maybe(magicbox::get_hardware_version(man.rpc().await?).await)?,
-            market_area: maybe(magicbox::get_market_area(man.rpc().await?).await)?,
-            process_info: maybe(magicbox::get_process_info(man.rpc().await?).await)?,
-            vendor: maybe(magicbox::get_vendor(man.rpc().await?).await)?,
+            sn: self.sn.take(res)?,
+            device_class: self.device_class.take(res)?,
+            device_type: self.device_type.take(res)?,
+            hardware_version: self.hardware_version.take(res)?,
+            market_area: self.market_area.take(res)?,
+            process_info: self.process_info.take(res)?,
+            vendor: self.vendor.take(res)?,
         })
     }
 }
 
 pub struct IpcSoftware(pub magicbox::GetSoftwareVersion);
 
+pub struct IpcSoftwareCall(Supported<magicbox::GetSoftwareVersion>);
+
 impl IpcSoftware {
+    pub fn call(mc: &mut Multicall, capabilities: &Capabilities) -> IpcSoftwareCall {
+        IpcSoftwareCall(Supported::new(
+            mc,
+            capabilities,
+            "magicBox.getSoftwareVersion",
+            magicbox::get_software_version_call,
+        ))
+    }
+
     pub async fn get(man: &IpcManager) -> Result<Self, Error> {
-        Ok(IpcSoftware(maybe(
-            magicbox::get_software_version(man.rpc().await?).await,
-        )?))
+        let mut mc = Multicall::new();
+        let call = Self::call(&mut mc, &man.client.capabilities().await?);
+        call.take(&mut man.multicall(mc).await?)
+    }
+}
+
+impl IpcSoftwareCall {
+    pub fn take(self, res: &mut MulticallResponse) -> Result<IpcSoftware, Error> {
+        Ok(IpcSoftware(self.0.take(res)?))
     }
 }
 
 pub struct IpcLicenses(pub Vec<license::InfoContainer>);
 
+pub struct IpcLicensesCall(Supported<Vec<license::InfoContainer>>);
+
 impl IpcLicenses {
+    pub fn call(mc: &mut Multicall, capabilities: &Capabilities) -> IpcLicensesCall {
+        IpcLicensesCall(Supported::new(
+            mc,
+            capabilities,
+            "License.getLicenseInfo",
+            license::get_license_info_call,
+        ))
+    }
+
+    pub async fn get(man: &IpcManager) -> Result<Self, Error> {
+        let mut mc = Multicall::new();
+        let call = Self::call(&mut mc, &man.client.capabilities().await?);
+        call.take(&mut man.multicall(mc).await?)
+    }
+}
+
+impl IpcLicensesCall {
+    pub fn take(self, res: &mut MulticallResponse) -> Result<IpcLicenses, Error> {
+        Ok(IpcLicenses(self.0.take(res)?))
+    }
+}
+
+pub struct IpcCapabilities(pub Capabilities);
+
+impl IpcCapabilities {
     pub async fn get(man: &IpcManager) -> Result<Self, Error> {
-        Ok(IpcLicenses(maybe(
-            license::get_license_info(man.rpc().await?).await,
-        )?))
+        man.client.capabilities().await.map(IpcCapabilities)
+    }
+}
+
+impl From<CameraCapabilities> for Capabilities {
+    fn from(value: CameraCapabilities) -> Self {
+        Capabilities {
+            methods: value.methods.0.into_iter().collect(),
+            services: value.services.0.into_iter().collect(),
+        }
+    }
+}
+
+/// Number of video channels on the device, NVRs have more than one.
+pub struct IpcChannels(pub i32);
+
+impl IpcChannels {
+    pub async fn get(man: &IpcManager) -> Result<Self, Error> {
+        if !man
+            .client
+            .capabilities()
+            .await?
+            .supports("configManager.getConfig")
+        {
+            return Ok(IpcChannels(1));
+        }
+
+        match config::ChannelTitle::get(man.rpc().await?).await {
+            Ok(titles) => Ok(IpcChannels((titles.0.len() as i32).max(1))),
+            Err(Error::Response(_)) => Ok(IpcChannels(1)),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Accounts on the device and who is logged in.
+pub struct IpcUsers {
+    pub users: Vec<usermanager::User>,
+    pub groups: Vec<usermanager::Group>,
+    pub active: Vec<usermanager::ActiveUser>,
+}
+
+impl IpcUsers {
+    pub async fn get(man: &IpcManager) -> Result<Self, Error> {
+        Ok(IpcUsers {
+            users: usermanager::get_user_info_all(man.rpc().await?).await?,
+            groups: usermanager::get_group_info_all(man.rpc().await?).await?,
+            active: usermanager::get_active_user_info_all(man.rpc().await?).await?,
+        })
+    }
+}
+
+pub struct IpcPtz {
+    man: IpcManager,
+    object: i64,
+    channel: i32,
+}
+
+impl IpcPtz {
+    /// Reuses the object of the channel for as long as the session lives, the device only frees
+    /// objects when the session ends.
+    pub async fn new(man: IpcManager, channel: i32) -> Result<IpcPtz, Error> {
+        let rpc = man.rpc().await?;
+        let session = man.client.connection().session;
+        let cached = man
+            .ptz_objects
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&channel)
+            .filter(|(s, _)| *s == session)
+            .map(|(_, object)| *object);
+        let object = match cached {
+            Some(object) => object,
+            None => {
+                let object = ptz::instance(rpc, channel).await?;
+                man.ptz_objects
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .insert(channel, (session, object));
+                object
+            }
+        };
+
+        Ok(IpcPtz {
+            man,
+            object,
+            channel,
+        })
+    }
+
+    pub async fn start(&self, code: ptz::Code, speed: i32) -> Result<bool, Error> {
+        ptz::start(
+            self.man.rpc().await?,
+            self.object,
+            self.channel,
+            code,
+            speed,
+        )
+        .await
+    }
+
+    pub async fn stop(&self, code: ptz::Code) -> Result<bool, Error> {
+        ptz::stop(self.man.rpc().await?, self.object, self.channel, code).await
+    }
+
+    pub async fn presets(&self) -> Result<Vec<ptz::Preset>, Error> {
+        ptz::get_presets(self.man.rpc().await?, self.object).await
+    }
+
+    pub async fn goto_preset(&self, index: i32, speed: i32) -> Result<bool, Error> {
+        ptz::goto_preset(self.man.rpc().await?, self.object, index, speed).await
+    }
+
+    pub async fn set_preset(&self, preset: ptz::Preset) -> Result<bool, Error> {
+        ptz::set_preset(self.man.rpc().await?, self.object, preset).await
+    }
+
+    pub async fn remove_preset(&self, index: i32) -> Result<bool, Error> {
+        ptz::remove_preset(self.man.rpc().await?, self.object, index).await
+    }
+
+    pub async fn tours(&self) -> Result<Vec<ptz::Tour>, Error> {
+        ptz::get_tours(self.man.rpc().await?, self.object).await
+    }
+
+    pub async fn start_tour(&self, index: i32) -> Result<bool, Error> {
+        ptz::start_tour(self.man.rpc().await?, self.object, index).await
+    }
+
+    pub async fn stop_tour(&self, index: i32) -> Result<bool, Error> {
+        ptz::stop_tour(self.man.rpc().await?, self.object, index).await
+    }
+
+    pub async fn set_tour(&self, tour: ptz::Tour) -> Result<bool, Error> {
+        ptz::set_tour(self.man.rpc().await?, self.object, tour).await
+    }
+
+    pub async fn remove_tour(&self, index: i32) -> Result<bool, Error> {
+        ptz::remove_tour(self.man.rpc().await?, self.object, index).await
     }
 }
 
@@ -116,7 +423,7 @@ impl IpcFileStream<'_> {
     pub async fn new(
         man: &IpcManager,
         condition: mediafilefind::Condition,
-    ) -> Result<IpcFileStream, Error> {
+    ) -> Result<IpcFileStream<'_>, Error> {
         let object = mediafilefind::create(man.rpc().await?).await?;
 
         let closed = match mediafilefind::find_file(man.rpc().await?, object, condition).await {
@@ -189,13 +496,75 @@ impl IpcFileStream<'_> {
     }
 }
 
-use crate::models::ICamera;
+use crate::models::{
+    credential_env, credential_file, CameraCapabilities, CameraLogin, CameraLoginState,
+    CameraScheme, CredentialSource, ICamera,
+};
+
+impl From<CameraScheme> for Scheme {
+    fn from(value: CameraScheme) -> Self {
+        match value {
+            CameraScheme::Http => Scheme::Http,
+            CameraScheme::Https => Scheme::Https,
+        }
+    }
+}
 
+impl ICamera {
+    pub fn endpoint(&self) -> Endpoint {
+        Endpoint::new(self.ip.clone())
+            .scheme(self.scheme.into())
+            .port(self.port)
+            .accept_invalid_certs(self.accept_invalid_certs)
+    }
+}
+
+impl ICamera {
+    /// Reads a password that lives outside of the database, shared credentials are already
+    /// resolved when the camera is loaded.
+    pub async fn resolve(&mut self) -> Result<()> {
+        match &self.credential {
+            CredentialSource::Env { name } => {
+                // Checked again since the variables of the station can change
+                let name = credential_env(name)?;
+                self.password = std::env::var(name).with_context(|| {
+                    format!(
+                        "Failed to read environment variable {name} of camera {}.",
+                        self.id
+                    )
+                })?;
+            }
+            CredentialSource::File { path } => {
+                // Checked again since symlinks can change after the camera is saved
+                let path = credential_file(path)?;
+                let password = tokio::fs::read_to_string(&path).await.with_context(|| {
+                    format!(
+                        "Failed to read credential file {} of camera {}.",
+                        path.display(),
+                        self.id
+                    )
+                })?;
+                // Secret mounts usually end with a newline
+                self.password = password.trim_end_matches(['\r', '\n']).to_string();
+            }
+            CredentialSource::Literal | CredentialSource::Shared { .. } => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// The reqwest client has to accept invalid certificates when the camera does.
 impl From<(ICamera, reqwest::Client)> for IpcManager {
     fn from(value: (ICamera, reqwest::Client)) -> Self {
         IpcManager::new(
             value.0.id,
-            dahua_rpc::Client::new(value.1, value.0.ip, value.0.username, value.0.password),
+            dahua_rpc::Client::new(
+                value.1,
+                value.0.endpoint(),
+                value.0.username,
+                value.0.password,
+            ),
         )
     }
 }
@@ -209,31 +578,82 @@ enum IpcStoreMessage {
 struct IpcStoreActor {
     receiver: mpsc::Receiver<IpcStoreMessage>,
     mans: Vec<IpcManager>,
+    listeners: HashMap<i64, JoinHandle<()>>,
     client: reqwest::Client,
+    insecure_client: reqwest::Client,
     pool: sqlx::SqlitePool,
+    /// Handle to this actor for listeners that move cameras.
+    store: IpcStore,
+    locator: Locator,
 }
 
 impl IpcStoreActor {
     async fn new(
         receiver: mpsc::Receiver<IpcStoreMessage>,
         pool: sqlx::SqlitePool,
+        store: IpcStore,
+        locator: Locator,
     ) -> Result<Self> {
         let client = dahua_rpc::recommended_reqwest_client_builder()
             .build()
             .context("Failed to build reqwest client.")?;
+        let insecure_client = dahua_rpc::recommended_reqwest_client_builder()
+            .danger_accept_invalid_certs(true)
+            .build()
+            .context("Failed to build insecure reqwest client.")?;
 
-        let mans = ICamera::list(&pool)
-            .await?
-            .into_iter()
-            .map(|c| IpcManager::from((c, client.clone())))
-            .collect();
-
-        Ok(IpcStoreActor {
+        let mut actor = IpcStoreActor {
             receiver,
-            mans,
+            mans: vec![],
+            listeners: HashMap::new(),
             client,
+            insecure_client,
             pool,
-        })
+            store,
+            locator,
+        };
+
+        for icam in ICamera::list(&actor.pool, actor.store.secret()).await? {
+            let man = actor.manager(icam).await;
+            actor.listen(&man);
+            actor.mans.push(man);
+        }
+
+        Ok(actor)
+    }
+
+    /// Credentials are resolved every time so changed sources are picked up on refresh.
+    async fn manager(&self, mut icam: ICamera) -> IpcManager {
+        // The camera stays listed, it will fail to log in
+        if let Err(err) = icam.resolve().await {
+            tracing::error!("{err:?}");
+        }
+
+        let client = if icam.accept_invalid_certs {
+            self.insecure_client.clone()
+        } else {
+            self.client.clone()
+        };
+
+        IpcManager::from((icam, client))
+    }
+
+    fn listen(&mut self, man: &IpcManager) {
+        let listener = EventListener::spawn(
+            man.clone(),
+            self.pool.clone(),
+            self.store.clone(),
+            self.locator.clone(),
+        );
+        if let Some(old) = self.listeners.insert(man.id, listener) {
+            old.abort();
+        }
+    }
+
+    fn unlisten(&mut self, id: i64) {
+        if let Some(old) = self.listeners.remove(&id) {
+            old.abort();
+        }
     }
 
     async fn handle_message(&mut self, msg: IpcStoreMessage) {
@@ -249,27 +669,38 @@ impl IpcStoreActor {
                 respond_to.send(None).ok();
             }
             IpcStoreMessage::Refresh(id) => {
-                let icam = match ICamera::find_optional(&self.pool, id).await {
+                let icam = match ICamera::find_optional(&self.pool, self.store.secret(), id).await {
                     Ok(o) => o,
                     Err(err) => {
                         tracing::error!("{err:?}");
                         return;
                     }
                 };
-                let old = self.mans.iter().enumerate().find(|(_, old)| old.id == id);
+                let old = self
+                    .mans
+                    .iter()
+                    .enumerate()
+                    .find(|(_, old)| old.id == id)
+                    .map(|(idx, old)| (idx, old.clone()));
 
                 match (icam, old) {
                     // Update
                     (Some(icam), Some((idx, old))) => {
+                        self.unlisten(id);
                         old.close().await;
-                        self.mans[idx] = IpcManager::from((icam, self.client.clone()));
+                        let man = self.manager(icam).await;
+                        self.listen(&man);
+                        self.mans[idx] = man;
                     }
                     // Add
-                    (Some(icam), None) => self
-                        .mans
-                        .push(IpcManager::from((icam, self.client.clone()))),
+                    (Some(icam), None) => {
+                        let man = self.manager(icam).await;
+                        self.listen(&man);
+                        self.mans.push(man);
+                    }
                     // Delete
                     (None, Some((idx, old))) => {
+                        self.unlisten(id);
                         old.close().await;
                         self.mans.remove(idx);
                     }
@@ -277,6 +708,10 @@ impl IpcStoreActor {
                 }
             }
             IpcStoreMessage::Shutdown(respond_to) => {
+                for (_, listener) in self.listeners.drain() {
+                    listener.abort();
+                }
+
                 for man in self.mans.iter() {
                     man.close().await;
                 }
@@ -298,15 +733,22 @@ impl IpcStoreActor {
 #[derive(Clone)]
 pub struct IpcStore {
     sender: mpsc::Sender<IpcStoreMessage>,
+    secret: Secret,
 }
 
 impl IpcStore {
-    pub async fn new(pool: sqlx::SqlitePool) -> Result<Self> {
+    /// Cameras that stop answering are looked for with locator, secret opens their passwords.
+    pub async fn new(pool: sqlx::SqlitePool, locator: Locator, secret: Secret) -> Result<Self> {
         let (sender, receiver) = mpsc::channel(8);
-        let actor = IpcStoreActor::new(receiver, pool).await?;
+        let store = Self { sender, secret };
+        let actor = IpcStoreActor::new(receiver, pool, store.clone(), locator).await?;
         tokio::spawn(actor.run());
 
-        Ok(Self { sender })
+        Ok(store)
+    }
+
+    pub fn secret(&self) -> &Secret {
+        &self.secret
     }
 
     pub async fn get_optional(&self, id: i64) -> Result<Option<IpcManager>> {
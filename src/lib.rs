@@ -2,7 +2,10 @@ pub use dahua_rpc;
 pub use sqlx;
 
 pub mod db;
+pub mod event;
 pub mod ipc;
+pub mod locate;
 pub mod models;
 pub mod procs;
 pub mod scan;
+pub mod secret;
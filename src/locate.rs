@@ -0,0 +1,145 @@
+use std::{
+    fmt,
+    net::{Ipv4Addr, SocketAddr},
+    str::FromStr,
+};
+
+use anyhow::{Context, Result};
+use dahua_rpc::discover::{Discover, DiscoveredDevice, MULTICAST_ADDR};
+
+use crate::models::ValidationError;
+
+/// Smallest prefix that is swept, larger subnets take too long.
+const MIN_PREFIX: u8 = 16;
+
+/// IPv4 subnet in CIDR notation, e.g. `192.168.1.0/24`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Subnet {
+    addr: Ipv4Addr,
+    prefix: u8,
+}
+
+impl Subnet {
+    fn mask(&self) -> u32 {
+        u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0)
+    }
+
+    /// Addresses without the network and broadcast address.
+    pub fn hosts(&self) -> impl Iterator<Item = Ipv4Addr> {
+        let network = u32::from(self.addr) & self.mask();
+        let broadcast = network | !self.mask();
+        let (first, last) = if self.prefix >= 31 {
+            (network, broadcast)
+        } else {
+            (network + 1, broadcast - 1)
+        };
+
+        (first..=last).map(Ipv4Addr::from)
+    }
+}
+
+impl FromStr for Subnet {
+    type Err = ValidationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = s
+            .split_once('/')
+            .ok_or_else(|| ValidationError(format!("subnet '{s}' is missing a prefix")))?;
+        let addr = addr
+            .parse()
+            .map_err(|_| ValidationError(format!("invalid address in subnet '{s}'")))?;
+        let prefix = prefix
+            .parse()
+            .ok()
+            .filter(|prefix| (MIN_PREFIX..=32).contains(prefix))
+            .ok_or_else(|| {
+                ValidationError(format!(
+                    "prefix of subnet '{s}' must be between {MIN_PREFIX} and 32"
+                ))
+            })?;
+
+        Ok(Subnet { addr, prefix })
+    }
+}
+
+impl fmt::Display for Subnet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// Finds cameras by serial number after their address changed.
+///
+/// DHDiscover is tried first, subnets that multicast does not reach are swept with unicast probes.
+#[derive(Clone, Default, Debug)]
+pub struct Locator {
+    pub discover: Discover,
+    pub subnets: Vec<Subnet>,
+}
+
+impl Locator {
+    pub fn new(subnets: Vec<Subnet>) -> Locator {
+        Locator {
+            subnets,
+            ..Default::default()
+        }
+    }
+
+    pub async fn find(&self, sn: &str) -> Result<Option<DiscoveredDevice>> {
+        let find = |devices: Vec<DiscoveredDevice>| devices.into_iter().find(|d| d.serial == sn);
+
+        let devices = self
+            .discover
+            .run()
+            .await
+            .context("Failed to discover devices.")?;
+        if let Some(device) = find(devices) {
+            return Ok(Some(device));
+        }
+
+        if self.subnets.is_empty() {
+            return Ok(None);
+        }
+        let targets = self
+            .subnets
+            .iter()
+            .flat_map(Subnet::hosts)
+            .map(|ip| SocketAddr::from((ip, MULTICAST_ADDR.port())))
+            .collect();
+        let devices = self
+            .discover
+            .clone()
+            .targets(targets)
+            .run()
+            .await
+            .context("Failed to sweep subnets.")?;
+
+        Ok(find(devices))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_subnet() {
+        let subnet: Subnet = "192.168.1.77/24".parse().unwrap();
+        let hosts: Vec<_> = subnet.hosts().collect();
+        assert_eq!(hosts.len(), 254);
+        assert_eq!(hosts[0], Ipv4Addr::new(192, 168, 1, 1));
+        assert_eq!(hosts[253], Ipv4Addr::new(192, 168, 1, 254));
+        assert_eq!(subnet.to_string(), "192.168.1.77/24");
+
+        let single: Subnet = "10.0.0.5/32".parse().unwrap();
+        assert_eq!(
+            single.hosts().collect::<Vec<_>>(),
+            [Ipv4Addr::new(10, 0, 0, 5)]
+        );
+
+        assert!("192.168.1.0".parse::<Subnet>().is_err());
+        assert!("192.168.1.0/8".parse::<Subnet>().is_err());
+        assert!("192.168.1.0/33".parse::<Subnet>().is_err());
+        assert!("camera/24".parse::<Subnet>().is_err());
+    }
+}
@@ -1,22 +1,133 @@
+use std::{fmt, str::FromStr};
+
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use utoipa::ToSchema;
 
 mod page;
 mod query;
 mod scan;
+mod validate;
+
+pub use validate::ValidationError;
+pub(crate) use validate::{credential_env, credential_file};
+
+#[cfg(test)]
+pub(crate) use validate::testing;
+
+/// Tells a missing field apart from null, the field also needs `#[serde(default)]`.
+fn deserialize_some<'de, D, T>(de: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(de).map(Some)
+}
+
+#[derive(
+    Serialize, Deserialize, sqlx::Type, ToSchema, Clone, Copy, Default, PartialEq, Eq, Debug,
+)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum CameraScheme {
+    #[default]
+    Http,
+    Https,
+}
+
+impl FromStr for CameraScheme {
+    type Err = ValidationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "http" => Ok(CameraScheme::Http),
+            "https" => Ok(CameraScheme::Https),
+            _ => Err(ValidationError(format!("invalid scheme '{s}'"))),
+        }
+    }
+}
+
+impl fmt::Display for CameraScheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CameraScheme::Http => f.write_str("http"),
+            CameraScheme::Https => f.write_str("https"),
+        }
+    }
+}
+
+/// Where the username and password of a camera come from.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Default, PartialEq, Eq, Debug)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum CredentialSource {
+    /// Username and password of the camera.
+    #[default]
+    Literal,
+    /// Shared credential that many cameras use.
+    Shared { credential_id: i64 },
+    /// Password in an environment variable of the station that starts with `IPCMANVIEW_CRED_`,
+    /// username of the camera.
+    Env { name: String },
+    /// Password in a file inside of the credential directory such as a secret mount, username
+    /// of the camera.
+    File { path: String },
+}
 
 #[derive(Deserialize, ToSchema, Debug)]
 pub struct CreateCameraRequest {
+    /// Host name or IP address, `host:port` is also accepted when port is not set.
     pub ip: String,
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub scheme: CameraScheme,
+    /// Accept self-signed certificates when the scheme is https.
+    #[serde(default)]
+    pub accept_invalid_certs: bool,
+    #[serde(default)]
     pub username: String,
+    #[serde(default)]
     pub password: String,
+    #[serde(default)]
+    pub credential: CredentialSource,
 }
 
 #[derive(Deserialize, ToSchema, Debug)]
 pub struct UpdateCameraRequest {
     pub id: i64,
+    /// A new ip drops the current port unless port is set.
     pub ip: Option<String>,
+    /// Null goes back to the default port of the scheme.
+    #[serde(default, deserialize_with = "deserialize_some")]
+    #[schema(value_type = Option<u16>)]
+    pub port: Option<Option<u16>>,
+    pub scheme: Option<CameraScheme>,
+    pub accept_invalid_certs: Option<bool>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub credential: Option<CredentialSource>,
+}
+
+/// Username and password shared by many cameras, the password is never shown.
+#[derive(Serialize, ToSchema, Debug)]
+pub struct Credential {
+    pub id: i64,
+    pub name: String,
+    pub username: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize, ToSchema, Debug)]
+pub struct CreateCredentialRequest {
+    pub name: String,
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Deserialize, ToSchema, Debug)]
+pub struct UpdateCredentialRequest {
+    pub id: i64,
+    pub name: Option<String>,
     pub username: Option<String>,
     pub password: Option<String>,
 }
@@ -24,19 +135,40 @@ pub struct UpdateCameraRequest {
 #[derive(Serialize, ToSchema, Debug)]
 pub struct Camera {
     pub id: i64,
+    /// Host name or IP address.
     pub ip: String,
+    /// Port when it is not the default of the scheme.
+    pub port: Option<u16>,
+    pub scheme: CameraScheme,
+    pub accept_invalid_certs: bool,
     pub username: String,
+    pub channels: i64,
     pub refreshed_at: DateTime<Utc>,
     pub created_at: DateTime<Utc>,
 }
 
+impl Camera {
+    /// Host with the port when it is set.
+    pub fn address(&self) -> String {
+        match self.port {
+            Some(port) => format!("{}:{port}", self.ip),
+            None => self.ip.clone(),
+        }
+    }
+}
+
 #[derive(Serialize, ToSchema, Debug)]
 pub struct CameraShow {
     pub id: i64,
     pub ip: String,
+    pub port: Option<u16>,
+    pub scheme: CameraScheme,
+    pub accept_invalid_certs: bool,
     pub username: String,
+    pub channels: i64,
     pub refreshed_at: DateTime<Utc>,
     pub created_at: DateTime<Utc>,
+    pub credential: CredentialSource,
     pub detail: CameraDetail,
     pub software: CameraSoftware,
     pub file_total: i32,
@@ -54,6 +186,33 @@ pub struct CameraDetail {
     pub vendor: String,
 }
 
+/// Address of a camera that was changed after it was found by serial number.
+#[derive(Serialize, ToSchema, Debug)]
+pub struct CameraAddressChange {
+    pub id: i64,
+    pub camera_id: i64,
+    pub sn: String,
+    pub old_ip: String,
+    pub new_ip: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Device found on the LAN with DHDiscover.
+#[derive(Serialize, ToSchema, Debug)]
+pub struct DiscoveredCamera {
+    pub ip: String,
+    pub port: u16,
+    pub mac: String,
+    pub sn: String,
+    pub device_class: String,
+    pub device_type: String,
+    /// Firmware version.
+    pub version: String,
+    pub vendor: String,
+    /// Camera with the same IP address or serial number.
+    pub camera_id: Option<i64>,
+}
+
 #[derive(Serialize, ToSchema, Debug)]
 pub struct CameraSoftware {
     pub build: String,
@@ -76,10 +235,42 @@ pub struct CameraLicense {
     pub username: String,
 }
 
+#[derive(Serialize, ToSchema, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum CameraLoginState {
+    Logout,
+    Login,
+    Locked,
+    Error,
+}
+
+/// Login state of the camera's client, lock fields are only known after the first login.
+#[derive(Serialize, ToSchema, Debug)]
+pub struct CameraLogin {
+    pub state: CameraLoginState,
+    pub error: Option<String>,
+    /// Seconds left until the camera unlocks logins.
+    pub locked_seconds: Option<u64>,
+    pub lock_enabled: Option<bool>,
+    /// Seconds the camera locks the user for.
+    pub lock_time: Option<u64>,
+}
+
+/// Capabilities are empty until the camera has been refreshed or when it cannot list them.
+#[derive(Serialize, ToSchema, Debug)]
+pub struct CameraCapabilities {
+    #[schema(value_type = Vec<String>)]
+    pub methods: sqlx::types::Json<Vec<String>>,
+    #[schema(value_type = Vec<String>)]
+    pub services: sqlx::types::Json<Vec<String>>,
+    pub updated_at: DateTime<Utc>,
+}
+
 #[derive(Serialize, sqlx::FromRow, ToSchema, Debug)]
 pub struct CameraFile {
     pub id: i64,
     pub camera_id: i64,
+    pub channel: i64,
     pub file_path: String,
     pub kind: String,
     pub size: i64,
@@ -92,6 +283,20 @@ pub struct CameraFile {
 
 pub struct IpcEvent {}
 
+#[derive(Serialize, sqlx::FromRow, ToSchema, Debug)]
+pub struct CameraEvent {
+    pub id: i64,
+    pub camera_id: i64,
+    pub code: String,
+    pub action: String,
+    #[sqlx(rename = "idx")]
+    pub index: i64,
+    pub start_time: DateTime<Utc>,
+    pub end_time: Option<DateTime<Utc>>,
+    #[schema(value_type = Object)]
+    pub data: sqlx::types::Json<serde_json::Value>,
+}
+
 pub struct Page {
     pub page: i32,
     pub per_page: i32,
@@ -119,17 +324,21 @@ pub struct CameraFileQueryFilter {
     pub start: Option<DateTime<Utc>>,
     pub end: Option<DateTime<Utc>>,
     pub camera_ids: Vec<i64>,
+    pub channels: Vec<i64>,
     pub kinds: Vec<String>,
     pub events: Vec<String>,
 }
 
+/// Page of a filter that is ordered by start time.
 #[derive(Debug)]
-pub struct CameraFileQuery<'a> {
+pub struct CursorQuery<'a, F> {
     pub cursor: CameraFileQueryCursor,
     pub limit: i32,
-    pub filter: &'a CameraFileQueryFilter,
+    pub filter: &'a F,
 }
 
+pub type CameraFileQuery<'a> = CursorQuery<'a, CameraFileQueryFilter>;
+
 #[derive(Serialize, ToSchema, Debug)]
 pub struct CameraFileQueryResult {
     pub files: Vec<CameraFile>,
@@ -140,11 +349,37 @@ pub struct CameraFileQueryResult {
     pub count: i32,
 }
 
+#[derive(Debug)]
+pub struct CameraEventQueryFilter {
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+    pub camera_ids: Vec<i64>,
+    pub codes: Vec<String>,
+    pub actions: Vec<String>,
+}
+
+pub type CameraEventQuery<'a> = CursorQuery<'a, CameraEventQueryFilter>;
+
+#[derive(Serialize, ToSchema, Debug)]
+pub struct CameraEventQueryResult {
+    pub events: Vec<CameraEvent>,
+    pub has_before: bool,
+    pub before: String,
+    pub has_after: bool,
+    pub after: String,
+    pub count: i32,
+}
+
 pub struct ICamera {
     pub id: i64,
     pub ip: String,
+    pub port: Option<u16>,
+    pub scheme: CameraScheme,
+    pub accept_invalid_certs: bool,
+    /// Resolved from credential.
     pub username: String,
     pub password: String,
+    pub credential: CredentialSource,
 }
 
 #[derive(Default, Debug)]
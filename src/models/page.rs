@@ -1,18 +1,15 @@
 use super::{Page, PageResult};
 
 impl Page {
-    pub fn new(mut page: i32, mut per_page: i32) -> Self {
+    pub fn new(mut page: i32, per_page: i32) -> Self {
         if page < 1 {
             page = 1;
         }
-        if per_page < 5 {
-            per_page = 5;
-        }
-        if per_page > 100 {
-            per_page = 100
-        }
 
-        Self { page, per_page }
+        Self {
+            page,
+            per_page: per_page.clamp(5, 100),
+        }
     }
 
     pub fn offset(&self) -> i32 {
@@ -1,7 +1,9 @@
 use anyhow::{Context, Result};
 use chrono::{DateTime, NaiveDateTime, Utc};
 
-use crate::models::{CameraFileQuery, CameraFileQueryCursor, CameraFileQueryFilter};
+use crate::models::{
+    CameraEventQueryFilter, CameraFileQueryCursor, CameraFileQueryFilter, CursorQuery,
+};
 
 impl CameraFileQueryCursor {
     fn from_(cursor: &str) -> Result<(i64, DateTime<Utc>)> {
@@ -26,12 +28,19 @@ impl CameraFileQueryCursor {
     }
 }
 
+impl Default for CameraFileQueryFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl CameraFileQueryFilter {
     pub fn new() -> Self {
         CameraFileQueryFilter {
             start: None,
             end: None,
             camera_ids: vec![],
+            channels: vec![],
             kinds: vec![],
             events: vec![],
         }
@@ -52,6 +61,54 @@ impl CameraFileQueryFilter {
         self
     }
 
+    pub fn channels(mut self, channels: Vec<i64>) -> Self {
+        self.channels = channels;
+        self
+    }
+
+    pub fn start(mut self, start: Option<DateTime<Utc>>) -> Self {
+        self.start = start;
+        self
+    }
+
+    pub fn end(mut self, end: Option<DateTime<Utc>>) -> Self {
+        self.end = end;
+        self
+    }
+}
+
+impl Default for CameraEventQueryFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CameraEventQueryFilter {
+    pub fn new() -> Self {
+        CameraEventQueryFilter {
+            start: None,
+            end: None,
+            camera_ids: vec![],
+            codes: vec![],
+            actions: vec![],
+        }
+    }
+
+    pub fn codes(mut self, codes: Vec<String>) -> Self {
+        self.codes = codes;
+        self
+    }
+
+    pub fn actions(mut self, actions: Vec<String>) -> Self {
+        self.actions = actions;
+        self
+    }
+
+    pub fn camera_ids(mut self, camera_ids: Vec<i64>) -> Self {
+        self.camera_ids = camera_ids;
+        self
+    }
+
     pub fn start(mut self, start: Option<DateTime<Utc>>) -> Self {
         self.start = start;
         self
@@ -63,9 +120,9 @@ impl CameraFileQueryFilter {
     }
 }
 
-impl<'a> CameraFileQuery<'a> {
-    pub fn new(filter: &'a CameraFileQueryFilter) -> Self {
-        CameraFileQuery {
+impl<'a, F> CursorQuery<'a, F> {
+    pub fn new(filter: &'a F) -> Self {
+        CursorQuery {
             cursor: CameraFileQueryCursor::None,
             limit: 25,
             filter,
@@ -74,13 +131,7 @@ impl<'a> CameraFileQuery<'a> {
 
     pub fn maybe_limit(mut self, limit: Option<i32>) -> Self {
         if let Some(limit) = limit {
-            self.limit = if limit > 100 {
-                100
-            } else if limit < 10 {
-                10
-            } else {
-                limit
-            };
+            self.limit = limit.clamp(10, 100);
         }
         self
     }
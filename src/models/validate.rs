@@ -0,0 +1,347 @@
+use std::path::PathBuf;
+
+use dahua_rpc::endpoint::split_host_port;
+
+use super::{
+    CreateCameraRequest, CreateCredentialRequest, CredentialSource, UpdateCameraRequest,
+    UpdateCredentialRequest,
+};
+
+#[derive(thiserror::Error, Debug)]
+#[error("Invalid request: {0}")]
+pub struct ValidationError(pub String);
+
+/// Splits ip into the host and the port it might contain.
+fn split_ip(ip: &str) -> Result<(&str, Option<u16>), ValidationError> {
+    if ip.is_empty() {
+        return Err(ValidationError("ip is required".to_string()));
+    }
+    if ip.contains("://") || ip.contains(['/', '@', '?', '#']) || ip.contains(char::is_whitespace) {
+        return Err(ValidationError(format!(
+            "ip '{ip}' must be a host without a scheme or path"
+        )));
+    }
+
+    let (host, port) =
+        split_host_port(ip).map_err(|_| ValidationError(format!("invalid port in ip '{ip}'")))?;
+    if host.is_empty() {
+        return Err(ValidationError("ip is required".to_string()));
+    }
+
+    Ok((host, port))
+}
+
+fn check_port(port: Option<u16>) -> Result<(), ValidationError> {
+    if port == Some(0) {
+        return Err(ValidationError("port must not be 0".to_string()));
+    }
+    Ok(())
+}
+
+/// Environment variables of credential sources have to start with this prefix.
+pub const CREDENTIAL_ENV_PREFIX: &str = "IPCMANVIEW_CRED_";
+
+/// Directory that holds the files of credential sources, set with `CREDENTIAL_DIR`.
+pub fn credential_dir() -> PathBuf {
+    std::env::var_os("CREDENTIAL_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/run/secrets"))
+}
+
+/// Checks that the environment variable can hold a camera password.
+pub(crate) fn credential_env(name: &str) -> Result<&str, ValidationError> {
+    if name.is_empty() {
+        return Err(ValidationError(
+            "name of the environment variable is required".to_string(),
+        ));
+    }
+    match name.strip_prefix(CREDENTIAL_ENV_PREFIX) {
+        Some(rest)
+            if !rest.is_empty()
+                && rest
+                    .chars()
+                    .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_') =>
+        {
+            Ok(name)
+        }
+        _ => Err(ValidationError(format!(
+            "environment variable must match {CREDENTIAL_ENV_PREFIX}[A-Z0-9_]+"
+        ))),
+    }
+}
+
+/// Canonical path of a credential file, which has to be inside of the credential directory.
+/// Relative paths are relative to the credential directory.
+pub(crate) fn credential_file(path: &str) -> Result<PathBuf, ValidationError> {
+    if path.is_empty() {
+        return Err(ValidationError(
+            "path of the credential file is required".to_string(),
+        ));
+    }
+    let dir = credential_dir();
+    let outside = || {
+        ValidationError(format!(
+            "credential file must be inside of {}",
+            dir.display()
+        ))
+    };
+    let root = std::fs::canonicalize(&dir).map_err(|_| outside())?;
+    // Symlinks and .. are resolved before the check
+    let path = std::fs::canonicalize(root.join(path)).map_err(|_| outside())?;
+    if path == root || !path.starts_with(&root) {
+        return Err(outside());
+    }
+    Ok(path)
+}
+
+impl CredentialSource {
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        match self {
+            CredentialSource::Env { name } => credential_env(name).map(|_| ()),
+            CredentialSource::File { path } => credential_file(path).map(|_| ()),
+            CredentialSource::Literal | CredentialSource::Shared { .. } => Ok(()),
+        }
+    }
+
+    /// Shared credentials bring their own username.
+    pub(crate) fn needs_username(&self) -> bool {
+        !matches!(self, CredentialSource::Shared { .. })
+    }
+}
+
+impl CreateCameraRequest {
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        self.credential.validate()?;
+        if self.credential.needs_username() && self.username.is_empty() {
+            return Err(ValidationError("username is required".to_string()));
+        }
+        self.address().map(|_| ())
+    }
+
+    /// Host and port of the camera, ip may contain the port.
+    pub(crate) fn address(&self) -> Result<(&str, Option<u16>), ValidationError> {
+        let (host, ip_port) = split_ip(&self.ip)?;
+        if ip_port.is_some() && self.port.is_some() {
+            return Err(ValidationError(
+                "port is set in both ip and port".to_string(),
+            ));
+        }
+        let port = self.port.or(ip_port);
+        check_port(port)?;
+
+        Ok((host, port))
+    }
+}
+
+impl UpdateCameraRequest {
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        if let Some(credential) = &self.credential {
+            credential.validate()?;
+        }
+        self.address().map(|_| ())
+    }
+
+    /// Host and port to store, None keeps the current value.
+    ///
+    /// A new host never keeps the port of the old one.
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn address(&self) -> Result<(Option<&str>, Option<Option<u16>>), ValidationError> {
+        let port = self.port.flatten();
+        check_port(port)?;
+        let Some(ip) = &self.ip else {
+            return Ok((None, self.port));
+        };
+
+        let (host, ip_port) = split_ip(ip)?;
+        if ip_port.is_some() && port.is_some() {
+            return Err(ValidationError(
+                "port is set in both ip and port".to_string(),
+            ));
+        }
+        check_port(ip_port)?;
+
+        Ok((Some(host), Some(port.or(ip_port))))
+    }
+}
+
+impl CreateCredentialRequest {
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        if self.name.is_empty() {
+            return Err(ValidationError("name is required".to_string()));
+        }
+        if self.username.is_empty() {
+            return Err(ValidationError("username is required".to_string()));
+        }
+        Ok(())
+    }
+}
+
+impl UpdateCredentialRequest {
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        if self.name.as_deref() == Some("") {
+            return Err(ValidationError("name must not be empty".to_string()));
+        }
+        if self.username.as_deref() == Some("") {
+            return Err(ValidationError("username must not be empty".to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod testing {
+    use std::{path::PathBuf, sync::OnceLock};
+
+    /// Points the credential directory of every test at the same temporary directory.
+    pub fn credential_dir() -> PathBuf {
+        static DIR: OnceLock<PathBuf> = OnceLock::new();
+        DIR.get_or_init(|| {
+            let dir = std::env::temp_dir().join("ipcmanview-test-credentials");
+            std::fs::create_dir_all(&dir).unwrap();
+            std::env::set_var("CREDENTIAL_DIR", &dir);
+            dir
+        })
+        .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::CameraScheme;
+
+    use super::*;
+
+    fn create(ip: &str, port: Option<u16>) -> CreateCameraRequest {
+        CreateCameraRequest {
+            ip: ip.to_string(),
+            port,
+            scheme: CameraScheme::Https,
+            accept_invalid_certs: true,
+            username: "admin".to_string(),
+            password: "123".to_string(),
+            credential: CredentialSource::Literal,
+        }
+    }
+
+    fn update(ip: Option<&str>, port: Option<Option<u16>>) -> UpdateCameraRequest {
+        UpdateCameraRequest {
+            id: 1,
+            ip: ip.map(str::to_string),
+            port,
+            scheme: None,
+            accept_invalid_certs: None,
+            username: None,
+            password: None,
+            credential: None,
+        }
+    }
+
+    #[test]
+    fn it_create_address() {
+        assert_eq!(
+            create("192.168.1.108", None).address().unwrap(),
+            ("192.168.1.108", None)
+        );
+        assert_eq!(
+            create("192.168.1.108", Some(8443)).address().unwrap(),
+            ("192.168.1.108", Some(8443))
+        );
+        assert_eq!(
+            create("cam.local:8443", None).address().unwrap(),
+            ("cam.local", Some(8443))
+        );
+        assert_eq!(
+            create("[::1]", Some(80)).address().unwrap(),
+            ("[::1]", Some(80))
+        );
+
+        for (ip, port) in [
+            ("", None),
+            ("https://cam.local", None),
+            ("cam.local/RPC2", None),
+            ("cam local", None),
+            ("cam.local:8443", Some(8443)),
+            ("cam.local", Some(0)),
+            ("cam.local:abc", None),
+        ] {
+            assert!(create(ip, port).validate().is_err(), "{ip} {port:?}");
+        }
+    }
+
+    #[test]
+    fn it_update_address() {
+        assert_eq!(update(None, None).address().unwrap(), (None, None));
+        assert_eq!(
+            update(None, Some(Some(82))).address().unwrap(),
+            (None, Some(Some(82)))
+        );
+        assert_eq!(
+            update(None, Some(None)).address().unwrap(),
+            (None, Some(None))
+        );
+        // The port of the old host is not carried over
+        assert_eq!(
+            update(Some("other"), None).address().unwrap(),
+            (Some("other"), Some(None))
+        );
+        assert_eq!(
+            update(Some("other:83"), None).address().unwrap(),
+            (Some("other"), Some(Some(83)))
+        );
+        assert_eq!(
+            update(Some("other"), Some(Some(84))).address().unwrap(),
+            (Some("other"), Some(Some(84)))
+        );
+        assert!(update(Some("other:83"), Some(Some(84))).address().is_err());
+        assert!(update(None, Some(Some(0))).validate().is_err());
+    }
+
+    #[test]
+    fn it_credential_source() {
+        let mut req = create("cam.local", None);
+        req.credential = CredentialSource::Env {
+            name: String::new(),
+        };
+        assert!(req.validate().is_err());
+
+        req.credential = CredentialSource::Env {
+            name: "STATION_SECRET".to_string(),
+        };
+        assert!(req.validate().is_err());
+        req.credential = CredentialSource::Env {
+            name: "IPCMANVIEW_CRED_".to_string(),
+        };
+        assert!(req.validate().is_err());
+        req.credential = CredentialSource::Env {
+            name: "IPCMANVIEW_CRED_CAMERA_1".to_string(),
+        };
+        assert!(req.validate().is_ok());
+
+        let dir = testing::credential_dir();
+        std::fs::write(dir.join("camera"), "password").unwrap();
+        req.credential = CredentialSource::File {
+            path: dir.join("camera").display().to_string(),
+        };
+        assert!(req.validate().is_ok());
+        req.credential = CredentialSource::File {
+            path: "camera".to_string(),
+        };
+        assert!(req.validate().is_ok());
+        for path in ["/etc/passwd", "../camera", "", ".", "missing"] {
+            req.credential = CredentialSource::File {
+                path: path.to_string(),
+            };
+            assert!(req.validate().is_err(), "{path}");
+        }
+
+        req.credential = CredentialSource::File {
+            path: "camera".to_string(),
+        };
+        req.username = String::new();
+        assert!(req.validate().is_err());
+
+        // Shared credentials bring their own username
+        req.credential = CredentialSource::Shared { credential_id: 1 };
+        assert!(req.validate().is_ok());
+    }
+}
@@ -1,11 +1,22 @@
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
+use dahua_rpc::{
+    discover::{Discover, DiscoveredDevice},
+    modules::usermanager,
+    multicall::Multicall,
+};
 use sqlx::SqlitePool;
 
-use crate::ipc::{IpcDetail, IpcLicenses, IpcManager, IpcSoftware, IpcStore};
+use crate::db::{Conflict, NotFound};
+use crate::ipc::{
+    IpcCapabilities, IpcChannels, IpcDetail, IpcLicenses, IpcManager, IpcSoftware, IpcStore,
+};
+use crate::locate::Locator;
 use crate::models::{
-    Camera, CameraDetail, CameraFile, CameraFileQuery, CameraFileQueryCursor,
+    Camera, CameraAddressChange, CameraDetail, CameraFile, CameraFileQuery, CameraFileQueryCursor,
     CameraFileQueryFilter, CameraFileQueryResult, CameraLicense, CameraScanResult, CameraShow,
-    CameraSoftware, CreateCameraRequest, ScanCompleted, UpdateCameraRequest,
+    CameraSoftware, CreateCameraRequest, CreateCredentialRequest, Credential, CredentialSource,
+    DiscoveredCamera, ICamera, ScanCompleted, UpdateCameraRequest, UpdateCredentialRequest,
+    ValidationError,
 };
 use crate::scan::{Scan, ScanActor, ScanKindPending};
 
@@ -13,8 +24,10 @@ use crate::scan::{Scan, ScanActor, ScanKindPending};
 
 impl CreateCameraRequest {
     pub async fn create(self, pool: &SqlitePool, store: &IpcStore) -> Result<i64> {
+        self.validate()?;
+        self.credential.check(pool).await?;
         // Create in database
-        let id = self.create_db(pool).await?;
+        let id = self.create_db(pool, store.secret()).await?;
         // Refresh in store
         store.refresh(id).await?;
         // Get from store and refresh in database
@@ -28,9 +41,13 @@ impl CreateCameraRequest {
 
 impl UpdateCameraRequest {
     pub async fn update(self, pool: &SqlitePool, store: &IpcStore) -> Result<()> {
+        self.validate()?;
+        if let Some(credential) = &self.credential {
+            credential.check(pool).await?;
+        }
         let id = self.id;
         // Update in database
-        self.update_db(pool).await?;
+        self.update_db(pool, store.secret()).await?;
         // Refresh in store
         store.refresh(id).await?;
         // Get from store and refresh in database
@@ -51,6 +68,52 @@ impl Camera {
     }
 }
 
+impl CredentialSource {
+    /// Shared credentials have to exist before a camera can use them.
+    async fn check(&self, pool: &SqlitePool) -> Result<()> {
+        let Some(credential_id) = self.credential_id() else {
+            return Ok(());
+        };
+        match Credential::find(pool, credential_id).await {
+            Ok(_) => Ok(()),
+            Err(e) if NotFound == e => {
+                Err(ValidationError(format!("credential {credential_id} does not exist")).into())
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+// -------------------- Credential
+
+impl CreateCredentialRequest {
+    pub async fn create(self, pool: &SqlitePool, store: &IpcStore) -> Result<i64> {
+        self.validate()?;
+        self.create_db(pool, store.secret()).await
+    }
+}
+
+impl UpdateCredentialRequest {
+    /// Every camera that uses the credential logs in again.
+    pub async fn update(self, pool: &SqlitePool, store: &IpcStore) -> Result<()> {
+        self.validate()?;
+        let id = self.id;
+        self.update_db(pool, store.secret()).await?;
+
+        for camera_id in Credential::camera_ids(pool, id).await? {
+            store.refresh(camera_id).await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Credential {
+    pub async fn delete(pool: &SqlitePool, id: i64) -> Result<()> {
+        Self::delete_db(pool, id).await
+    }
+}
+
 impl CameraShow {
     // TODO: make this into a single query inside of db crate
     pub async fn find(pool: &SqlitePool, id: i64) -> Result<Self> {
@@ -58,6 +121,7 @@ impl CameraShow {
         let software = CameraSoftware::find(pool, id).await?;
         let licenses = CameraLicense::list(pool, id).await?;
         let camera = Camera::find(pool, id).await?;
+        let credential = CredentialSource::find(pool, id).await?;
 
         let file_total =
             CameraFile::total(pool, &CameraFileQueryFilter::new().camera_ids(vec![id])).await?;
@@ -65,9 +129,14 @@ impl CameraShow {
         Ok(CameraShow {
             id: camera.id,
             ip: camera.ip,
+            port: camera.port,
+            scheme: camera.scheme,
+            accept_invalid_certs: camera.accept_invalid_certs,
             username: camera.username,
+            channels: camera.channels,
             refreshed_at: camera.refreshed_at,
             created_at: camera.created_at,
+            credential,
             detail,
             software,
             licenses,
@@ -78,22 +147,40 @@ impl CameraShow {
 
 impl IpcManager {
     pub async fn refresh(&self, pool: &SqlitePool) -> Result<()> {
-        self.refresh_detail(pool).await?;
-        self.refresh_licenses(pool).await?;
-        self.refresh_software(pool).await?;
+        // Get everything in a single request
+        let capabilities = self.client.capabilities().await?;
+        let mut mc = Multicall::new();
+        let detail = IpcDetail::call(&mut mc, &capabilities);
+        let licenses = IpcLicenses::call(&mut mc, &capabilities);
+        let software = IpcSoftware::call(&mut mc, &capabilities);
+        let mut res = self.multicall(mc).await?;
+
+        detail.take(&mut res)?.save(pool, self.id).await?;
+        licenses.take(&mut res)?.save(pool, self.id).await?;
+        software.take(&mut res)?.save(pool, self.id).await?;
+        self.refresh_capabilities(pool).await?;
+        self.refresh_channels(pool).await?;
         Camera::update_refreshed_at(pool, self.id).await
     }
 
     pub async fn refresh_detail(&self, pool: &SqlitePool) -> Result<()> {
-        IpcDetail::get(&self).await?.save(pool, self.id).await
+        IpcDetail::get(self).await?.save(pool, self.id).await
     }
 
     pub async fn refresh_licenses(&self, pool: &SqlitePool) -> Result<()> {
-        IpcLicenses::get(&self).await?.save(pool, self.id).await
+        IpcLicenses::get(self).await?.save(pool, self.id).await
     }
 
     pub async fn refresh_software(&self, pool: &SqlitePool) -> Result<()> {
-        IpcSoftware::get(&self).await?.save(pool, self.id).await
+        IpcSoftware::get(self).await?.save(pool, self.id).await
+    }
+
+    pub async fn refresh_capabilities(&self, pool: &SqlitePool) -> Result<()> {
+        IpcCapabilities::get(self).await?.save(pool, self.id).await
+    }
+
+    pub async fn refresh_channels(&self, pool: &SqlitePool) -> Result<()> {
+        IpcChannels::get(self).await?.save(pool, self.id).await
     }
 }
 
@@ -112,6 +199,231 @@ impl CameraFile {
     }
 }
 
+impl IpcManager {
+    /// Looks for the camera by serial number and moves it to the address it was found at, the
+    /// port is kept.
+    pub async fn relocate(
+        &self,
+        pool: &SqlitePool,
+        store: &IpcStore,
+        locator: &Locator,
+    ) -> Result<Option<CameraAddressChange>> {
+        let sn = CameraDetail::find(pool, self.id).await?.sn;
+        if sn.is_empty() {
+            bail!("Camera with id {} has no serial number.", self.id);
+        }
+        let Some(device) = locator.find(&sn).await? else {
+            return Ok(None);
+        };
+
+        let old_ip = Camera::find(pool, self.id).await?.ip;
+        if old_ip == device.ip {
+            return Ok(None);
+        }
+
+        let change =
+            CameraAddressChange::create_db(pool, self.id, &sn, &old_ip, &device.ip).await?;
+        tracing::info!(
+            "Camera {} with serial number {sn} moved from {old_ip} to {}.",
+            self.id,
+            device.ip
+        );
+        store.refresh(self.id).await?;
+
+        Ok(Some(change))
+    }
+
+    /// Changes the password of the camera's user on the device, the database is only updated
+    /// after the device accepted it.
+    ///
+    /// The new password is stored as pending first, which fails while another rotation is
+    /// pending. It is only dropped when the device answered, so it survives when the request or
+    /// the database fails after the device changed it. A request that failed is followed by a
+    /// login with the pending password in case only the reply was lost.
+    pub async fn rotate_password(
+        &self,
+        pool: &SqlitePool,
+        store: &IpcStore,
+        password: &str,
+    ) -> Result<()> {
+        if password.is_empty() {
+            return Err(ValidationError("password is required".to_string()).into());
+        }
+        let camera = ICamera::find_optional(pool, store.secret(), self.id)
+            .await?
+            .ok_or(NotFound)
+            .with_context(|| format!("Failed to find camera with id {}.", self.id))?;
+        if camera.credential != CredentialSource::Literal {
+            return Err(ValidationError(format!(
+                "password of camera {} comes from a {} credential source",
+                self.id,
+                camera.credential.kind()
+            ))
+            .into());
+        }
+
+        // Also refuses to start while another rotation is pending
+        Camera::begin_password_rotation_db(pool, store.secret(), self.id, password).await?;
+        let res = usermanager::modify_password(
+            self.rpc().await?,
+            &camera.username,
+            &camera.password,
+            password,
+        )
+        .await;
+        match res {
+            Ok(true) => {}
+            Ok(false) => {
+                Camera::discard_pending_password_db(pool, self.id).await?;
+                bail!("Camera {} did not accept the new password.", self.id);
+            }
+            // The device answered, it still has the old password
+            Err(err @ dahua_rpc::Error::Response(_)) => {
+                Camera::discard_pending_password_db(pool, self.id).await?;
+                return Err(err)
+                    .with_context(|| format!("Failed to change password on camera {}.", self.id));
+            }
+            // The device might have changed the password before the reply was lost
+            Err(err) => {
+                if let Ok(true) = self.recover_password(pool, store).await {
+                    return Ok(());
+                }
+                return Err(err).with_context(|| {
+                    format!(
+                    "Failed to change password on camera {}, the new password is kept as pending.",
+                    self.id
+                )
+                });
+            }
+        }
+
+        Camera::confirm_password_db(pool, self.id)
+            .await
+            .with_context(|| {
+                format!(
+                    "Camera {} changed its password, the new password is kept as pending.",
+                    self.id
+                )
+            })?;
+        tracing::info!(
+            "Changed password of user {} on camera {}.",
+            camera.username,
+            self.id
+        );
+        // Log in again with the new password
+        store.refresh(self.id).await?;
+
+        Ok(())
+    }
+
+    /// Logs in with the pending password of the camera and makes it the password when the
+    /// device accepts it, returns false when there is no pending password or the device rejects
+    /// it.
+    ///
+    /// A rejected pending password is kept, a rotation might still be waiting for the device.
+    pub async fn recover_password(&self, pool: &SqlitePool, store: &IpcStore) -> Result<bool> {
+        let Some(password) = Camera::pending_password_db(pool, store.secret(), self.id).await?
+        else {
+            return Ok(false);
+        };
+
+        let client = self.client.with_password(password);
+        match client.login().await {
+            Ok(()) => client.logout().await,
+            Err(dahua_rpc::Error::Login(
+                dahua_rpc::LoginError::UserOrPasswordNotValid
+                | dahua_rpc::LoginError::PasswordNotValid,
+            )) => return Ok(false),
+            Err(err) => {
+                return Err(err).with_context(|| {
+                    format!(
+                        "Failed to log in to camera {} with the pending password.",
+                        self.id
+                    )
+                })
+            }
+        }
+
+        match Camera::confirm_password_db(pool, self.id).await {
+            // Confirmed by the rotation or another recovery in the meantime
+            Err(err) if NotFound == err => {}
+            res => res?,
+        }
+        tracing::info!(
+            "Camera {} accepted its pending password, it is now the password.",
+            self.id
+        );
+        store.refresh(self.id).await?;
+
+        Ok(true)
+    }
+
+    /// Promotes the pending password of the camera after checking that the device accepts it, a
+    /// pending password the device rejects is discarded.
+    pub async fn confirm_pending_password(
+        &self,
+        pool: &SqlitePool,
+        store: &IpcStore,
+    ) -> Result<()> {
+        if Camera::pending_password_db(pool, store.secret(), self.id)
+            .await?
+            .is_none()
+        {
+            return Err(NotFound)
+                .with_context(|| format!("Camera with id {} has no pending password.", self.id));
+        }
+        if !self.recover_password(pool, store).await? {
+            self.discard_pending_password(pool).await?;
+            return Err(Conflict).with_context(|| {
+                format!(
+                    "Camera {} rejected its pending password, it was discarded.",
+                    self.id
+                )
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Drops the pending password of the camera, for when the device is known to still have the
+    /// old password.
+    pub async fn discard_pending_password(&self, pool: &SqlitePool) -> Result<()> {
+        Camera::discard_pending_password_db(pool, self.id).await
+    }
+}
+
+impl DiscoveredCamera {
+    /// Searches the LAN and marks the devices that are already cameras.
+    pub async fn discover(pool: &SqlitePool, discover: &Discover) -> Result<Vec<Self>> {
+        let mut devices: Vec<DiscoveredCamera> = discover
+            .run()
+            .await
+            .context("Failed to discover devices.")?
+            .into_iter()
+            .map(DiscoveredCamera::from)
+            .collect();
+        DiscoveredCamera::link_db(pool, &mut devices).await?;
+
+        Ok(devices)
+    }
+}
+
+impl From<DiscoveredDevice> for DiscoveredCamera {
+    fn from(value: DiscoveredDevice) -> Self {
+        DiscoveredCamera {
+            ip: value.ip,
+            port: value.http_port,
+            mac: value.mac,
+            sn: value.serial,
+            device_class: value.device_class,
+            device_type: value.device_type,
+            version: value.version,
+            vendor: value.vendor,
+            camera_id: None,
+        }
+    }
+}
+
 // -------------------- Scan
 
 impl Scan {
@@ -147,11 +459,8 @@ impl Scan {
 
         // Get rest of the pending scans
         let mut handles = vec![first_handle];
-        loop {
-            match ScanActor::next(&pool).await {
-                Ok(Some(handle)) => handles.push(handle),
-                Ok(None) | Err(_) => break,
-            }
+        while let Ok(Some(handle)) = ScanActor::next(pool).await {
+            handles.push(handle)
         }
 
         // Start worker for each scan
@@ -225,3 +534,431 @@ impl ScanActor {
         res
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+    use dahua_rpc::discover::Discover;
+    use dahua_rpc_mock::{Device, MockFile, MockResponder, MockServer};
+
+    use crate::db::testing::TempDb;
+    use crate::ipc::IpcPtz;
+    use crate::models::{testing, CameraScheme};
+    use crate::secret::Secret;
+
+    use super::*;
+
+    fn secret() -> Secret {
+        Secret::new("aXBjbWFudmlldyB0ZXN0IHNlY3JldCwgMzIgYnl0ZXM=").unwrap()
+    }
+
+    async fn setup() -> (TempDb, Device, MockServer, SqlitePool, IpcManager) {
+        let device = Device::new("admin", "password");
+        let server = MockServer::spawn(device.clone()).await.unwrap();
+        let (db, pool) = TempDb::new(&secret()).await;
+
+        let id = CreateCameraRequest {
+            ip: server.ip(),
+            port: None,
+            scheme: CameraScheme::Http,
+            accept_invalid_certs: false,
+            username: "admin".to_string(),
+            password: "password".to_string(),
+            credential: CredentialSource::Literal,
+        }
+        .create_db(&pool, &secret())
+        .await
+        .unwrap();
+        let icam = ICamera::find_optional(&pool, &secret(), id)
+            .await
+            .unwrap()
+            .unwrap();
+        let man = IpcManager::from((
+            icam,
+            dahua_rpc::recommended_reqwest_client_builder()
+                .build()
+                .unwrap(),
+        ));
+
+        (db, device, server, pool, man)
+    }
+
+    #[tokio::test]
+    async fn it_refresh_and_scan() {
+        let (_db, device, _server, pool, man) = setup().await;
+
+        device.set_channels(2);
+        let start = Utc.with_ymd_and_hms(2023, 6, 18, 10, 0, 0).unwrap();
+        let end = start + Duration::minutes(5);
+        device.add_file(MockFile::new(0, "dav", start, end));
+        device.add_file(MockFile::new(0, "jpg", start, start));
+        // Same start time on another channel
+        device.add_file(MockFile::new(1, "dav", start, end));
+
+        man.refresh(&pool).await.unwrap();
+        let show = CameraShow::find(&pool, man.id).await.unwrap();
+        assert_eq!(show.channels, 2);
+        assert_eq!(show.detail.sn, "MOCK0000000000");
+
+        let res = man
+            .scan_files(&pool, start, start + Duration::hours(1))
+            .await
+            .unwrap();
+        assert_eq!(res.upserted, 3);
+        assert_eq!(res.deleted, 0);
+
+        let filter = CameraFileQueryFilter::new()
+            .camera_ids(vec![man.id])
+            .channels(vec![1]);
+        assert_eq!(CameraFile::total(&pool, &filter).await.unwrap(), 1);
+
+        // Files removed from the camera are removed on the next scan
+        device.remove_file(&MockFile::new(1, "dav", start, end).file_path);
+        let res = man
+            .scan_files(&pool, start, start + Duration::hours(1))
+            .await
+            .unwrap();
+        assert_eq!(res.upserted, 2);
+        assert_eq!(res.deleted, 1);
+        assert_eq!(CameraFile::total(&pool, &filter).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn it_scan_channels() {
+        let (_db, device, _server, pool, man) = setup().await;
+
+        device.set_channels(2);
+        let start = Utc.with_ymd_and_hms(2023, 6, 18, 10, 0, 0).unwrap();
+        let end = start + Duration::minutes(5);
+        let file = MockFile::new(0, "dav", start, end);
+        device.add_file(file.clone());
+        // Same start time and path on another channel
+        device.add_file(MockFile { channel: 1, ..file });
+
+        man.refresh(&pool).await.unwrap();
+        let res = man
+            .scan_files(&pool, start, start + Duration::hours(1))
+            .await
+            .unwrap();
+        assert_eq!(res.upserted, 2);
+
+        let rows: Vec<(i64, DateTime<Utc>)> = sqlx::query_as(
+            "SELECT channel, start_time FROM camera_files WHERE camera_id = ? ORDER BY channel",
+        )
+        .bind(man.id)
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!((rows[0].0, rows[1].0), (0, 1));
+        assert_eq!(rows[0].1, rows[1].1);
+    }
+
+    #[tokio::test]
+    async fn it_refresh_unsupported() {
+        let (_db, device, _server, pool, man) = setup().await;
+
+        device.unsupport("License.getLicenseInfo");
+        man.refresh(&pool).await.unwrap();
+        assert!(!device
+            .calls()
+            .contains(&"License.getLicenseInfo".to_string()));
+        let show = CameraShow::find(&pool, man.id).await.unwrap();
+        assert_eq!(show.detail.sn, "MOCK0000000000");
+        assert!(show.licenses.is_empty());
+
+        // Errors of supported methods are no longer hidden
+        device.fail("magicBox.getVendor", 268632064, "Internal error");
+        assert!(man.refresh(&pool).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn it_relocate() {
+        let (_db, device, server, pool, man) = setup().await;
+        let store = IpcStore::new(pool.clone(), Locator::default(), secret())
+            .await
+            .unwrap();
+        man.refresh(&pool).await.unwrap();
+
+        // DHCP gave the camera a new address
+        let moved = SocketAddr::from(([127, 0, 0, 2], 80));
+        let responder = MockResponder::spawn(device, moved).await.unwrap();
+        let locator = Locator {
+            discover: Discover::new()
+                .targets(vec![responder.addr])
+                .timeout(std::time::Duration::from_millis(300)),
+            subnets: vec![],
+        };
+
+        let change = man
+            .relocate(&pool, &store, &locator)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(change.sn, "MOCK0000000000");
+        assert_eq!(change.old_ip, "127.0.0.1");
+        assert_eq!(change.new_ip, "127.0.0.2");
+        // The port stays with the camera
+        let camera = Camera::find(&pool, man.id).await.unwrap();
+        assert_eq!(camera.ip, "127.0.0.2");
+        assert_eq!(camera.port, Some(server.addr.port()));
+
+        let changes = CameraAddressChange::list(&pool, man.id).await.unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].old_ip, "127.0.0.1");
+
+        // Nothing to do when the camera is already at that address
+        assert!(man
+            .relocate(&pool, &store, &locator)
+            .await
+            .unwrap()
+            .is_none());
+
+        store.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn it_ptz_object() {
+        let (_db, device, _server, _pool, man) = setup().await;
+        device.respond("ptz.factory.instance", serde_json::Value::Null);
+        device.respond("ptz.getPresets", serde_json::json!({ "presets": [] }));
+        let instances = || {
+            device
+                .calls()
+                .iter()
+                .filter(|method| *method == "ptz.factory.instance")
+                .count()
+        };
+
+        // One object per channel and session
+        IpcPtz::new(man.clone(), 0).await.unwrap();
+        let ptz = IpcPtz::new(man.clone(), 0).await.unwrap();
+        assert!(ptz.presets().await.unwrap().is_empty());
+        assert_eq!(instances(), 1);
+        IpcPtz::new(man.clone(), 1).await.unwrap();
+        assert_eq!(instances(), 2);
+
+        man.client.logout().await;
+        IpcPtz::new(man.clone(), 0).await.unwrap();
+        assert_eq!(instances(), 3);
+    }
+
+    #[tokio::test]
+    async fn it_rotate_password() {
+        let (_db, device, _server, pool, man) = setup().await;
+        let store = IpcStore::new(pool.clone(), Locator::default(), secret())
+            .await
+            .unwrap();
+
+        assert!(man.rotate_password(&pool, &store, "").await.is_err());
+
+        man.rotate_password(&pool, &store, "new-password")
+            .await
+            .unwrap();
+        assert_eq!(device.password(), "new-password");
+        let icam = ICamera::find_optional(&pool, &secret(), man.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(icam.password, "new-password");
+
+        // The device rejects the stored password once it is out of date
+        sqlx::query("UPDATE cameras SET password = ? WHERE id = ?")
+            .bind(secret().seal("stale", "cameras", man.id))
+            .bind(man.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(man.rotate_password(&pool, &store, "other").await.is_err());
+        assert_eq!(device.password(), "new-password");
+        let icam = ICamera::find_optional(&pool, &secret(), man.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(icam.password, "stale");
+        let pending: Option<String> =
+            sqlx::query_scalar("SELECT pending_password FROM cameras WHERE id = ?")
+                .bind(man.id)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(pending, None);
+
+        // The store logs in with the password the device has again
+        sqlx::query("UPDATE cameras SET password = ? WHERE id = ?")
+            .bind(secret().seal("new-password", "cameras", man.id))
+            .bind(man.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        store.refresh(man.id).await.unwrap();
+        store.get(man.id).await.unwrap().rpc().await.unwrap();
+
+        // Only one rotation can be pending at a time
+        sqlx::query("UPDATE cameras SET pending_password = ? WHERE id = ?")
+            .bind(secret().seal("unknown", "cameras", man.id))
+            .bind(man.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(
+            crate::db::Conflict
+                == man
+                    .rotate_password(&pool, &store, "other")
+                    .await
+                    .unwrap_err()
+        );
+        assert_eq!(device.password(), "new-password");
+
+        // A pending password the device rejects is discarded
+        assert!(
+            crate::db::Conflict
+                == man
+                    .confirm_pending_password(&pool, &store)
+                    .await
+                    .unwrap_err()
+        );
+        assert!(
+            NotFound
+                == man
+                    .confirm_pending_password(&pool, &store)
+                    .await
+                    .unwrap_err()
+        );
+        assert!(NotFound == man.discard_pending_password(&pool).await.unwrap_err());
+
+        // The camera logs in with the password it took before the response was lost
+        device.lose_response("userManager.modifyPassword");
+        let man = store.get(man.id).await.unwrap();
+        man.rotate_password(&pool, &store, "lost").await.unwrap();
+        assert_eq!(device.password(), "lost");
+        let icam = ICamera::find_optional(&pool, &secret(), man.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(icam.password, "lost");
+        let pending: Option<String> =
+            sqlx::query_scalar("SELECT pending_password FROM cameras WHERE id = ?")
+                .bind(man.id)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(pending, None);
+        store.get(man.id).await.unwrap().rpc().await.unwrap();
+
+        // A pending password the device took is promoted
+        device.set_password("taken");
+        Camera::begin_password_rotation_db(&pool, &secret(), man.id, "taken")
+            .await
+            .unwrap();
+        assert!(man.recover_password(&pool, &store).await.unwrap());
+        assert!(!man.recover_password(&pool, &store).await.unwrap());
+        store.get(man.id).await.unwrap().rpc().await.unwrap();
+
+        store.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn it_credential_sources() {
+        let (_db, device, _server, pool, man) = setup().await;
+        let store = IpcStore::new(pool.clone(), Locator::default(), secret())
+            .await
+            .unwrap();
+        let id = man.id;
+        let login = |store: IpcStore| async move { store.get(id).await.unwrap().rpc().await };
+
+        let credential_id = CreateCredentialRequest {
+            name: "cameras".to_string(),
+            username: "admin".to_string(),
+            password: "wrong".to_string(),
+        }
+        .create(&pool, &store)
+        .await
+        .unwrap();
+        let update = |credential| UpdateCameraRequest {
+            id: man.id,
+            ip: None,
+            port: None,
+            scheme: None,
+            accept_invalid_certs: None,
+            username: None,
+            password: None,
+            credential: Some(credential),
+        };
+        update(CredentialSource::Shared { credential_id })
+            .update(&pool, &store)
+            .await
+            .unwrap();
+        assert!(login(store.clone()).await.is_err());
+
+        // Changing the shared credential refreshes the cameras that use it
+        UpdateCredentialRequest {
+            id: credential_id,
+            name: None,
+            username: None,
+            password: Some("password".to_string()),
+        }
+        .update(&pool, &store)
+        .await
+        .unwrap();
+        login(store.clone()).await.unwrap();
+        assert_eq!(
+            CameraShow::find(&pool, man.id).await.unwrap().credential,
+            CredentialSource::Shared { credential_id }
+        );
+
+        assert!(crate::db::Conflict == Credential::delete(&pool, credential_id).await.unwrap_err());
+        assert!(man
+            .rotate_password(&pool, &store, "new-password")
+            .await
+            .unwrap_err()
+            .is::<ValidationError>());
+        assert!(update(CredentialSource::Shared { credential_id: 999 })
+            .update(&pool, &store)
+            .await
+            .unwrap_err()
+            .is::<ValidationError>());
+
+        // Read when the client is built
+        let name = format!("IPCMANVIEW_CRED_TEST_{}", man.id);
+        std::env::set_var(&name, "password");
+        update(CredentialSource::Env { name })
+            .update(&pool, &store)
+            .await
+            .unwrap();
+        login(store.clone()).await.unwrap();
+        Credential::delete(&pool, credential_id).await.unwrap();
+
+        let path = testing::credential_dir().join(format!("password-{}", man.id));
+        std::fs::write(&path, "password\n").unwrap();
+        update(CredentialSource::File {
+            path: path.display().to_string(),
+        })
+        .update(&pool, &store)
+        .await
+        .unwrap();
+        login(store.clone()).await.unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(device.password(), "password");
+
+        // Nothing else of the station can be sent to a camera
+        for credential in [
+            CredentialSource::Env {
+                name: "STATION_SECRET".to_string(),
+            },
+            CredentialSource::File {
+                path: "/etc/passwd".to_string(),
+            },
+        ] {
+            assert!(update(credential)
+                .update(&pool, &store)
+                .await
+                .unwrap_err()
+                .is::<ValidationError>());
+        }
+
+        store.shutdown().await;
+    }
+}
@@ -0,0 +1,352 @@
+use std::{fmt, path::Path};
+
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Key, XChaCha20Poly1305, XNonce,
+};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use sqlx::SqlitePool;
+
+/// Prefix of sealed values, the version allows changing the format later.
+const PREFIX: &str = "v2:";
+const NONCE_LEN: usize = 24;
+const SECRET_LEN: usize = 32;
+/// Sealed into the database so a wrong secret is noticed before any camera is used.
+const CHECK_VALUE: &str = "ipcmanview";
+
+/// Key that seals camera credentials in the database, derived from the station secret.
+#[derive(Clone)]
+pub struct Secret {
+    key: Key,
+}
+
+impl Secret {
+    /// The secret is 32 random bytes encoded as base64 or hex, such as the output of
+    /// `openssl rand -base64 32`.
+    pub fn new(secret: &str) -> Result<Secret> {
+        let secret = secret.trim();
+        let ikm = STANDARD
+            .decode(secret)
+            .ok()
+            .filter(|ikm| ikm.len() == SECRET_LEN)
+            .or_else(|| hex::decode(secret).ok())
+            .filter(|ikm| ikm.len() == SECRET_LEN)
+            .with_context(|| {
+                format!("Secret must be {SECRET_LEN} random bytes encoded as base64 or hex.")
+            })?;
+
+        let mut key = Key::default();
+        Hkdf::<Sha256>::new(None, &ikm)
+            .expand(b"ipcmanview camera credentials", &mut key)
+            .expect("key is shorter than the HKDF limit");
+
+        Ok(Secret { key })
+    }
+
+    /// Reads the secret from a key file, surrounding whitespace is ignored.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Secret> {
+        let path = path.as_ref();
+        let secret = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read secret from {}.", path.display()))?;
+
+        Secret::new(&secret)
+    }
+
+    /// Reads the secret from the `{name}` environment variable or the file in `{name}_FILE`.
+    pub fn from_env(name: &str) -> Result<Secret> {
+        if let Ok(secret) = std::env::var(name) {
+            return Secret::new(&secret);
+        }
+        if let Ok(path) = std::env::var(format!("{name}_FILE")) {
+            return Secret::from_file(path);
+        }
+
+        bail!("{name} or {name}_FILE must be set.")
+    }
+
+    /// Seals a value of the row with id in table, it can only be opened for that row.
+    pub fn seal(&self, plaintext: &str, table: &str, id: i64) -> String {
+        let cipher = XChaCha20Poly1305::new(&self.key);
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let aad = format!("{table}:{id}");
+        let ciphertext = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext.as_bytes(),
+                    aad: aad.as_bytes(),
+                },
+            )
+            .expect("encrypting in memory can not fail");
+
+        let mut buf = nonce.to_vec();
+        buf.extend(ciphertext);
+        format!("{PREFIX}{}", STANDARD.encode(buf))
+    }
+
+    pub fn open(&self, sealed: &str, table: &str, id: i64) -> Result<String> {
+        let buf = sealed
+            .strip_prefix(PREFIX)
+            .and_then(|sealed| STANDARD.decode(sealed).ok())
+            .filter(|buf| buf.len() > NONCE_LEN)
+            .context("Value is not sealed.")?;
+        let (nonce, ciphertext) = buf.split_at(NONCE_LEN);
+
+        let aad = format!("{table}:{id}");
+        let plaintext = XChaCha20Poly1305::new(&self.key)
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: aad.as_bytes(),
+                },
+            )
+            .map_err(|_| {
+                anyhow::anyhow!("Failed to open sealed value, the secret or the row is wrong.")
+            })?;
+
+        String::from_utf8(plaintext).context("Sealed value is not UTF-8.")
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(..)")
+    }
+}
+
+/// Seals plaintext passwords the first time the database is opened with a secret, afterwards the
+/// secret has to match.
+pub async fn migrate(pool: &SqlitePool, secret: &Secret) -> Result<()> {
+    let mut tx = pool.begin().await?;
+
+    let check = sqlx::query!("SELECT value FROM secret_checks WHERE id = 1")
+        .fetch_optional(&mut *tx)
+        .await
+        .context("Failed to find secret check.")?;
+    if let Some(check) = check {
+        if secret
+            .open(&check.value, "secret_checks", 1)
+            .ok()
+            .as_deref()
+            != Some(CHECK_VALUE)
+        {
+            bail!("Secret does not match the one the database was sealed with.");
+        }
+        return Ok(());
+    }
+
+    let cameras = sqlx::query!("SELECT id, password FROM cameras")
+        .fetch_all(&mut *tx)
+        .await
+        .context("Failed to list camera passwords.")?;
+    for camera in &cameras {
+        let password = secret.seal(&camera.password, "cameras", camera.id);
+        sqlx::query!(
+            "UPDATE cameras SET password = ? WHERE id = ?",
+            password,
+            camera.id
+        )
+        .execute(&mut *tx)
+        .await
+        .with_context(|| format!("Failed to seal password of camera with id {}.", camera.id))?;
+    }
+
+    let check = secret.seal(CHECK_VALUE, "secret_checks", 1);
+    sqlx::query!("INSERT INTO secret_checks (id, value) VALUES (1, ?)", check)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to create secret check.")?;
+
+    tx.commit().await?;
+    if !cameras.is_empty() {
+        tracing::info!("Sealed passwords of {} cameras.", cameras.len());
+    }
+
+    Ok(())
+}
+
+/// Seals every camera and credential password with the new secret, the station must not be
+/// running.
+pub async fn rotate(pool: &SqlitePool, old: &Secret, new: &Secret) -> Result<usize> {
+    let mut tx = pool.begin().await?;
+
+    let cameras = sqlx::query!("SELECT id, password FROM cameras")
+        .fetch_all(&mut *tx)
+        .await
+        .context("Failed to list camera passwords.")?;
+    for camera in &cameras {
+        let password = new.seal(
+            &old.open(&camera.password, "cameras", camera.id)
+                .with_context(|| format!("Failed to open password of camera {}.", camera.id))?,
+            "cameras",
+            camera.id,
+        );
+        sqlx::query!(
+            "UPDATE cameras SET password = ? WHERE id = ?",
+            password,
+            camera.id
+        )
+        .execute(&mut *tx)
+        .await
+        .with_context(|| format!("Failed to seal password of camera with id {}.", camera.id))?;
+    }
+
+    let pending = sqlx::query!(
+        r#"SELECT id, pending_password as "pending_password!" FROM cameras WHERE pending_password IS NOT NULL"#
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .context("Failed to list pending camera passwords.")?;
+    for camera in &pending {
+        let password = new.seal(
+            &old.open(&camera.pending_password, "cameras", camera.id)
+                .with_context(|| {
+                    format!("Failed to open pending password of camera {}.", camera.id)
+                })?,
+            "cameras",
+            camera.id,
+        );
+        sqlx::query!(
+            "UPDATE cameras SET pending_password = ? WHERE id = ?",
+            password,
+            camera.id
+        )
+        .execute(&mut *tx)
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to seal pending password of camera with id {}.",
+                camera.id
+            )
+        })?;
+    }
+
+    let credentials = sqlx::query!("SELECT id, password FROM credentials")
+        .fetch_all(&mut *tx)
+        .await
+        .context("Failed to list credential passwords.")?;
+    for credential in &credentials {
+        let password = new.seal(
+            &old.open(&credential.password, "credentials", credential.id)
+                .with_context(|| {
+                    format!("Failed to open password of credential {}.", credential.id)
+                })?,
+            "credentials",
+            credential.id,
+        );
+        sqlx::query!(
+            "UPDATE credentials SET password = ? WHERE id = ?",
+            password,
+            credential.id
+        )
+        .execute(&mut *tx)
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to seal password of credential with id {}.",
+                credential.id
+            )
+        })?;
+    }
+
+    let check = new.seal(CHECK_VALUE, "secret_checks", 1);
+    sqlx::query!("UPDATE secret_checks SET value = ? WHERE id = 1", check)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to update secret check.")?;
+
+    tx.commit().await?;
+
+    Ok(cameras.len() + pending.len() + credentials.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_seal() {
+        let secret = Secret::new("aXBjbWFudmlldyB0ZXN0IHNlY3JldCwgMzIgYnl0ZXM=").unwrap();
+        let sealed = secret.seal("password", "cameras", 1);
+        assert!(sealed.starts_with(PREFIX));
+        assert!(!sealed.contains("password"));
+        assert_ne!(sealed, secret.seal("password", "cameras", 1));
+        assert_eq!(secret.open(&sealed, "cameras", 1).unwrap(), "password");
+
+        // Sealed values can not be moved to another row
+        assert!(secret.open(&sealed, "cameras", 2).is_err());
+        assert!(secret.open(&sealed, "credentials", 1).is_err());
+
+        let other = Secret::new("YW5vdGhlciBzdGF0aW9uIHNlY3JldCwgMzIgYnl0ZXM=").unwrap();
+        assert!(other.open(&sealed, "cameras", 1).is_err());
+        assert!(secret.open("password", "cameras", 1).is_err());
+
+        let mut tampered = sealed.into_bytes();
+        let last = tampered.len() - 2;
+        tampered[last] = if tampered[last] == b'A' { b'B' } else { b'A' };
+        assert!(secret
+            .open(&String::from_utf8(tampered).unwrap(), "cameras", 1)
+            .is_err());
+
+        // Hex works as well
+        let hex = Secret::new("6970636d616e766965772074657374207365637265742c203332206279746573")
+            .unwrap();
+        assert_eq!(
+            hex.open(&secret.seal("password", "cameras", 1), "cameras", 1)
+                .unwrap(),
+            "password"
+        );
+        // Passphrases are not keys
+        assert!(Secret::new("correct horse battery staple").is_err());
+        assert!(Secret::new("c2hvcnQ=").is_err());
+        assert_eq!(format!("{secret:?}"), "Secret(..)");
+    }
+
+    #[tokio::test]
+    async fn it_migrate_and_rotate() {
+        let path =
+            std::env::temp_dir().join(format!("ipcmanview-test-secret-{}.db", std::process::id()));
+        std::fs::remove_file(&path).ok();
+        let url = format!("sqlite://{}", path.display());
+        let old = Secret::new("dGhlIG9sZCBzdGF0aW9uIHNlY3JldCwgMzIgYnl0ZXM=").unwrap();
+        let new = Secret::new("dGhlIG5ldyBzdGF0aW9uIHNlY3JldCwgMzIgYnl0ZXM=").unwrap();
+        let pool = crate::db::new(&url, &old).await.unwrap();
+
+        // Database from before passwords were sealed
+        sqlx::query("DELETE FROM secret_checks")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO cameras (ip, username, password, scan_cursor) VALUES ('a', 'admin', 'plain', 0)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let password = || async {
+            sqlx::query_scalar::<_, String>("SELECT password FROM cameras")
+                .fetch_one(&pool)
+                .await
+                .unwrap()
+        };
+
+        migrate(&pool, &old).await.unwrap();
+        assert_eq!(old.open(&password().await, "cameras", 1).unwrap(), "plain");
+        // Sealed rows are not sealed again
+        migrate(&pool, &old).await.unwrap();
+        assert_eq!(old.open(&password().await, "cameras", 1).unwrap(), "plain");
+        assert!(migrate(&pool, &new).await.is_err());
+
+        assert_eq!(rotate(&pool, &old, &new).await.unwrap(), 1);
+        assert_eq!(new.open(&password().await, "cameras", 1).unwrap(), "plain");
+        assert!(migrate(&pool, &old).await.is_err());
+        migrate(&pool, &new).await.unwrap();
+
+        pool.close().await;
+        std::fs::remove_file(&path).ok();
+    }
+}